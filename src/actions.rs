@@ -0,0 +1,95 @@
+use clap::ValueEnum;
+
+use crate::display;
+use crate::error::Errors;
+use crate::kubernetes;
+use crate::settings;
+use crate::types;
+
+pub fn check_fail_on(settings: &settings::SettingsValidated, line: &str) {
+    if let Some(fail_on) = &settings.fail_on {
+        if fail_on.is_match(line) {
+            settings.state.set_fail_on_matched();
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum NotifyMode {
+    Bell,
+    Desktop,
+}
+
+fn new_desktop_notification(title: &str, message: &str) -> Option<tokio::process::Command> {
+    if cfg!(target_os = "linux") {
+        let mut command = tokio::process::Command::new("notify-send");
+        command.arg(title).arg(message);
+        return Some(command);
+    }
+    if cfg!(target_os = "macos") {
+        let mut command = tokio::process::Command::new("osascript");
+        command
+            .arg("-e")
+            .arg(format!("display notification {:?} with title {:?}", message, title));
+        return Some(command);
+    }
+    return None;
+}
+
+/// ring the terminal bell or send a desktop notification (falls back to the bell where desktop notifications are not supported)
+pub async fn notify(mode: &NotifyMode, pod: &kubernetes::Pod, line: &str, streams: &types::ArcMutex<display::Streams>) -> Result<(), Errors> {
+    if *mode == NotifyMode::Desktop {
+        let title = format!("rusty_stern: {}", pod.id());
+        if let Some(mut command) = new_desktop_notification(&title, line) {
+            let (pod_id, color) = (pod.id(), pod.color);
+            let streams = streams.clone();
+            tokio::spawn(async move {
+                if let Err(err) = command.status().await {
+                    let mut streams = streams.lock().await;
+                    return display::print_error(&mut streams, Some(color), format!("desktop notification for {pod_id} failled: {err}")).await;
+                }
+                return Ok(());
+            });
+            return Ok(());
+        }
+    }
+    let streams = streams.lock().await;
+    return streams.out.raw("\x07".to_string());
+}
+
+fn new_shell_command(command: &str) -> tokio::process::Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = tokio::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = tokio::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    return shell;
+}
+
+/// run the command in a shell without waiting for it, the pod, namespace and line are exposed as environment variables
+/// (RUSTY_STERN_POD, RUSTY_STERN_NAMESPACE, RUSTY_STERN_LINE)
+pub fn exec_on_match(command: &str, pod: &kubernetes::Pod, line: &str, streams: &types::ArcMutex<display::Streams>) {
+    let mut shell = new_shell_command(command);
+    shell
+        .env("RUSTY_STERN_POD", &pod.name)
+        .env("RUSTY_STERN_NAMESPACE", &pod.namespace.name)
+        .env("RUSTY_STERN_LINE", line);
+    let command = command.to_string();
+    let (pod_id, color) = (pod.id(), pod.color);
+    let streams = streams.clone();
+    tokio::spawn(async move {
+        let status = shell.status().await.map_err(|err| Errors::Other(err.to_string()));
+        let message = match status {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => format!("command {command} run for {pod_id} exited with {status}"),
+            Err(err) => format!("command {command} run for {pod_id} failled to run: {err}"),
+        };
+        let mut streams = streams.lock().await;
+        return display::print_error(&mut streams, Some(color), message).await;
+    });
+}
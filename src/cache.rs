@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use k8s_openapi::api::core::v1::Pod as ApiPod;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+use crate::metrics;
+
+/// pods kept at most, the cache is emptied when it's full so that the deleted pods don't accumulate
+const CAPACITY: usize = 5000;
+
+/// pod objects by uid with the resource version they were fetched at, shared by the namespaces of every context:
+/// a pod whose resource version did not change since it was fetched is not fetched again
+#[derive(Default)]
+pub struct PodCache {
+    pods: Mutex<HashMap<String, (String, ApiPod)>>,
+}
+
+impl PodCache {
+    /// the cached pod if the metadata has the same resource version, the hits and misses are counted for the stats
+    pub fn get(&self, metadata: &ObjectMeta) -> Option<ApiPod> {
+        let (Some(uid), Some(resource_version)) = (&metadata.uid, &metadata.resource_version) else {
+            return None;
+        };
+        let pods = self.pods.lock().unwrap();
+        return match pods.get(uid).filter(|(cached_version, _)| cached_version == resource_version) {
+            Some((_, pod)) => {
+                metrics::inc(&metrics::POD_CACHE_HITS);
+                Some(pod.clone())
+            }
+            None => {
+                metrics::inc(&metrics::POD_CACHE_MISSES);
+                None
+            }
+        };
+    }
+
+    pub fn insert(&self, pod: &ApiPod) {
+        let (Some(uid), Some(resource_version)) = (&pod.metadata.uid, &pod.metadata.resource_version) else {
+            return;
+        };
+        let mut pods = self.pods.lock().unwrap();
+        if pods.get(uid).is_some_and(|(cached_version, _)| cached_version == resource_version) {
+            return;
+        }
+        if pods.len() >= CAPACITY {
+            pods.clear();
+        }
+        pods.insert(uid.clone(), (resource_version.clone(), pod.clone()));
+    }
+}
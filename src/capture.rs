@@ -0,0 +1,209 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+
+use crate::error::Errors;
+use crate::{kubernetes, lineproc, settings};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS lines (
+    id INTEGER PRIMARY KEY,
+    context TEXT,
+    namespace TEXT NOT NULL,
+    pod TEXT NOT NULL,
+    container TEXT NOT NULL,
+    timestamp TEXT,
+    received TEXT NOT NULL,
+    line TEXT NOT NULL,
+    node TEXT,
+    image TEXT,
+    labels TEXT
+);
+CREATE INDEX IF NOT EXISTS lines_timestamp ON lines (timestamp);
+";
+
+/// columns added after the first version of the schema, added to the captures created before them
+const ADDED_COLUMNS: [&str; 3] = ["node", "image", "labels"];
+
+const INSERT: &str = "INSERT INTO lines (context, namespace, pod, container, timestamp, received, line, node, image, labels)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)";
+
+/// a line stored by the sqlite sink
+pub struct CapturedLine {
+    context: Option<String>,
+    namespace: String,
+    pod: String,
+    container: String,
+    timestamp: Option<String>,
+    received: String,
+    line: String,
+    node: String,
+    image: String,
+    /// json object of the labels asked for with sink_labels
+    labels: String,
+}
+
+impl CapturedLine {
+    pub fn new(pod: &kubernetes::Pod, date: Option<DateTime<FixedOffset>>, line: &str, sink_labels: &[String]) -> CapturedLine {
+        let (_, message) = lineproc::split_timestamp(line);
+        return CapturedLine {
+            context: pod.namespace.context.clone(),
+            namespace: pod.namespace.name.clone(),
+            pod: pod.name.clone(),
+            container: pod.default_container(),
+            timestamp: date.map(|date| format_date(&date)),
+            received: format_date(&Utc::now()),
+            line: message.trim_end_matches("\n").to_string(),
+            node: pod.node_name(),
+            image: pod.image(),
+            labels: serde_json::json!(pod.labels(sink_labels)).to_string(),
+        };
+    }
+}
+
+/// utc with a fixed number of digits, so that the dates of the capture can be compared as text
+fn format_date<Tz: chrono::TimeZone>(date: &DateTime<Tz>) -> String {
+    return date.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Nanos, true);
+}
+
+fn sqlite_error(path: &PathBuf, err: rusqlite::Error) -> Errors {
+    return Errors::Other(format!("sqlite capture {}: {err}", path.display()));
+}
+
+/// open the capture, creating it if needed, before following the pods so that an invalid path fails right away
+pub fn open(path: &PathBuf) -> Result<Connection, Errors> {
+    let connection = Connection::open(path).map_err(|err| sqlite_error(path, err))?;
+    connection.execute_batch(SCHEMA).map_err(|err| sqlite_error(path, err))?;
+    let mut statement = connection
+        .prepare("SELECT name FROM pragma_table_info('lines')")
+        .map_err(|err| sqlite_error(path, err))?;
+    let columns: Vec<String> = statement
+        .query_map([], |row| row.get(0))
+        .and_then(|rows| rows.collect())
+        .map_err(|err| sqlite_error(path, err))?;
+    drop(statement);
+    for column in ADDED_COLUMNS.iter().filter(|column| !columns.iter().any(|name| name == *column)) {
+        connection
+            .execute_batch(&format!("ALTER TABLE lines ADD COLUMN {column} TEXT"))
+            .map_err(|err| sqlite_error(path, err))?;
+    }
+    return Ok(connection);
+}
+
+/// insert the lines until every sender is dropped, the lines received while inserting are written in the same transaction
+pub fn write_lines(
+    path: PathBuf,
+    mut connection: Connection,
+    mut receiver: tokio::sync::mpsc::UnboundedReceiver<CapturedLine>,
+) -> Result<(), Errors> {
+    while let Some(first) = receiver.blocking_recv() {
+        let mut batch = vec![first];
+        while let Ok(next) = receiver.try_recv() {
+            batch.push(next);
+        }
+        let transaction = connection.transaction().map_err(|err| sqlite_error(&path, err))?;
+        {
+            let mut insert = transaction.prepare_cached(INSERT).map_err(|err| sqlite_error(&path, err))?;
+            for line in batch {
+                insert
+                    .execute(rusqlite::params![
+                        line.context,
+                        line.namespace,
+                        line.pod,
+                        line.container,
+                        line.timestamp,
+                        line.received,
+                        line.line,
+                        line.node,
+                        line.image,
+                        line.labels
+                    ])
+                    .map_err(|err| sqlite_error(&path, err))?;
+            }
+        }
+        transaction.commit().map_err(|err| sqlite_error(&path, err))?;
+    }
+    return Ok(());
+}
+
+fn value_to_string(value: ValueRef) -> String {
+    return match value {
+        ValueRef::Null => "".to_string(),
+        ValueRef::Integer(value) => value.to_string(),
+        ValueRef::Real(value) => value.to_string(),
+        ValueRef::Text(value) => String::from_utf8_lossy(value).to_string(),
+        ValueRef::Blob(value) => format!("<{} bytes>", value.len()),
+    };
+}
+
+/// print the rows of the statement tab separated, with the column names first
+fn print_sql(path: &PathBuf, connection: &Connection, sql: &str) -> Result<(), Errors> {
+    let mut statement = connection
+        .prepare(sql)
+        .map_err(|err| Errors::Validation(format!("invalid query {sql}: {err}")))?;
+    let column_cnt = statement.column_count();
+    println!("{}", statement.column_names().join("\t"));
+    let mut rows = statement.query([]).map_err(|err| sqlite_error(path, err))?;
+    while let Some(row) = rows.next().map_err(|err| sqlite_error(path, err))? {
+        let mut values = vec![];
+        for idx in 0..column_cnt {
+            values.push(value_to_string(row.get_ref(idx).map_err(|err| sqlite_error(path, err))?));
+        }
+        println!("{}", values.join("\t"));
+    }
+    return Ok(());
+}
+
+/// print the lines matching the filters in timestamp order, prefixed by their pod like the export file
+fn print_lines(path: &PathBuf, connection: &Connection, query: &settings::QueryArgs) -> Result<(), Errors> {
+    let since_time = query.since_time.as_ref().map(format_date);
+    let until = query.until.as_ref().map(format_date);
+    let mut statement = connection
+        .prepare(
+            "SELECT context, namespace, pod, timestamp, line FROM lines
+            WHERE (?1 IS NULL OR namespace = ?1) AND (?2 IS NULL OR timestamp >= ?2) AND (?3 IS NULL OR timestamp <= ?3)
+            ORDER BY timestamp, id",
+        )
+        .map_err(|err| sqlite_error(path, err))?;
+    let mut rows = statement
+        .query(rusqlite::params![query.namespace, since_time, until])
+        .map_err(|err| sqlite_error(path, err))?;
+    let mut printed = 0;
+    while let Some(row) = rows.next().map_err(|err| sqlite_error(path, err))? {
+        if query.limit.is_some_and(|limit| printed >= limit) {
+            break;
+        }
+        let context: Option<String> = row.get(0).map_err(|err| sqlite_error(path, err))?;
+        let namespace: String = row.get(1).map_err(|err| sqlite_error(path, err))?;
+        let pod: String = row.get(2).map_err(|err| sqlite_error(path, err))?;
+        let timestamp: Option<String> = row.get(3).map_err(|err| sqlite_error(path, err))?;
+        let line: String = row.get(4).map_err(|err| sqlite_error(path, err))?;
+        if query.pod.as_ref().is_some_and(|pod_search| !pod_search.is_match(&pod)) {
+            continue;
+        }
+        if query.grep.as_ref().is_some_and(|grep| !grep.is_match(&line)) {
+            continue;
+        }
+        let pod_id = match context {
+            Some(context) => format!("{context}/{namespace}/{pod}"),
+            None => format!("{namespace}/{pod}"),
+        };
+        println!("{pod_id} {} {line}", timestamp.unwrap_or("-".to_string()));
+        printed += 1;
+    }
+    return Ok(());
+}
+
+/// run a sql statement or the filters of the query subcommand against a capture of the sqlite sink
+pub fn run_query(query: &settings::QueryArgs) -> Result<(), Errors> {
+    if !query.db.exists() {
+        return Err(Errors::Validation(format!("capture {} not found", query.db.display())));
+    }
+    let connection = Connection::open(&query.db).map_err(|err| sqlite_error(&query.db, err))?;
+    return match &query.sql {
+        Some(sql) => print_sql(&query.db, &connection, sql),
+        None => print_lines(&query.db, &connection, query),
+    };
+}
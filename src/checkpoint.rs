@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::error::Errors;
+
+/// timestamp of the last line seen for each pod, persisted in a json state file
+#[derive(Clone)]
+pub struct Checkpoint {
+    path: PathBuf,
+    last_seen: HashMap<String, DateTime<FixedOffset>>,
+    dirty: bool,
+}
+
+impl Checkpoint {
+    /// the state file is only read when resuming, otherwise it's overwritten
+    pub fn load(path: &PathBuf, resume: bool) -> Result<Checkpoint, Errors> {
+        let mut last_seen = HashMap::new();
+        if resume && path.exists() {
+            let content =
+                std::fs::read_to_string(path).map_err(|err| Errors::Validation(format!("failled to read state file {}: {err}", path.display())))?;
+            let raw: HashMap<String, String> =
+                serde_json::from_str(&content).map_err(|err| Errors::Validation(format!("failled to parse state file {}: {err}", path.display())))?;
+            for (pod_id, timestamp) in raw {
+                let date = DateTime::parse_from_rfc3339(&timestamp)
+                    .map_err(|err| Errors::Validation(format!("failled to parse state file {}: {err}", path.display())))?;
+                last_seen.insert(pod_id, date);
+            }
+        }
+        return Ok(Checkpoint {
+            path: path.clone(),
+            last_seen,
+            dirty: false,
+        });
+    }
+
+    pub fn last_seen(&self, pod_id: &str) -> Option<DateTime<FixedOffset>> {
+        return self.last_seen.get(pod_id).cloned();
+    }
+
+    /// record the line date, returns false if the line was already seen by a previous invocation
+    pub fn advance(&mut self, pod_id: &str, date: DateTime<FixedOffset>) -> bool {
+        if let Some(last_seen) = self.last_seen.get(pod_id) {
+            if date <= *last_seen {
+                return false;
+            }
+        }
+        self.last_seen.insert(pod_id.to_string(), date);
+        self.dirty = true;
+        return true;
+    }
+
+    pub fn save(&mut self) -> Result<(), Errors> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let raw: HashMap<&String, String> = self.last_seen.iter().map(|(pod_id, date)| (pod_id, date.to_rfc3339())).collect();
+        let content = serde_json::to_string_pretty(&raw).map_err(|err| Errors::Other(err.to_string()))?;
+        std::fs::write(&self.path, content).map_err(|err| Errors::Other(format!("writing state file {}: {err}", self.path.display())))?;
+        self.dirty = false;
+        return Ok(());
+    }
+}
@@ -0,0 +1,239 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use clap::parser::ValueSource;
+
+use crate::error::Errors;
+
+/// $XDG_CONFIG_HOME/rusty_stern/config.toml, ~/.config/rusty_stern/config.toml or %APPDATA%\rusty_stern\config.toml
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) => PathBuf::from(config_home),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".config"),
+            None => PathBuf::from(std::env::var_os("APPDATA")?),
+        },
+    };
+    return Some(config_dir.join("rusty_stern").join("config.toml"));
+}
+
+/// the config file passed on the command line or the default one if it exists
+pub fn resolve_config_path(config: Option<&PathBuf>) -> Option<PathBuf> {
+    return match config {
+        Some(config) => Some(config.clone()),
+        None => default_config_path().filter(|config_path| config_path.exists()),
+    };
+}
+
+fn value_to_string(key: &str, value: &toml::Value) -> Result<String, Errors> {
+    return match value {
+        toml::Value::String(value) => Ok(value.clone()),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        // color-map = [{ pattern = "payments-.*", color = "#ff8800" }] is passed as --color-map=payments-.*=#ff8800
+        toml::Value::Table(table) if key.replace("-", "_") == "color_map" => match (table.get("pattern"), table.get("color")) {
+            (Some(toml::Value::String(pattern)), Some(toml::Value::String(color))) => Ok(format!("{pattern}={color}")),
+            _ => Err(Errors::Validation(format!(
+                "config key {key}: expected {{ pattern = \"regex\", color = \"#rrggbb\" }}, got {value}"
+            ))),
+        },
+        _ => Err(Errors::Validation(format!("config key {key}: unsupported value {value}"))),
+    };
+}
+
+/// the sections of the config file overriding its keys for the pods of a namespace
+const NAMESPACE_SECTION: &str = "namespace";
+
+fn read_config(path: &PathBuf) -> Result<toml::Table, Errors> {
+    let content =
+        std::fs::read_to_string(path).map_err(|err| Errors::Validation(format!("failled to read config file {}: {err}", path.display())))?;
+    return content
+        .parse()
+        .map_err(|err| Errors::Validation(format!("failled to parse config file {}: {err}", path.display())));
+}
+
+/// translate the config file into command line arguments, keys are the long argument names (eg: pod-search or pod_search)
+/// arguments passed on the command line or through the environment are skipped so that they keep precedence over the file
+pub fn config_args(path: &PathBuf, command: &clap::Command, matches: &clap::ArgMatches) -> Result<Vec<OsString>, Errors> {
+    let table = read_config(path)?;
+    return Ok(table_args(&table, path, command, matches)?
+        .into_iter()
+        .flat_map(|(_, args)| args)
+        .collect());
+}
+
+/// the command line arguments of each [namespace.<name>] section of the config file: the keys of the section replace the ones
+/// of the file for the pods of this namespace, the command line and the environment still take precedence
+pub fn namespace_config_args(path: &PathBuf, command: &clap::Command, matches: &clap::ArgMatches) -> Result<Vec<(String, Vec<OsString>)>, Errors> {
+    let table = read_config(path)?;
+    let Some(sections) = table.get(NAMESPACE_SECTION) else {
+        return Ok(vec![]);
+    };
+    let Some(sections) = sections.as_table() else {
+        return Err(Errors::Validation(format!(
+            "config key {NAMESPACE_SECTION} in {}: expected [{NAMESPACE_SECTION}.<name>] sections",
+            path.display()
+        )));
+    };
+    let global_args = table_args(&table, path, command, matches)?;
+    let mut namespace_args = vec![];
+    for (namespace, section) in sections.iter() {
+        let Some(section) = section.as_table() else {
+            return Err(Errors::Validation(format!(
+                "config key {NAMESPACE_SECTION}.{namespace}: expected a section"
+            )));
+        };
+        let section_args = table_args(section, path, command, matches)?;
+        let mut args: Vec<OsString> = global_args
+            .iter()
+            .filter(|(id, _)| !section_args.iter().any(|(section_id, _)| section_id == id))
+            .flat_map(|(_, args)| args.clone())
+            .collect();
+        args.extend(section_args.into_iter().flat_map(|(_, args)| args));
+        namespace_args.push((namespace.clone(), args));
+    }
+    return Ok(namespace_args);
+}
+
+/// the arguments of each key of a table of the config file with their argument id
+fn table_args(
+    table: &toml::Table,
+    path: &PathBuf,
+    command: &clap::Command,
+    matches: &clap::ArgMatches,
+) -> Result<Vec<(String, Vec<OsString>)>, Errors> {
+    let mut table_args = vec![];
+    for (key, value) in table.iter().filter(|(key, _)| key.as_str() != NAMESPACE_SECTION) {
+        let id = key.replace("-", "_");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id().as_str() == id)
+            .ok_or(Errors::Validation(format!("unknown config key {key} in {}", path.display())))?;
+        let long = arg
+            .get_long()
+            .ok_or(Errors::Validation(format!("config key {key} can't be set in a config file")))?;
+        match matches.value_source(&id) {
+            Some(ValueSource::CommandLine) | Some(ValueSource::EnvVariable) => continue,
+            _ => {}
+        }
+        let mut args = vec![];
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            if !arg.get_action().takes_values() {
+                if let toml::Value::Boolean(true) = value {
+                    args.push(OsString::from(format!("--{long}")));
+                }
+                continue;
+            }
+            args.push(OsString::from(format!("--{long}={}", value_to_string(key, value)?)));
+        }
+        table_args.push((id, args));
+    }
+    return Ok(table_args);
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+    use crate::settings::Settings;
+
+    /// a config file in the temp dir, unique to the test
+    fn write_config(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rusty_stern_config_{}_{name}.toml", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        return path;
+    }
+
+    fn matches(command: &clap::Command, args: &[&str]) -> clap::ArgMatches {
+        return command.clone().try_get_matches_from(args).unwrap();
+    }
+
+    const CONFIG: &str = r##"
+tail-lines = 10
+timestamps = true
+max_prefix_width = 20
+color-map = [{ pattern = "payments-.*", color = "#ff8800" }]
+
+[namespace.shop]
+tail-lines = 50
+
+[namespace.billing]
+timestamps = false
+"##;
+
+    #[test]
+    fn translates_the_keys_into_arguments() {
+        let path = write_config("translates", CONFIG);
+        let command = Settings::command();
+        let args = config_args(&path, &command, &matches(&command, &["rusty_stern"])).unwrap();
+        assert_eq!(
+            args,
+            vec![
+                "--color-map=payments-.*=#ff8800",
+                "--max-prefix-width=20",
+                "--tail-lines=10",
+                "--timestamps"
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_the_keys_passed_on_the_command_line() {
+        let path = write_config("skips", CONFIG);
+        let command = Settings::command();
+        let matches = matches(&command, &["rusty_stern", "--tail-lines", "3"]);
+        let args = config_args(&path, &command, &matches).unwrap();
+        assert_eq!(args, vec!["--color-map=payments-.*=#ff8800", "--max-prefix-width=20", "--timestamps"]);
+        let namespace_args = namespace_config_args(&path, &command, &matches).unwrap();
+        assert!(namespace_args
+            .iter()
+            .all(|(_, args)| !args.iter().any(|arg| arg.to_string_lossy().starts_with("--tail-lines"))));
+    }
+
+    #[test]
+    fn a_section_only_overrides_its_own_keys() {
+        let path = write_config("sections", CONFIG);
+        let command = Settings::command();
+        let namespace_args = namespace_config_args(&path, &command, &matches(&command, &["rusty_stern"])).unwrap();
+        assert_eq!(
+            namespace_args,
+            vec![
+                (
+                    "billing".to_string(),
+                    vec!["--color-map=payments-.*=#ff8800", "--max-prefix-width=20", "--tail-lines=10"]
+                        .into_iter()
+                        .map(OsString::from)
+                        .collect()
+                ),
+                (
+                    "shop".to_string(),
+                    vec![
+                        "--color-map=payments-.*=#ff8800",
+                        "--max-prefix-width=20",
+                        "--timestamps",
+                        "--tail-lines=50"
+                    ]
+                    .into_iter()
+                    .map(OsString::from)
+                    .collect()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_the_unknown_keys_of_a_section() {
+        let path = write_config("unknown", "tail-lines = 10\n\n[namespace.shop]\ntail-line = 50\n");
+        let command = Settings::command();
+        let matches = matches(&command, &["rusty_stern"]);
+        assert!(config_args(&path, &command, &matches).is_ok());
+        let err = namespace_config_args(&path, &command, &matches).unwrap_err();
+        assert!(err.to_string().contains("unknown config key tail-line"), "{err}");
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use regex::Regex;
+use tokio::io::AsyncBufReadExt;
+
+use crate::error::Errors;
+use crate::{display, kubernetes, lineproc, settings, state, types};
+
+fn replaces(settings: &settings::SettingsValidated) -> Vec<(&str, &str, bool)> {
+    return settings
+        .replace
+        .iter()
+        .map(|replace| (replace.pattern.as_str(), replace.value.as_str(), replace.highlight))
+        .collect();
+}
+
+fn regexes(regexes: &[Regex]) -> Vec<&str> {
+    return regexes.iter().map(|regex| regex.as_str()).collect();
+}
+
+/// the processors are built once at startup, the ones which differ in the reloaded settings are reported instead of silently kept
+fn changed_processors(current: &settings::SettingsValidated, reloaded: &settings::SettingsValidated) -> Vec<&'static str> {
+    let mut changed = vec![];
+    if replaces(current) != replaces(reloaded) || current.replace_highlight != reloaded.replace_highlight {
+        changed.push("replace");
+    }
+    if regexes(&current.highlight) != regexes(&reloaded.highlight) {
+        changed.push("highlight");
+    }
+    if current.redactor.as_ref().map(lineproc::Redactor::patterns) != reloaded.redactor.as_ref().map(lineproc::Redactor::patterns) {
+        changed.push("redact");
+    }
+    if format!("{:?}", current.json_query) != format!("{:?}", reloaded.json_query) {
+        changed.push("json_query");
+    }
+    if format!("{:?}", current.json_exclude) != format!("{:?}", reloaded.json_exclude) {
+        changed.push("json_exclude");
+    }
+    return changed;
+}
+
+/// the reloaded settings which are only applied on restart: the processors other than the filters,
+/// and the [namespace.<name>] sections added since startup (their pods keep the top level settings)
+fn not_reloaded(current: &settings::SettingsValidated, reloaded: &settings::SettingsValidated) -> Vec<String> {
+    let mut not_reloaded: Vec<String> = changed_processors(current, reloaded).into_iter().map(str::to_string).collect();
+    let mut sections: Vec<&String> = reloaded.namespace_settings.keys().collect();
+    sections.sort();
+    for namespace in sections {
+        match current.namespace_settings.get(namespace) {
+            Some(current_section) => not_reloaded.extend(
+                changed_processors(current_section, &reloaded.namespace_settings[namespace])
+                    .into_iter()
+                    .map(|name| format!("namespace.{namespace}.{name}")),
+            ),
+            None => not_reloaded.push(format!("namespace.{namespace}")),
+        }
+    }
+    return not_reloaded;
+}
+
+/// parse the arguments again (which reads the config and rules files again) and apply the filters (the ones of each namespace section too),
+/// pod search, namespaces and colors, the pods already followed keep their color. returns the settings which are not applied
+async fn reload(
+    current: &settings::SettingsValidated,
+    clients: &[kubernetes::ContextClient],
+    pods: &types::ArcMutex<kubernetes::Pods>,
+) -> Result<Vec<String>, Errors> {
+    let settings = settings::Settings::parse_from_args(current.args.clone())?.to_validated()?;
+    let not_reloaded = not_reloaded(current, &settings);
+    tracing::info!(namespaces = ?settings.namespaces, pod_search = %settings.pod_search, ?not_reloaded, "settings reloaded");
+    let mut filters = HashMap::from([(None, settings.filter.clone())]);
+    for (namespace, namespace_settings) in settings.namespace_settings.iter() {
+        filters.insert(Some(namespace.clone()), namespace_settings.filter.clone());
+    }
+    current.state.set_reloaded_filters(filters);
+    let namespaces = kubernetes::Namespaces::new(clients, &settings);
+    let mut pods = pods.lock().await;
+    // at least one color per cycle, the hues are spread according to the number of pods
+    let mut colors_params = display::ColorParams::new(&settings, pods.items.len().max(1));
+    pods.colors.reload(&mut colors_params);
+    pods.reload(namespaces, settings.pod_search.clone());
+    pods.refresh().await?;
+    return Ok(not_reloaded);
+}
+
+async fn reload_and_report(
+    current: &settings::SettingsValidated,
+    clients: &[kubernetes::ContextClient],
+    pods: &types::ArcMutex<kubernetes::Pods>,
+    streams: &types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let message = match reload(current, clients, pods).await {
+        Ok(not_reloaded) if not_reloaded.len() > 0 => format!("settings reloaded, restart to apply {}", not_reloaded.join(", ")),
+        Ok(_) => "settings reloaded".to_string(),
+        Err(err) => format!("failled to reload settings: {err}"),
+    };
+    let mut streams = streams.lock().await;
+    return display::print_color(&mut streams.err, None, message).await;
+}
+
+/// reload the settings each time the process receives SIGHUP
+#[cfg(unix)]
+pub async fn reload_on_sighup(
+    settings: settings::SettingsValidated,
+    clients: Vec<kubernetes::ContextClient>,
+    pods: types::ArcMutex<kubernetes::Pods>,
+    streams: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).map_err(|err| Errors::Other(err.to_string()))?;
+    while let Some(_) = hangup.recv().await {
+        reload_and_report(&settings, &clients, &pods, &streams).await?;
+    }
+    return Ok(());
+}
+
+/// reload the settings each time the config file changes
+/// the directory is watched because editors often replace the file instead of writing it
+pub async fn reload_on_config_change(
+    config_path: std::path::PathBuf,
+    settings: settings::SettingsValidated,
+    clients: Vec<kubernetes::ContextClient>,
+    pods: types::ArcMutex<kubernetes::Pods>,
+    streams: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let file_name = config_path.file_name().map(|file_name| file_name.to_os_string());
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            if event.kind.is_modify() || event.kind.is_create() {
+                if event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name().map(|name| name.to_os_string()) == file_name)
+                {
+                    let _ = sender.send(());
+                }
+            }
+        }
+    })
+    .map_err(|err| Errors::Other(format!("watching config file: {err}")))?;
+    let config_dir = match config_path.parent() {
+        Some(config_dir) if config_dir.as_os_str().len() > 0 => config_dir.to_path_buf(),
+        _ => std::path::PathBuf::from("."),
+    };
+    notify::Watcher::watch(&mut watcher, &config_dir, notify::RecursiveMode::NonRecursive)
+        .map_err(|err| Errors::Other(format!("watching config file: {err}")))?;
+    while let Some(_) = receiver.recv().await {
+        // a save usually triggers several events, wait for the last one
+        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        while let Ok(_) = receiver.try_recv() {}
+        reload_and_report(&settings, &clients, &pods, &streams).await?;
+    }
+    return Ok(());
+}
+
+/// keep the wrap width up to date with the terminal: on SIGWINCH on unix, by checking its size every second elsewhere
+pub async fn follow_terminal_resize(state: Arc<state::RunState>) -> Result<(), Errors> {
+    #[cfg(unix)]
+    {
+        let mut window_change = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::window_change())
+            .map_err(|err| Errors::Other(format!("installing SIGWINCH handler: {err}")))?;
+        while let Some(_) = window_change.recv().await {
+            display::terminal_resized(&state);
+        }
+        return Ok(());
+    }
+    #[cfg(not(unix))]
+    {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            display::terminal_resized(&state);
+        }
+    }
+}
+
+/// resolves on ctrl + c, or SIGTERM on unix
+pub async fn shutdown_signal() -> Result<(), Errors> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .map_err(|err| Errors::Other(format!("installing SIGTERM handler: {err}")))?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res.map_err(|err| Errors::Other(err.to_string()))?,
+            _ = terminate.recv() => {}
+        }
+        return Ok(());
+    }
+    #[cfg(not(unix))]
+    {
+        return tokio::signal::ctrl_c().await.map_err(|err| Errors::Other(err.to_string()));
+    }
+}
+
+/// `<n pattern` where both n and pattern are optional
+async fn replay(args: &str, settings: &settings::SettingsValidated, streams: &types::ArcMutex<display::Streams>) -> Result<String, Errors> {
+    let (count, pattern) = match args.split_once(" ").unwrap_or((args, "")) {
+        (count, pattern) if count.parse::<usize>().is_ok() => (count.parse::<usize>().ok(), pattern.trim()),
+        _ => (None, args),
+    };
+    let pattern = match pattern {
+        "" => None,
+        pattern => match settings::new_regex(pattern, settings.ignore_case) {
+            Ok(pattern) => Some(pattern),
+            Err(err) => return Ok(format!("invalid replay pattern: {err}")),
+        },
+    };
+    return Ok(match display::replay(streams, settings, count, pattern).await? {
+        Some((replayed, printed)) => format!("replayed {printed} of the last {replayed} lines received"),
+        None => "replay buffer disabled, see --replay-buffer".to_string(),
+    });
+}
+
+/// `f pattern` focuses the output on the followed pod matching the pattern, `f` alone removes the focus
+async fn focus(
+    pattern: &str,
+    settings: &settings::SettingsValidated,
+    pods: &types::ArcMutex<kubernetes::Pods>,
+    streams: &types::ArcMutex<display::Streams>,
+) -> Result<String, Errors> {
+    if pattern.len() == 0 {
+        display::set_focus(streams, None).await?;
+        return Ok(String::new());
+    }
+    let pattern = match settings::new_regex(pattern, settings.ignore_case) {
+        Ok(pattern) => pattern,
+        Err(err) => return Ok(format!("invalid focus pattern: {err}")),
+    };
+    let ids: Vec<String> = pods
+        .lock()
+        .await
+        .items
+        .iter()
+        .map(|pod| pod.id())
+        .filter(|id| pattern.is_match(id))
+        .collect();
+    // a pod whose full id is typed is picked even if its name is a prefix of other pods
+    let exact = ids.iter().find(|id| pattern.as_str() == id.as_str());
+    return Ok(match (ids.len(), exact) {
+        (0, _) => format!("no followed pod matches {pattern}"),
+        (1, _) | (_, Some(_)) => {
+            let id = exact.unwrap_or(&ids[0]);
+            display::set_focus(streams, Some(std::sync::Arc::from(id.as_str()))).await?;
+            String::new()
+        }
+        (_, None) => format!("{} pods match {pattern}, narrow the pattern: {}", ids.len(), ids.join(", ")),
+    });
+}
+
+/// read commands typed on stdin (followed by enter) while streaming:
+/// `/pattern` replaces the live filter, `/` alone removes it, space or an empty line pauses or resumes the output,
+/// `<n pattern` prints again the last n lines received matching the filters and the pattern,
+/// `f pattern` prints only the lines of the pod matching the pattern until `f` alone is typed
+pub async fn read_commands(
+    settings: settings::SettingsValidated,
+    pods: types::ArcMutex<kubernetes::Pods>,
+    streams: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    while let Some(command) = lines.next_line().await.map_err(|err| Errors::Other(format!("reading stdin: {err}")))? {
+        if command.trim().len() == 0 {
+            display::toggle_pause(&streams).await?;
+            continue;
+        }
+        if let Some(args) = command.trim().strip_prefix("<") {
+            let message = replay(args.trim(), &settings, &streams).await?;
+            let mut streams = streams.lock().await;
+            display::print_color(&mut streams.err, None, message).await?;
+            continue;
+        }
+        if let Some(args) = command.trim().strip_prefix("f").filter(|args| args.len() == 0 || args.starts_with(" ")) {
+            // the writer reports the focus changes itself
+            let message = focus(args.trim(), &settings, &pods, &streams).await?;
+            if message.len() > 0 {
+                let mut streams = streams.lock().await;
+                display::print_color(&mut streams.err, None, message).await?;
+            }
+            continue;
+        }
+        let message = match command.trim_end().strip_prefix("/") {
+            Some("") => {
+                settings.state.set_live_filter(None);
+                "live filter removed".to_string()
+            }
+            Some(pattern) => match settings::new_regex(pattern, settings.ignore_case) {
+                Ok(live_filter) => {
+                    settings.state.set_live_filter(Some(live_filter));
+                    format!("live filter set to {pattern}")
+                }
+                Err(err) => format!("invalid live filter: {err}"),
+            },
+            None => format!("unknown command {command}, use /pattern to filter, <n pattern to replay, f pattern to focus on a pod or space to pause"),
+        };
+        let mut streams = streams.lock().await;
+        display::print_color(&mut streams.err, None, message).await?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn validated(args: &[&str]) -> settings::SettingsValidated {
+        let args: Vec<&str> = std::iter::once("rusty_stern").chain(args.iter().copied()).collect();
+        return settings::Settings::try_parse_from(args).unwrap().to_validated().unwrap();
+    }
+
+    #[test]
+    fn reports_the_processors_which_are_not_reloaded() {
+        let current = validated(&["--replace-pattern", "db", "--replace-value", "database"]);
+        let reloaded = validated(&["--replace-pattern", "db", "--replace-value", "database", "--filter", "error"]);
+        assert_eq!(not_reloaded(&current, &reloaded), Vec::<String>::new());
+        assert_eq!(not_reloaded(&current, &validated(&["--redact", "emails"])), vec!["replace", "redact"]);
+    }
+
+    #[test]
+    fn the_reloaded_filters_follow_the_namespace_sections() {
+        let state = state::RunState::default();
+        let keeps = |section: Option<&str>, filter: &settings::Filter, line: &str| {
+            return state.current_filter_match(&section.map(str::to_string), filter, settings::Filter::keeps, line);
+        };
+        let startup = validated(&["--filter", "startup"]).filter;
+        assert!(keeps(Some("shop"), &startup, "startup line"));
+        state.set_reloaded_filters(HashMap::from([
+            (None, validated(&["--filter", "top"]).filter),
+            (Some("shop".to_string()), validated(&["--filter", "shop"]).filter),
+        ]));
+        assert!(keeps(Some("shop"), &startup, "shop line"));
+        assert!(!keeps(Some("shop"), &startup, "top line"));
+        // a section removed from the config file gets the top level filters
+        assert!(keeps(Some("billing"), &startup, "top line"));
+        assert!(!keeps(None, &startup, "startup line"));
+    }
+}
@@ -1,15 +1,30 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::Write;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 
+use clap::ValueEnum;
 use colors_transform::Color as ColorTransform;
+use regex::Regex;
 use termcolor::WriteColor;
 use validator::Validate;
 
+use crate::actions;
+use crate::capture;
+use crate::encoder;
 use crate::error::Errors;
 use crate::kubernetes;
+use crate::lineproc;
+use crate::metrics;
+use crate::replay;
 use crate::settings;
+use crate::sinks;
+use crate::state;
+use crate::stats;
 use crate::types;
+use crate::writer;
 
 #[derive(Debug, Validate, Clone)]
 pub struct Saturation {
@@ -58,10 +73,13 @@ impl FromStr for Hue {
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct HueInterval {
     pub start: Hue,
     pub end: Hue,
+    /// overrides of --color-saturation and --color-lightness for the hues of the interval
+    pub saturation: Option<Saturation>,
+    pub lightness: Option<Lightness>,
 }
 
 impl HueInterval {
@@ -74,10 +92,22 @@ impl HueInterval {
     }
 }
 
+/// $start-$end with optional saturation and lightness overrides: $start-$end@$sat,$light, either can be left empty
 impl FromStr for HueInterval {
     type Err = Errors;
     fn from_str(string: &str) -> Result<Self, Self::Err> {
-        let interval: Vec<&str> = string.split("-").collect();
+        let (range, overrides) = match string.split_once("@") {
+            Some((range, overrides)) => (range, Some(overrides)),
+            None => (string, None),
+        };
+        let (saturation, lightness) = match overrides.map(|overrides| overrides.split_once(",").unwrap_or((overrides, ""))) {
+            Some((saturation, lightness)) => (
+                parse_shade::<Saturation>(saturation, string)?,
+                parse_shade::<Lightness>(lightness, string)?,
+            ),
+            None => (None, None),
+        };
+        let interval: Vec<&str> = range.split("-").collect();
         if interval.len() != 2 {
             return Err(Errors::Validation(format!("excpected 2 elements, found {} for {string}", interval.len())));
         }
@@ -89,7 +119,50 @@ impl FromStr for HueInterval {
                 "failled to parse {string}: format excpected => 0 <= value <= 359 && start < end"
             )));
         }
-        return Ok(HueInterval { start, end });
+        return Ok(HueInterval {
+            start,
+            end,
+            saturation,
+            lightness,
+        });
+    }
+}
+
+/// an empty saturation or lightness override keeps the one of the settings
+fn parse_shade<T: FromStr<Err = Errors> + Validate>(value: &str, interval: &str) -> Result<Option<T>, Errors> {
+    if value.len() == 0 {
+        return Ok(None);
+    }
+    let shade = T::from_str(value).map_err(|err| Errors::Validation(format!("failled to parse {interval}: {err}")))?;
+    shade
+        .validate()
+        .map_err(|err| Errors::Validation(format!("failled to parse {interval}: {err}")))?;
+    return Ok(Some(shade));
+}
+
+/// the hue intervals of one --hue-intervals value, the comma separating the saturation from the lightness of an interval
+/// is told apart from the one separating the intervals as the lightness has no dash
+#[derive(Debug, Clone)]
+pub struct HueIntervals(pub Vec<HueInterval>);
+
+impl FromStr for HueIntervals {
+    type Err = Errors;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut items: Vec<String> = vec![];
+        for item in string.split(",") {
+            match items.last_mut() {
+                Some(last) if !item.contains("-") && last.contains("@") && !last.contains(",") => {
+                    last.push(',');
+                    last.push_str(item);
+                }
+                _ => items.push(item.to_string()),
+            }
+        }
+        let intervals = items
+            .iter()
+            .map(|item| HueInterval::from_str(item))
+            .collect::<Result<Vec<HueInterval>, Errors>>()?;
+        return Ok(HueIntervals(intervals));
     }
 }
 
@@ -114,12 +187,228 @@ impl FromStr for Hsl {
     }
 }
 
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum OutputMode {
+    /// colored lines prefixed by the pod name
+    Default,
+    /// one json object per line, without colors
+    Json,
+    /// only the log lines as sent by kubernetes, without prefix, colors or status messages
+    Raw,
+    /// default output with the node name and the pod ip added to the prefix
+    Wide,
+    /// one line of key=value pairs per log line, without colors
+    Logfmt,
+    /// one csv row per log line after a header, without colors
+    Csv,
+    /// colored lines formatted by the template setting
+    Template,
+}
+
+/// how the lines following the first one of a message holding newlines (eg: grouped lines, stack traces) are printed
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum ContinuationMode {
+    /// as sent by the container, without prefix
+    None,
+    /// prefixed by the pod prefix like the first line
+    Prefix,
+    /// indented to the message column behind a mark
+    Indent,
+}
+
+/// what happens to the lines of the other pods while the output is focused on a single one
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum FocusOthers {
+    /// kept and printed once the focus is removed
+    Buffer,
+    /// not printed at all
+    Drop,
+}
+
+/// mark of the continuation lines in the indent continuation mode
+const CONTINUATION_MARK: &str = "↳ ";
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplateVar {
+    Namespace,
+    Pod,
+    Container,
+    Node,
+    Timestamp,
+    Message,
+    Padding,
+}
+
+#[derive(Debug, Clone)]
+enum TemplatePart<V> {
+    Text(String),
+    Var(V),
+}
+
+/// the text and {{variable}} parts of a template, var_of gives the variable of a name
+fn parse_template_parts<V>(string: &str, var_of: impl Fn(&str) -> Option<V>) -> Result<Vec<TemplatePart<V>>, Errors> {
+    let mut parts = vec![];
+    let mut rest = string;
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            parts.push(TemplatePart::Text(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find("}}")
+            .ok_or(Errors::Validation(format!("unclosed {{{{ in template {string}")))?;
+        let name = rest[start + 2..start + end].trim();
+        let var = var_of(name).ok_or(Errors::Validation(format!("unknown variable {name} in template {string}")))?;
+        parts.push(TemplatePart::Var(var));
+        rest = &rest[start + end + 2..];
+    }
+    if rest.len() > 0 {
+        parts.push(TemplatePart::Text(rest.to_string()));
+    }
+    return Ok(parts);
+}
+
+/// line format with {{namespace}}, {{pod}}, {{container}}, {{node}}, {{timestamp}}, {{message}} and {{padding}} variables
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<TemplatePart<TemplateVar>>,
+}
+
+pub struct TemplateValues<'a> {
+    pub namespace: &'a str,
+    pub pod: &'a str,
+    pub container: &'a str,
+    pub node: &'a str,
+    pub timestamp: &'a str,
+    pub message: &'a str,
+    pub padding: &'a str,
+}
+
+impl FromStr for Template {
+    type Err = Errors;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let parts = parse_template_parts(string, |name| {
+            return match name {
+                "namespace" => Some(TemplateVar::Namespace),
+                "pod" => Some(TemplateVar::Pod),
+                "container" => Some(TemplateVar::Container),
+                "node" => Some(TemplateVar::Node),
+                "timestamp" => Some(TemplateVar::Timestamp),
+                "message" => Some(TemplateVar::Message),
+                "padding" => Some(TemplateVar::Padding),
+                _ => None,
+            };
+        })?;
+        return Ok(Template { parts });
+    }
+}
+
+impl Template {
+    /// returns the rendered text before the first {{message}} and the rendered text starting at it
+    pub fn render(&self, values: &TemplateValues) -> (String, String) {
+        let mut before = String::new();
+        let mut after = String::new();
+        let mut message_found = false;
+        for part in self.parts.iter() {
+            if let TemplatePart::Var(TemplateVar::Message) = part {
+                message_found = true;
+            }
+            let text = match part {
+                TemplatePart::Text(text) => text.as_str(),
+                TemplatePart::Var(TemplateVar::Namespace) => values.namespace,
+                TemplatePart::Var(TemplateVar::Pod) => values.pod,
+                TemplatePart::Var(TemplateVar::Container) => values.container,
+                TemplatePart::Var(TemplateVar::Node) => values.node,
+                TemplatePart::Var(TemplateVar::Timestamp) => values.timestamp,
+                TemplatePart::Var(TemplateVar::Message) => values.message,
+                TemplatePart::Var(TemplateVar::Padding) => values.padding,
+            };
+            if message_found {
+                after.push_str(text);
+            } else {
+                before.push_str(text);
+            }
+        }
+        return (before, after);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BannerVar {
+    Id,
+    Namespace,
+    Pod,
+    Container,
+    Node,
+    Restarts,
+    Age,
+    Image,
+}
+
+/// format of the status message printed when a pod starts or stops being followed, with {{id}}, {{namespace}}, {{pod}},
+/// {{container}}, {{node}}, {{restarts}}, {{age}} and {{image}} variables. an empty template disables the message
+#[derive(Debug, Clone)]
+pub struct BannerTemplate {
+    parts: Vec<TemplatePart<BannerVar>>,
+}
+
+impl FromStr for BannerTemplate {
+    type Err = Errors;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let parts = parse_template_parts(string, |name| {
+            return match name {
+                "id" => Some(BannerVar::Id),
+                "namespace" => Some(BannerVar::Namespace),
+                "pod" => Some(BannerVar::Pod),
+                "container" => Some(BannerVar::Container),
+                "node" => Some(BannerVar::Node),
+                "restarts" => Some(BannerVar::Restarts),
+                "age" => Some(BannerVar::Age),
+                "image" => Some(BannerVar::Image),
+                _ => None,
+            };
+        })?;
+        return Ok(BannerTemplate { parts });
+    }
+}
+
+/// the message of a pod start or stop: the default one without template, None when the template is empty
+pub fn banner_message(template: &Option<BannerTemplate>, pod: &kubernetes::Pod, default: String) -> Option<String> {
+    let Some(template) = template else {
+        return Some(default);
+    };
+    if template.parts.len() == 0 {
+        return None;
+    }
+    let mut message = String::new();
+    for part in template.parts.iter() {
+        match part {
+            TemplatePart::Text(text) => message.push_str(text),
+            TemplatePart::Var(BannerVar::Id) => message.push_str(&pod.id()),
+            TemplatePart::Var(BannerVar::Namespace) => message.push_str(&pod.namespace.name),
+            TemplatePart::Var(BannerVar::Pod) => message.push_str(&pod.name),
+            TemplatePart::Var(BannerVar::Container) => message.push_str(&pod.default_container()),
+            TemplatePart::Var(BannerVar::Node) => message.push_str(&pod.node_name()),
+            TemplatePart::Var(BannerVar::Restarts) => message.push_str(&pod.restarts().to_string()),
+            TemplatePart::Var(BannerVar::Age) => message.push_str(&pod.age()),
+            TemplatePart::Var(BannerVar::Image) => message.push_str(&pod.image()),
+        }
+    }
+    return Some(message);
+}
+
+/// number of distinct hues given to the ordinals of a statefulset before shifting
+const ORDINAL_HUES: u32 = 8;
+/// (saturation, lightness) offsets of the shades given to the pods of a namespace hue band before moving the hue within the band
+const NAMESPACE_SHADES: [(i32, i32); 6] = [(0, 0), (0, -15), (0, 15), (-35, 0), (-35, -15), (-35, 15)];
+/// lightness offsets given in turn to the colors of the cycle, so that close hues stay distinguishable with many pods
+const LIGHTNESS_JITTER: [i32; 5] = [0, -6, 6, -3, 3];
+
 #[derive(Clone)]
 pub struct ColorParams {
     pod_cnt: u8,
-    saturation: Saturation,
-    lightness: Lightness,
-    hue_intervals: Vec<Hue>,
+    /// every hue of the intervals with the saturation and lightness of its interval
+    hue_intervals: Vec<Hsl>,
+    color_map: Vec<crate::settings::ColorMapping>,
     state: ColorGeneratorState,
 }
 
@@ -137,14 +426,17 @@ impl ColorParams {
         let mut hue_values = vec![];
         for interval in settings.hue_intervals.iter() {
             for val in interval.start.value..interval.end.value + 1 {
-                hue_values.push(Hue { value: val });
+                hue_values.push(Hsl {
+                    h: Hue { value: val },
+                    s: interval.saturation.clone().unwrap_or(settings.color_saturation.clone()),
+                    l: interval.lightness.clone().unwrap_or(settings.color_lightness.clone()),
+                });
             }
         }
         return ColorParams {
             pod_cnt: pod_cnt as u8,
-            saturation: settings.color_saturation.clone(),
-            lightness: settings.color_lightness.clone(),
             hue_intervals: hue_values.clone(),
+            color_map: settings.color_map.clone(),
             state: ColorGeneratorState {
                 step: 0,
                 offset: 0,
@@ -161,6 +453,28 @@ impl ColorParams {
         }
         return colors;
     }
+    /// the same ordinal always gets the same color: the hues are spread in 8 steps, shifted by half a step every 8 ordinals
+    fn ordinal_color(&self, ordinal: u32) -> colors_transform::Rgb {
+        let hue_count = self.hue_intervals.len() as u32;
+        let step = (hue_count / ORDINAL_HUES).max(1);
+        let hue_index = ((ordinal % ORDINAL_HUES) * step + (ordinal / ORDINAL_HUES) * (step / 2).max(1)) % hue_count;
+        let hue = &self.hue_intervals[hue_index as usize];
+        return colors_transform::Hsl::from(hue.h.value as f32, hue.s.value as f32, hue.l.value as f32).to_rgb();
+    }
+
+    /// the hues are split in band_count bands, the shades vary the saturation and lightness around the requested ones, then the hue within the band
+    fn namespace_color(&self, band: u32, band_count: u32, shade: u32) -> colors_transform::Rgb {
+        let hue_count = self.hue_intervals.len() as u32;
+        let band_width = (hue_count / band_count.max(1)).max(1);
+        let (saturation_offset, lightness_offset) = NAMESPACE_SHADES[shade as usize % NAMESPACE_SHADES.len()];
+        let hue_shift = (shade / NAMESPACE_SHADES.len() as u32) * (band_width / 4).max(1);
+        let hue_index = (band * band_width + (band_width / 2 + hue_shift) % band_width) % hue_count;
+        let hue = &self.hue_intervals[hue_index as usize];
+        let saturation = (hue.s.value as i32 + saturation_offset).clamp(10, 100);
+        let lightness = (hue.l.value as i32 + lightness_offset).clamp(15, 85);
+        return colors_transform::Hsl::from(hue.h.value as f32, saturation as f32, lightness as f32).to_rgb();
+    }
+
     fn next_color(&mut self) -> colors_transform::Rgb {
         if self.state.step >= self.state.pod_cnt {
             self.state.offset = (self.state.hue_count / self.state.pod_cnt) / 2;
@@ -174,12 +488,14 @@ impl ColorParams {
 
         let current_hue_index = std::cmp::min(hue_step * self.state.step, self.state.hue_count - 1);
         let current_hue = &self.hue_intervals[current_hue_index as usize];
-        if self.state.hue_generated.contains(&current_hue.value) {
+        if self.state.hue_generated.contains(&current_hue.h.value) {
             self.state.step += 1;
             return self.next_color();
         }
-        self.state.hue_generated.insert(current_hue.value);
-        let hsl = colors_transform::Hsl::from(current_hue.value as f32, self.saturation.value as f32, self.lightness.value as f32);
+        let jitter = LIGHTNESS_JITTER[self.state.hue_generated.len() % LIGHTNESS_JITTER.len()];
+        self.state.hue_generated.insert(current_hue.h.value);
+        let lightness = (current_hue.l.value as i32 + jitter).clamp(0, 100);
+        let hsl = colors_transform::Hsl::from(current_hue.h.value as f32, current_hue.s.value as f32, lightness as f32);
         self.state.step += 1;
         return hsl.to_rgb();
     }
@@ -187,43 +503,543 @@ impl ColorParams {
 
 #[derive(Clone)]
 pub struct Colors {
-    available: Vec<colors_transform::Rgb>,
+    /// free list: new pods take the color released the longest time ago, released colors go to the back
+    available: VecDeque<colors_transform::Rgb>,
+    /// one entry per pod using the color, a color is only released when its last pod is
     used: Vec<colors_transform::Rgb>,
     colors_param: ColorParams,
+    /// color given to each pod and workload, persisted when a color file is set
+    assigned: HashMap<String, colors_transform::Rgb>,
+    color_file: Option<PathBuf>,
+    /// namespaces in the order of their hue band when the namespaces have their own band
+    namespace_bands: Vec<String>,
+    /// shades already given in each namespace hue band
+    namespace_shades: HashMap<String, u32>,
 }
 
 impl Colors {
     pub fn new(colors_param: &mut ColorParams) -> Colors {
         let colors = colors_param.init_colors();
         return Colors {
-            available: colors,
+            available: VecDeque::from(colors),
             used: vec![],
             colors_param: colors_param.clone(),
+            assigned: HashMap::new(),
+            color_file: None,
+            namespace_bands: vec![],
+            namespace_shades: HashMap::new(),
         };
     }
 
+    /// read the colors assigned by the previous invocations, the file is created on save if it doesn't exist
+    pub fn load_color_file(&mut self, path: &PathBuf) -> Result<(), Errors> {
+        self.color_file = Some(path.clone());
+        if !path.exists() {
+            return Ok(());
+        }
+        let content =
+            std::fs::read_to_string(path).map_err(|err| Errors::Validation(format!("failled to read color file {}: {err}", path.display())))?;
+        let raw: HashMap<String, String> =
+            serde_json::from_str(&content).map_err(|err| Errors::Validation(format!("failled to parse color file {}: {err}", path.display())))?;
+        for (key, hex) in raw {
+            let color = colors_transform::Rgb::from_hex_str(&hex)
+                .map_err(|_| Errors::Validation(format!("failled to parse color file {}: invalid color {hex}", path.display())))?;
+            self.assigned.insert(key, color);
+        }
+        return Ok(());
+    }
+
+    pub fn save_color_file(&self) -> Result<(), Errors> {
+        let Some(path) = &self.color_file else {
+            return Ok(());
+        };
+        let raw: HashMap<&String, String> = self.assigned.iter().map(|(key, color)| (key, color.to_css_hex_string())).collect();
+        let content = serde_json::to_string_pretty(&raw).map_err(|err| Errors::Other(err.to_string()))?;
+        std::fs::write(path, content).map_err(|err| Errors::Other(format!("writing color file {}: {err}", path.display())))?;
+        return Ok(());
+    }
+
+    fn is_used(&self, color: &colors_transform::Rgb) -> bool {
+        return self.used.iter().any(|item| item.as_tuple() == color.as_tuple());
+    }
+
+    /// the color previously given to the first of the keys (eg: the pod then its workload) which is not used by another pod, or a new one
+    pub fn get_color_for(&mut self, keys: &[String]) -> colors_transform::Rgb {
+        let previous = keys
+            .iter()
+            .filter_map(|key| self.assigned.get(key))
+            .find(|color| !self.is_used(color))
+            .cloned();
+        let color = match previous {
+            Some(color) => {
+                self.available.retain(|item| item.as_tuple() != color.as_tuple());
+                self.used.push(color);
+                color
+            }
+            None => self.get_new_color(),
+        };
+        for key in keys {
+            self.assigned.insert(key.clone(), color);
+        }
+        return color;
+    }
+
+    /// color of the first color map rule matching the pod name, it's not taken from the pool either
+    pub fn get_mapped_color(&self, pod_name: &str) -> Option<colors_transform::Rgb> {
+        return self
+            .colors_param
+            .color_map
+            .iter()
+            .find(|mapping| mapping.pattern.is_match(pod_name))
+            .map(|mapping| mapping.color);
+    }
+
+    /// give each namespace a hue band, in the order they are followed
+    pub fn set_namespace_bands(&mut self, namespaces: Vec<String>) {
+        self.namespace_bands = namespaces;
+    }
+
+    /// a shade of the hue band of the namespace, the next one unless given (eg: a statefulset ordinal), it's not taken from the pool
+    pub fn get_namespace_color(&mut self, namespace: &str, shade: Option<u32>) -> colors_transform::Rgb {
+        let band = match self.namespace_bands.iter().position(|name| name == namespace) {
+            Some(band) => band,
+            None => {
+                self.namespace_bands.push(namespace.to_string());
+                self.namespace_bands.len() - 1
+            }
+        };
+        let shade = shade.unwrap_or_else(|| {
+            let next_shade = self.namespace_shades.entry(namespace.to_string()).or_insert(0);
+            *next_shade += 1;
+            return *next_shade - 1;
+        });
+        return self.colors_param.namespace_color(band as u32, self.namespace_bands.len() as u32, shade);
+    }
+
+    /// color of a statefulset replica, it's not taken from the pool so several statefulsets share the colors of their ordinals
+    pub fn get_ordinal_color(&self, ordinal: u32) -> colors_transform::Rgb {
+        return self.colors_param.ordinal_color(ordinal);
+    }
+
     pub fn get_new_color(&mut self) -> colors_transform::Rgb {
-        let color = self.available.pop().unwrap_or(self.colors_param.next_color());
+        let color = match self.available.pop_front() {
+            Some(color) => color,
+            None => self.colors_param.next_color(),
+        };
         self.used.push(color);
         return color;
     }
 
+    /// the hues, saturation, lightness and color map of reloaded settings: the free colors are generated again from them,
+    /// the followed pods keep their color until they are followed again
+    pub fn reload(&mut self, colors_param: &mut ColorParams) {
+        let colors = colors_param.init_colors();
+        self.available = colors.into_iter().filter(|color| !self.is_used(color)).collect();
+        self.colors_param = colors_param.clone();
+    }
+
+    /// release the color of a pod which is not followed anymore, unknown colors are ignored
     pub fn set_color_to_unused(&mut self, color: colors_transform::Rgb) {
-        self.used
-            .remove(self.used.iter().position(|item| item.as_tuple() == color.as_tuple()).unwrap_or(0));
-        self.available.push(color);
+        let Some(position) = self.used.iter().position(|item| item.as_tuple() == color.as_tuple()) else {
+            tracing::debug!(color = %color.to_css_hex_string(), "released color was not in use");
+            return;
+        };
+        self.used.remove(position);
+        if !self.is_used(&color) {
+            self.available.push_back(color);
+        }
     }
 }
 
+/// lines are not wrapped if less than this number of columns is left after the prefix
+const MIN_WRAP_WIDTH: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+enum StatusTarget {
+    Out,
+    Err,
+    Suppressed,
+}
+
+/// stdout, or the stdin of the pager when one is used
+pub type Output = Box<dyn termcolor::WriteColor + Send>;
+
 pub struct Streams {
-    pub out: termcolor::StandardStream,
+    /// stdout is owned by the writer thread
+    pub out: writer::Writer,
     pub err: termcolor::StandardStream,
+    error_style: Vec<settings::TextStyle>,
+    status_target: StatusTarget,
+    /// lines waiting to be printed in timestamp order when ordered output is requested
+    pub reorder_buffer: Vec<ReorderEntry>,
+    /// lines waiting for the same line from other pods when aggregate is requested, by aggregate key
+    pub aggregate_buffer: HashMap<String, AggregateEntry>,
+    /// lines waiting to be posted when the http sink is enabled
+    pub http_sink: Option<sinks::HttpSink>,
+    /// formatted messages are sent to the syslog forwarding task through this channel
+    pub syslog_sink: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+    /// lines sent to the thread writing the sqlite capture
+    pub sqlite_sink: Option<tokio::sync::mpsc::UnboundedSender<capture::CapturedLine>>,
+    /// writes the received lines to the record file
+    pub recorder: Option<Arc<replay::Recorder>>,
+    /// printed to stderr on exit when requested
+    pub summary: Option<stats::Summary>,
+    /// files of the output dir, shared by the streams of a workload when grouped by owner
+    pub output_files: Option<sinks::OutputFiles>,
+    /// last lines received, printed again on demand
+    pub replay_buffer: Option<Arc<std::sync::Mutex<ReplayBuffer>>>,
+    /// target of the pod lifecycle events
+    pub lifecycle_webhook: Option<sinks::HttpTarget>,
+    status_window: Option<std::time::Duration>,
+    /// started and stopped messages of the pods in the current status window, see print_banner
+    banners: HashMap<String, BannerWindow>,
+}
+
+struct BannerWindow {
+    since: tokio::time::Instant,
+    printed: u32,
+    /// started messages suppressed since the window started
+    restarts: u32,
+    color: Option<colors_transform::Rgb>,
+}
+
+pub struct ReorderEntry {
+    received: tokio::time::Instant,
+    date: chrono::DateTime<chrono::FixedOffset>,
+    line: String,
+    pod: kubernetes::Pod,
+    prefix: Prefix,
+}
+
+/// queue a line which doesn't come from the log stream of the pod (eg: its events), it's printed sorted with its log lines
+pub async fn push_reordered(
+    streams: &types::ArcMutex<Streams>,
+    pod: &kubernetes::Pod,
+    prefix: &Prefix,
+    date: chrono::DateTime<chrono::FixedOffset>,
+    line: String,
+) {
+    streams.lock().await.reorder_buffer.push(ReorderEntry {
+        received: tokio::time::Instant::now(),
+        date,
+        line,
+        pod: pod.clone(),
+        prefix: prefix.clone(),
+    });
+}
+
+/// print the buffered lines received more than window ago, sorted by their timestamp, runs forever
+pub async fn flush_reorder_buffer(
+    window: tokio::time::Duration,
+    settings: settings::SettingsValidated,
+    streams: types::ArcMutex<Streams>,
+) -> Result<(), Errors> {
+    loop {
+        tokio::time::sleep(window / 2).await;
+        print_reordered(&settings, &streams, window).await?;
+    }
+}
+
+/// print the buffered lines received more than window ago, sorted by their timestamp
+async fn print_reordered(
+    settings: &settings::SettingsValidated,
+    streams: &types::ArcMutex<Streams>,
+    window: tokio::time::Duration,
+) -> Result<(), Errors> {
+    let (mut ready, out) = {
+        let mut streams = streams.lock().await;
+        let now = tokio::time::Instant::now();
+        let (ready, waiting): (Vec<ReorderEntry>, Vec<ReorderEntry>) = streams
+            .reorder_buffer
+            .drain(..)
+            .partition(|entry| now.duration_since(entry.received) >= window);
+        streams.reorder_buffer = waiting;
+        (ready, streams.out.clone())
+    };
+    ready.sort_by(|current, next| current.date.cmp(&next.date));
+    for entry in ready {
+        print_pod_line(entry.line, settings, &entry.prefix, &out, &entry.pod).await?;
+    }
+    return Ok(());
+}
+
+/// a line received from one or more pods during the aggregate window, printed once with the pods it came from
+pub struct AggregateEntry {
+    received: tokio::time::Instant,
+    line: String,
+    pod: kubernetes::Pod,
+    prefix: Prefix,
+    /// names of the pods which sent the line, in their receive order
+    pods: Vec<String>,
+    count: usize,
+}
+
+/// the message of the line without its timestamp and replace markers, the parts matching the aggregate pattern (eg: ids or durations)
+/// don't tell the lines apart
+fn aggregate_key(line: &str, pattern: &Option<Regex>) -> String {
+    let message = lineproc::strip_replace_markers(lineproc::split_timestamp(line).1);
+    return match pattern {
+        Some(pattern) => pattern.replace_all(&message, "*").into_owned(),
+        None => message.into_owned(),
+    };
 }
 
-pub fn new_streams() -> Streams {
-    return Streams {
-        out: termcolor::StandardStream::stdout(termcolor::ColorChoice::Always),
+/// print the aggregated lines whose window is over, runs forever
+pub async fn flush_aggregate_buffer(
+    window: tokio::time::Duration,
+    settings: settings::SettingsValidated,
+    streams: types::ArcMutex<Streams>,
+) -> Result<(), Errors> {
+    loop {
+        tokio::time::sleep(window / 2).await;
+        print_aggregated(&settings, &streams, window).await?;
+    }
+}
+
+/// print the lines received more than window ago in their receive order, followed by the number of times and the pods they came from
+async fn print_aggregated(
+    settings: &settings::SettingsValidated,
+    streams: &types::ArcMutex<Streams>,
+    window: tokio::time::Duration,
+) -> Result<(), Errors> {
+    let (mut ready, out) = {
+        let mut streams = streams.lock().await;
+        let now = tokio::time::Instant::now();
+        let (ready, waiting): (HashMap<String, AggregateEntry>, HashMap<String, AggregateEntry>) = streams
+            .aggregate_buffer
+            .drain()
+            .partition(|(_, entry)| now.duration_since(entry.received) >= window);
+        streams.aggregate_buffer = waiting;
+        (ready.into_values().collect::<Vec<AggregateEntry>>(), streams.out.clone())
+    };
+    ready.sort_by(|current, next| current.received.cmp(&next.received));
+    for entry in ready {
+        let line = if entry.count > 1 {
+            format!(
+                "[x{} from {} pods: {}] {}",
+                entry.count,
+                entry.pods.len(),
+                entry.pods.join(", "),
+                entry.line
+            )
+        } else {
+            entry.line
+        };
+        print_pod_line(line, settings, &entry.prefix, &out, &entry.pod).await?;
+    }
+    return Ok(());
+}
+
+/// print the lines waiting to be reordered or aggregated and send the ones waiting for the http sink, used when stopping
+pub async fn flush_pending_lines(settings: &settings::SettingsValidated, streams: &types::ArcMutex<Streams>) -> Result<(), Errors> {
+    print_aggregated(settings, streams, tokio::time::Duration::ZERO).await?;
+    print_reordered(settings, streams, tokio::time::Duration::ZERO).await?;
+    return sinks::send_http_pending(streams).await;
+}
+
+/// $PAGER or less, less is told to keep the colors if the user did not configure it
+fn spawn_pager() -> Result<(std::process::Child, std::process::ChildStdin), Errors> {
+    let pager = std::env::var("PAGER").unwrap_or("less".to_string());
+    let mut args = pager.split_whitespace();
+    let program = args.next().ok_or(Errors::Validation("PAGER is empty".to_string()))?;
+    let mut command = std::process::Command::new(program);
+    command.args(args).stdin(std::process::Stdio::piped());
+    if std::env::var("LESS").is_err() {
+        command.env("LESS", "R");
+    }
+    let mut child = command.spawn().map_err(|err| Errors::Other(format!("starting pager {pager}: {err}")))?;
+    let stdin = child.stdin.take().ok_or(Errors::Other(format!("opening pager {pager} stdin")))?;
+    return Ok((child, stdin));
+}
+
+pub fn new_streams(settings: &settings::SettingsValidated) -> Result<Streams, Errors> {
+    let (out_color, status_target) = match settings.output {
+        OutputMode::Default | OutputMode::Wide | OutputMode::Template => (termcolor::ColorChoice::Always, StatusTarget::Out),
+        OutputMode::Json | OutputMode::Logfmt | OutputMode::Csv => (termcolor::ColorChoice::Never, StatusTarget::Err),
+        OutputMode::Raw => (termcolor::ColorChoice::Never, StatusTarget::Suppressed),
+    };
+    let status_target = if settings.quiet {
+        StatusTarget::Suppressed
+    } else if settings.status_to_stderr {
+        StatusTarget::Err
+    } else {
+        status_target
+    };
+    let target = if settings.pager {
+        let (child, stdin) = spawn_pager()?;
+        let writer = std::io::LineWriter::new(stdin);
+        let out: Output = if out_color == termcolor::ColorChoice::Never {
+            Box::new(termcolor::NoColor::new(writer))
+        } else {
+            Box::new(termcolor::Ansi::new(writer))
+        };
+        writer::Target::Pager(out, child)
+    } else {
+        writer::Target::Stdout(termcolor::BufferWriter::stdout(out_color))
+    };
+    return Ok(Streams {
+        out: writer::Writer::new(target, settings),
         err: termcolor::StandardStream::stderr(termcolor::ColorChoice::Always),
+        error_style: settings.error_style.clone(),
+        status_target,
+        reorder_buffer: vec![],
+        aggregate_buffer: HashMap::new(),
+        http_sink: settings
+            .sink_http
+            .as_ref()
+            .map(|url| sinks::HttpSink::new(url.clone(), settings.sink_http_batch)),
+        syslog_sink: None,
+        sqlite_sink: None,
+        recorder: match &settings.record {
+            Some(path) => Some(Arc::new(replay::Recorder::create(path)?)),
+            None => None,
+        },
+        summary: if settings.summary { Some(stats::Summary::new()) } else { None },
+        output_files: settings
+            .output_dir
+            .as_ref()
+            .map(|output_dir| sinks::OutputFiles::new(output_dir, &settings.rotation, settings.compress, &settings.group_files)),
+        replay_buffer: if settings.replay_buffer > 0 {
+            Some(Arc::new(std::sync::Mutex::new(ReplayBuffer::new(settings.replay_buffer))))
+        } else {
+            None
+        },
+        lifecycle_webhook: settings.lifecycle_webhook.as_ref().map(|url| sinks::HttpTarget::new(url.clone())),
+        status_window: settings.status_window,
+        banners: HashMap::new(),
+    });
+}
+
+/// print the started and stopped messages of a pod, when it flaps only the first ones of the status window are printed,
+/// the following starts are counted and reported once the window is over
+pub async fn print_banner(
+    streams: &mut Streams,
+    pod_id: &str,
+    started: bool,
+    color: Option<colors_transform::Rgb>,
+    message: String,
+) -> Result<(), Errors> {
+    if streams.status_window.is_none() {
+        return print_status(streams, color, message).await;
+    }
+    flush_banners(streams).await?;
+    let banner = streams.banners.entry(pod_id.to_string()).or_insert(BannerWindow {
+        since: tokio::time::Instant::now(),
+        printed: 0,
+        restarts: 0,
+        color,
+    });
+    // a start and a stop
+    if banner.printed >= 2 {
+        if started {
+            banner.restarts += 1;
+        }
+        return Ok(());
+    }
+    banner.printed += 1;
+    return print_status(streams, color, message).await;
+}
+
+/// report the restarts counted in the status windows which are over
+pub async fn flush_banners(streams: &mut Streams) -> Result<(), Errors> {
+    let Some(window) = streams.status_window else {
+        return Ok(());
+    };
+    let expired: Vec<String> = streams
+        .banners
+        .iter()
+        .filter(|(_, banner)| banner.since.elapsed() >= window)
+        .map(|(pod_id, _)| pod_id.clone())
+        .collect();
+    for pod_id in expired {
+        let Some(banner) = streams.banners.remove(&pod_id) else {
+            continue;
+        };
+        if banner.restarts > 0 {
+            let message = format!("+++ {} restarted {} times in the last {}s", pod_id, banner.restarts, window.as_secs());
+            print_status(streams, banner.color, message).await?;
+        }
+    }
+    return Ok(());
+}
+
+#[derive(Clone)]
+struct ReplayEntry {
+    line: String,
+    pod: Arc<kubernetes::Pod>,
+    prefix: Prefix,
+}
+
+/// the last lines received across pods, kept before the filters so that replaying them applies the current ones
+pub struct ReplayBuffer {
+    capacity: usize,
+    entries: VecDeque<ReplayEntry>,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> ReplayBuffer {
+        return ReplayBuffer {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        };
+    }
+
+    fn push(&mut self, entry: ReplayEntry) {
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// the count last entries, all of them if None
+    fn last(&self, count: Option<usize>) -> Vec<ReplayEntry> {
+        let count = count.unwrap_or(self.entries.len()).min(self.entries.len());
+        return self.entries.iter().skip(self.entries.len() - count).cloned().collect();
+    }
+}
+
+/// print again the count last lines received (all the buffered ones if None) which match the current filters and the pattern
+/// returns the number of lines replayed and printed, None if the replay buffer is disabled
+pub async fn replay(
+    streams: &types::ArcMutex<Streams>,
+    settings: &settings::SettingsValidated,
+    count: Option<usize>,
+    pattern: Option<Regex>,
+) -> Result<Option<(usize, usize)>, Errors> {
+    let (out, entries) = {
+        let streams = streams.lock().await;
+        let Some(replay_buffer) = &streams.replay_buffer else {
+            return Ok(None);
+        };
+        let replay_buffer = replay_buffer
+            .lock()
+            .map_err(|err| Errors::Other(format!("reading replay buffer: {err}")))?;
+        (streams.out.clone(), replay_buffer.last(count))
+    };
+    let replayed = entries.len();
+    let mut printed = 0;
+    for entry in entries {
+        let Some(line) = process_log_line_marked(entry.line, settings) else {
+            continue;
+        };
+        if pattern
+            .as_ref()
+            .is_some_and(|pattern| !pattern.is_match(&lineproc::strip_replace_markers(&line)))
+        {
+            continue;
+        }
+        print_pod_line(line, settings, &entry.prefix, &out, &entry.pod).await?;
+        printed += 1;
+    }
+    return Ok(Some((replayed, printed)));
+}
+
+/// print status messages (pod started, stopped...), they go to stderr or are dropped when stdout must only contain log lines
+pub async fn print_status(streams: &mut Streams, color_rgb: Option<colors_transform::Rgb>, message: String) -> Result<(), Errors> {
+    return match streams.status_target {
+        StatusTarget::Out => streams.out.text(color_rgb, message),
+        StatusTarget::Err => print_color(&mut streams.err, color_rgb, message).await,
+        StatusTarget::Suppressed => Ok(()),
     };
 }
 
@@ -231,15 +1047,55 @@ pub fn new_streams_mutex(streams: Streams) -> types::ArcMutex<Streams> {
     return std::sync::Arc::new(tokio::sync::Mutex::new(streams));
 }
 
-pub async fn print_color(std: &mut termcolor::StandardStream, color_rgb: Option<colors_transform::Rgb>, message: String) -> Result<(), Errors> {
-    let mut message = message;
-    if let Some(last_char) = message.chars().last() {
-        if last_char.to_string() != "\n" {
-            message = format!("{message}\n");
+/// the windows console (without virtual terminal support) only knows 16 colors, windows terminal and the terminals setting COLORTERM
+/// handle rgb ones
+fn truecolor_supported() -> bool {
+    static TRUECOLOR: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    return *TRUECOLOR.get_or_init(|| {
+        if !cfg!(windows) {
+            return true;
+        }
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        return std::env::var("WT_SESSION").is_ok() || colorterm == "truecolor" || colorterm == "24bit";
+    });
+}
+
+/// colors of the default windows console palette, black is left out as it is unreadable on most backgrounds
+const CONSOLE_PALETTE: [(termcolor::Color, bool, (f32, f32, f32)); 15] = [
+    (termcolor::Color::Red, false, (128.0, 0.0, 0.0)),
+    (termcolor::Color::Green, false, (0.0, 128.0, 0.0)),
+    (termcolor::Color::Yellow, false, (128.0, 128.0, 0.0)),
+    (termcolor::Color::Blue, false, (0.0, 0.0, 128.0)),
+    (termcolor::Color::Magenta, false, (128.0, 0.0, 128.0)),
+    (termcolor::Color::Cyan, false, (0.0, 128.0, 128.0)),
+    (termcolor::Color::White, false, (192.0, 192.0, 192.0)),
+    (termcolor::Color::Black, true, (128.0, 128.0, 128.0)),
+    (termcolor::Color::Red, true, (255.0, 0.0, 0.0)),
+    (termcolor::Color::Green, true, (0.0, 255.0, 0.0)),
+    (termcolor::Color::Yellow, true, (255.0, 255.0, 0.0)),
+    (termcolor::Color::Blue, true, (0.0, 0.0, 255.0)),
+    (termcolor::Color::Magenta, true, (255.0, 0.0, 255.0)),
+    (termcolor::Color::Cyan, true, (0.0, 255.0, 255.0)),
+    (termcolor::Color::White, true, (255.0, 255.0, 255.0)),
+];
+
+/// nearest console color and its intensity
+fn console_color(color_rgb: colors_transform::Rgb) -> (termcolor::Color, bool) {
+    let distance = |(red, green, blue): (f32, f32, f32)| {
+        return (color_rgb.get_red() - red).powi(2) + (color_rgb.get_green() - green).powi(2) + (color_rgb.get_blue() - blue).powi(2);
+    };
+    let mut nearest = &CONSOLE_PALETTE[0];
+    for candidate in CONSOLE_PALETTE.iter() {
+        if distance(candidate.2) < distance(nearest.2) {
+            nearest = candidate;
         }
     }
-    let color_spec = match color_rgb {
-        Some(color_rgb) => {
+    return (nearest.0, nearest.1);
+}
+
+fn new_color_spec(color_rgb: Option<colors_transform::Rgb>) -> termcolor::ColorSpec {
+    return match color_rgb {
+        Some(color_rgb) if truecolor_supported() => {
             let mut spec = termcolor::ColorSpec::new();
             spec.set_fg(Some(termcolor::Color::Rgb(
                 color_rgb.get_red() as u8,
@@ -248,80 +1104,864 @@ pub async fn print_color(std: &mut termcolor::StandardStream, color_rgb: Option<
             )));
             spec
         }
+        Some(color_rgb) => {
+            let (color, intense) = console_color(color_rgb);
+            let mut spec = termcolor::ColorSpec::new();
+            spec.set_fg(Some(color)).set_intense(intense);
+            spec
+        }
         None => termcolor::ColorSpec::default(),
     };
+}
 
-    std.set_color(&color_spec).map_err(|err| Errors::StdErr(err.to_string()))?;
-    std.write_fmt(format_args!("{message}")).map_err(|err| Errors::StdErr(err.to_string()))?;
+/// color of the pod the error is about with the error style on top, so that errors stand out but can still be attributed
+fn error_color_spec(color_rgb: Option<colors_transform::Rgb>, error_style: &[settings::TextStyle]) -> termcolor::ColorSpec {
+    let mut spec = new_color_spec(color_rgb);
+    for style in error_style {
+        apply_style(&mut spec, style);
+    }
+    return spec;
+}
+
+fn apply_style(spec: &mut termcolor::ColorSpec, style: &settings::TextStyle) {
+    match style {
+        settings::TextStyle::Bold => spec.set_bold(true),
+        settings::TextStyle::Underline => spec.set_underline(true),
+        settings::TextStyle::Italic => spec.set_italic(true),
+        settings::TextStyle::Dimmed => spec.set_dimmed(true),
+        settings::TextStyle::Intense => spec.set_intense(true),
+        settings::TextStyle::Plain => return,
+    };
+}
+
+/// style of the lines of the pod from the value of its split_by label, none for the pods without this label
+fn split_style(pod: &kubernetes::Pod, settings: &settings::SettingsValidated) -> Option<settings::TextStyle> {
+    let key = settings.split_by.as_ref()?;
+    let value = pod.labels(std::slice::from_ref(key)).remove(key)?;
+    let idx = settings.state.split_index(value);
+    return settings.split_styles.get(idx % settings.split_styles.len().max(1)).cloned();
+}
+
+pub async fn print_color(
+    std: &mut (dyn termcolor::WriteColor + Send),
+    color_rgb: Option<colors_transform::Rgb>,
+    message: String,
+) -> Result<(), Errors> {
+    return write_color(std, color_rgb, message);
+}
+
+/// print an error to stderr, in the color of the pod it is about (if any) and the error style
+pub async fn print_error(streams: &mut Streams, color_rgb: Option<colors_transform::Rgb>, message: String) -> Result<(), Errors> {
+    return write_error(&mut streams.err, color_rgb, &streams.error_style, message);
+}
+
+/// blocking version of print_color, used by the writer thread
+pub fn write_color(std: &mut (dyn termcolor::WriteColor + Send), color_rgb: Option<colors_transform::Rgb>, message: String) -> Result<(), Errors> {
+    return write_spec(std, &new_color_spec(color_rgb), message);
+}
+
+/// blocking version of print_error, used by the writer thread
+pub fn write_error(
+    std: &mut (dyn termcolor::WriteColor + Send),
+    color_rgb: Option<colors_transform::Rgb>,
+    error_style: &[settings::TextStyle],
+    message: String,
+) -> Result<(), Errors> {
+    return write_spec(std, &error_color_spec(color_rgb, error_style), message);
+}
+
+fn write_spec(std: &mut (dyn termcolor::WriteColor + Send), color_spec: &termcolor::ColorSpec, message: String) -> Result<(), Errors> {
+    std.set_color(color_spec).map_err(|err| Errors::StdErr(err.to_string()))?;
+    std.write_all(message.as_bytes()).map_err(|err| Errors::StdErr(err.to_string()))?;
+    if message.len() > 0 && !message.ends_with('\n') {
+        std.write_all(b"\n").map_err(|err| Errors::StdErr(err.to_string()))?;
+    }
     Ok(())
 }
 
-// should be nice to use this when ctrl + c
-#[allow(dead_code)]
-pub async fn reset_terminal_colors(streams: &types::ArcMutex<Streams>) -> Result<(), Errors> {
-    let mut streams = streams.lock().await;
-    streams
-        .out
-        .set_color(&termcolor::ColorSpec::default())
-        .map_err(|err| Errors::StdErr(err.to_string()))?;
-    streams
-        .err
-        .set_color(&termcolor::ColorSpec::default())
-        .map_err(|err| Errors::StdErr(err.to_string()))?;
-
-    streams
-        .out
-        .write_fmt(format_args!("bye"))
-        .map_err(|err| Errors::StdErr(err.to_string()))?;
-    streams
-        .err
-        .write_fmt(format_args!("bye"))
-        .map_err(|err| Errors::StdErr(err.to_string()))?;
+/// split the line in (text, is_highlighted) segments, overlapping matches are merged
+fn highlight_segments(line: &str, highlight: &Vec<Regex>) -> Vec<(String, bool)> {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for reg in highlight.iter() {
+        for found in reg.find_iter(line) {
+            if found.start() < found.end() {
+                ranges.push((found.start(), found.end()));
+            }
+        }
+    }
+    ranges.sort();
+    let mut segments = vec![];
+    let mut cursor = 0;
+    for (start, end) in ranges {
+        if end <= cursor {
+            continue;
+        }
+        let start = std::cmp::max(start, cursor);
+        if start > cursor {
+            segments.push((line[cursor..start].to_string(), false));
+        }
+        segments.push((line[start..end].to_string(), true));
+        cursor = end;
+    }
+    if cursor < line.len() {
+        segments.push((line[cursor..].to_string(), false));
+    }
+    return segments;
+}
+
+/// same as write_color but the parts of the line matching one of the highlight regexes are printed in bold and underlined
+pub fn write_color_highlighted(
+    std: &mut (dyn termcolor::WriteColor + Send),
+    color_rgb: Option<colors_transform::Rgb>,
+    prefix: &str,
+    line: &str,
+    highlight: &Vec<Regex>,
+    style: Option<&settings::TextStyle>,
+) -> Result<(), Errors> {
+    let mut color_spec = new_color_spec(color_rgb);
+    if let Some(style) = style {
+        apply_style(&mut color_spec, style);
+    }
+
+    std.set_color(&color_spec).map_err(|err| Errors::StdErr(err.to_string()))?;
+    std.write_all(prefix.as_bytes()).map_err(|err| Errors::StdErr(err.to_string()))?;
+    if highlight.len() == 0 && !line.contains(lineproc::REPLACED_START) {
+        std.write_all(line.as_bytes()).map_err(|err| Errors::StdErr(err.to_string()))?;
+        if !line.ends_with('\n') {
+            std.write_all(b"\n").map_err(|err| Errors::StdErr(err.to_string()))?;
+        }
+        return Ok(());
+    }
+    for (part, is_replaced) in lineproc::replaced_segments(line.trim_end_matches("\n")) {
+        for (segment, is_highlighted) in highlight_segments(part, highlight) {
+            let mut spec = color_spec.clone();
+            if is_highlighted {
+                spec.set_bold(true).set_underline(true);
+            }
+            if is_replaced {
+                spec.set_italic(true).set_intense(true);
+            }
+            std.set_color(&spec).map_err(|err| Errors::StdErr(err.to_string()))?;
+            std.write_fmt(format_args!("{segment}")).map_err(|err| Errors::StdErr(err.to_string()))?;
+        }
+    }
+    std.set_color(&color_spec).map_err(|err| Errors::StdErr(err.to_string()))?;
+    std.write_fmt(format_args!("\n")).map_err(|err| Errors::StdErr(err.to_string()))?;
     Ok(())
 }
 
+/// pause the output, or print the lines buffered while it was paused and resume it
+pub async fn toggle_pause(streams: &types::ArcMutex<Streams>) -> Result<(), Errors> {
+    return streams.lock().await.out.toggle_pause();
+}
+
+/// print only the lines of the given pod until the focus is removed with None
+pub async fn set_focus(streams: &types::ArcMutex<Streams>, pod_id: Option<Arc<str>>) -> Result<(), Errors> {
+    return streams.lock().await.out.set_focus(pod_id);
+}
+
+pub fn print_summary(streams: &mut Streams) {
+    if let Some(summary) = &streams.summary {
+        streams.out.flush();
+        let _ = streams.err.reset();
+        for line in summary.report() {
+            let _ = writeln!(streams.err, "{line}");
+        }
+    }
+}
+
+/// print the pods which would be followed, each one with the color it would get
+/// a row per container with its state, restarts and image, the pod columns are only filled on the row of its first container
+pub async fn print_pod_list(pods: &kubernetes::Pods, streams: &mut Streams) -> Result<(), Errors> {
+    let header = ["NAMESPACE", "NAME", "PHASE", "NODE", "CONTAINER", "STATE", "RESTARTS", "IMAGE", "COLOR"];
+    let mut rows: Vec<(Option<colors_transform::Rgb>, Vec<String>)> = vec![(None, header.iter().map(|title| title.to_string()).collect())];
+    for pod in pods.items.iter() {
+        let color = format!(
+            "#{:02x}{:02x}{:02x}",
+            pod.color.get_red() as u8,
+            pod.color.get_green() as u8,
+            pod.color.get_blue() as u8
+        );
+        let pod_columns = vec![pod.namespace.qualified_name.clone(), pod.name.clone(), pod.phase(), pod.node_name()];
+        for (idx, container) in pod.container_states().iter().enumerate() {
+            let mut row = if idx == 0 {
+                pod_columns.clone()
+            } else {
+                vec![String::new(); pod_columns.len()]
+            };
+            row.extend([
+                container.name.clone(),
+                container.state.clone(),
+                container.restarts.to_string(),
+                container.image.clone(),
+                if idx == 0 { color.clone() } else { String::new() },
+            ]);
+            rows.push((Some(pod.color), row));
+        }
+    }
+    let mut widths = vec![0; header.len()];
+    for (_, row) in rows.iter() {
+        for (idx, column) in row.iter().enumerate() {
+            widths[idx] = widths[idx].max(column.chars().count());
+        }
+    }
+    for (color, row) in rows {
+        let columns: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(column, width)| format!("{column:width$}", width = width))
+            .collect();
+        streams.out.text(color, columns.join("  ").trim_end().to_string())?;
+    }
+    return Ok(());
+}
+
+/// the pods which would be followed as a json document, for the scripts reusing the pod selection
+/// ({"namespaces": [{"context", "name"}], "pods": [{"context", "namespace", "name", "phase", "node", "owner", "workload", "color", "containers"}]})
+pub async fn print_pod_inventory(pods: &kubernetes::Pods, streams: &mut Streams) -> Result<(), Errors> {
+    let namespaces: Vec<serde_json::Value> = pods
+        .namespaces
+        .items
+        .iter()
+        .map(|namespace| serde_json::json!({"context": namespace.context, "name": namespace.name}))
+        .collect();
+    let items: Vec<serde_json::Value> = pods
+        .items
+        .iter()
+        .map(|pod| {
+            let containers: Vec<serde_json::Value> = pod
+                .container_states()
+                .iter()
+                .map(|container| {
+                    return serde_json::json!({
+                        "name": container.name,
+                        "state": container.state,
+                        "restarts": container.restarts,
+                        "image": container.image,
+                    });
+                })
+                .collect();
+            return serde_json::json!({
+                "context": pod.namespace.context,
+                "namespace": pod.namespace.name,
+                "name": pod.name,
+                "phase": pod.phase(),
+                "node": pod.node_name(),
+                "owner": pod.owner(),
+                "workload": pod.workload(),
+                "color": format!("#{:02x}{:02x}{:02x}", pod.color.get_red() as u8, pod.color.get_green() as u8, pod.color.get_blue() as u8),
+                "containers": containers,
+            });
+        })
+        .collect();
+    let inventory = serde_json::json!({"namespaces": namespaces, "pods": items});
+    let rendered = serde_json::to_string_pretty(&inventory).map_err(|err| Errors::Other(err.to_string()))?;
+    return streams.out.text(None, rendered);
+}
+
+/// print the summary, reset terminal colors and wait for the user to quit the pager
+pub fn finish(streams: &mut Streams) {
+    print_summary(streams);
+    reset_terminal_colors(streams);
+    let _ = streams.err.flush();
+    streams.out.finish();
+}
+
+/// so that the terminal is not left in the color of the last pod
+pub fn reset_terminal_colors(streams: &mut Streams) {
+    let _ = streams.out.reset();
+    let _ = streams.err.reset();
+}
+
+/// apply filters and transformations to the line, returns None if the line is filtered out
+pub fn process_log_line(line: String, settings: &settings::SettingsValidated) -> Option<String> {
+    let line = process_log_line_marked(line, settings)?;
+    if !settings.replace_highlight {
+        return Some(line);
+    }
+    return Some(lineproc::strip_replace_markers(&line).into_owned());
+}
+
+/// the line for the sinks with their own filter: processed like the printed lines but without the filters
+fn process_sink_line(line: String, settings: &settings::SettingsValidated) -> Option<String> {
+    let mut line = line;
+    for processor in settings.sink_processors.iter() {
+        line = processor.process(line)?;
+    }
+    return Some(lineproc::strip_replace_markers(&line).into_owned());
+}
+
+/// the line matching the own filter of a sink, or the line kept by the filters for the sinks without one
+fn sink_line<'a>(filter: &Option<Regex>, kept: Option<&'a str>, unfiltered: Option<&'a str>) -> Option<&'a str> {
+    return match filter {
+        Some(filter) => unfiltered.filter(|line| filter.is_match(line)),
+        None => kept,
+    };
+}
+
+/// same as process_log_line but the replaced parts keep their markers, for the terminal output
+fn process_log_line_marked(line: String, settings: &settings::SettingsValidated) -> Option<String> {
+    let mut line = line;
+    for processor in settings.processors.iter() {
+        line = processor.process(line)?;
+    }
+    return Some(line);
+}
+
 pub async fn print_log_line(
-    line: &String,
+    line: String,
     settings: &settings::SettingsValidated,
-    pods: &types::ArcMutex<kubernetes::Pods>,
-    streams: &types::ArcMutex<Streams>,
+    prefix: &Prefix,
+    out: &writer::Writer,
     pod: &kubernetes::Pod,
 ) -> Result<(), Errors> {
-    let mut line = line.clone();
-    if let Some(reg) = &settings.filter {
-        if !reg.is_match(&line) {
-            return Ok(());
+    return match process_log_line_marked(line, settings) {
+        Some(line) => {
+            actions::check_fail_on(settings, &lineproc::strip_replace_markers(&line));
+            print_pod_line(line, settings, prefix, out, pod).await
+        }
+        None => {
+            pod.stats.filtered_lines.fetch_add(1, Ordering::Relaxed);
+            Ok(())
         }
+    };
+}
+
+/// terminal width, None if stdout is not a terminal
+pub fn get_terminal_width() -> Option<usize> {
+    return terminal_size::terminal_size().map(|(width, _)| width.0 as usize);
+}
+
+/// called when the terminal is resized, the next lines are wrapped to the new width
+pub fn terminal_resized(state: &state::RunState) {
+    if let Some(width) = get_terminal_width() {
+        state.set_resized_width(width);
     }
-    if let Some(reg) = &settings.inv_filter {
-        if reg.is_match(&line) {
-            return Ok(());
+}
+
+/// the width the lines are wrapped to: the one of the terminal when it was resized, the one at startup otherwise
+fn current_wrap_width(settings: &settings::SettingsValidated) -> Option<usize> {
+    return settings.wrap_width.map(|width| settings.state.resized_width().unwrap_or(width));
+}
+
+/// wrap the line so that it fits in width once prefixed, continuation lines are indented to the message column
+fn wrap_line(line: &str, width: usize, indent: usize) -> String {
+    let available = width.saturating_sub(indent);
+    if available < MIN_WRAP_WIDTH {
+        return line.to_string();
+    }
+    let indent_str = " ".repeat(indent);
+    let mut wrapped = String::new();
+    for (line_idx, source_line) in line.trim_end_matches("\n").split('\n').enumerate() {
+        if line_idx > 0 {
+            wrapped.push('\n');
+            wrapped.push_str(&indent_str);
         }
+        let chars: Vec<char> = source_line.chars().collect();
+        for (chunk_idx, chunk) in chars.chunks(available).enumerate() {
+            if chunk_idx > 0 {
+                wrapped.push('\n');
+                wrapped.push_str(&indent_str);
+            }
+            wrapped.extend(chunk.iter());
+        }
+    }
+    return wrapped;
+}
+
+/// prefix the lines following the first one according to the continuation mode, then wrap each of them when requested
+fn format_continuation(line: String, prefix: &str, settings: &settings::SettingsValidated) -> String {
+    let indent = prefix.chars().count();
+    let width = current_wrap_width(settings);
+    if settings.continuation == ContinuationMode::None || !line.trim_end_matches("\n").contains('\n') {
+        return match width {
+            Some(width) => wrap_line(&line, width, indent),
+            None => line,
+        };
     }
-    if let Some(replace) = &settings.replace {
-        line = replace.pattern.replace_all(&line, &replace.value).to_string();
+    let mark = match settings.continuation {
+        ContinuationMode::Indent => format!("{}{CONTINUATION_MARK}", " ".repeat(indent)),
+        _ => prefix.to_string(),
+    };
+    let mark_width = mark.chars().count();
+    let lines: Vec<String> = line
+        .trim_end_matches("\n")
+        .split('\n')
+        .enumerate()
+        .map(|(idx, part)| {
+            let part = part.trim_end_matches('\r');
+            let part = match width {
+                Some(width) => wrap_line(part, width, if idx == 0 { indent } else { mark_width }),
+                None => part.to_string(),
+            };
+            return if idx == 0 { part } else { format!("{mark}{part}") };
+        })
+        .collect();
+    return lines.join("\n");
+}
+
+/// how long after its kubernetes timestamp the line was received, a clock ahead of the local one counts as no lag
+fn line_lag(date: chrono::DateTime<chrono::FixedOffset>) -> std::time::Duration {
+    return (chrono::Utc::now() - date.with_timezone(&chrono::Utc)).to_std().unwrap_or_default();
+}
+
+/// label and padding of a pod prefix, with the default (or wide) prefix already rendered
+#[derive(Clone)]
+pub struct Prefix {
+    pod_id: Arc<str>,
+    padding: String,
+    rendered: Arc<str>,
+    /// split style of the pod, see split_by
+    style: Option<settings::TextStyle>,
+}
+
+impl Prefix {
+    pub fn new(pod: &kubernetes::Pod, layout: &kubernetes::PrefixLayout, settings: &settings::SettingsValidated) -> Prefix {
+        let label = pod.prefix_label(layout.print_namespace.load(Ordering::Relaxed), settings.max_prefix_width);
+        let padding = " ".repeat(layout.padding.load(Ordering::Relaxed).saturating_sub(label.chars().count()));
+        let rendered = if settings.output == OutputMode::Wide {
+            format!("{label}:{padding} [{} {}] ", pod.node_name(), pod.pod_ip())
+        } else {
+            format!("{label}:{padding} ")
+        };
+        return Prefix {
+            pod_id: Arc::from(pod.id()),
+            padding,
+            rendered: Arc::from(rendered),
+            style: split_style(pod, settings),
+        };
+    }
+
+    pub fn rendered(&self) -> Arc<str> {
+        return self.rendered.clone();
     }
-    let padding_cnt;
-    let namespace: String;
-    {
-        let pods = pods.lock().await;
-        namespace = match pods.print_namespace {
-            true => {
-                padding_cnt = pods.padding - pod.name.len() - pod.namespace.name.len() + 1;
-                format!("{}/", pod.namespace.name)
+
+    pub fn padding(&self) -> &str {
+        return &self.padding;
+    }
+}
+
+/// prefix of a pod, rendered again only when the pod set changed
+pub struct PrefixCache {
+    layout: Arc<kubernetes::PrefixLayout>,
+    generation: u64,
+    prefix: Prefix,
+}
+
+impl PrefixCache {
+    pub fn new(pod: &kubernetes::Pod, layout: Arc<kubernetes::PrefixLayout>, settings: &settings::SettingsValidated) -> PrefixCache {
+        let generation = layout.generation.load(Ordering::Acquire);
+        let prefix = Prefix::new(pod, &layout, settings);
+        return PrefixCache { layout, generation, prefix };
+    }
+
+    pub fn get(&mut self, pod: &kubernetes::Pod, settings: &settings::SettingsValidated) -> &Prefix {
+        let generation = self.layout.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.generation = generation;
+            self.prefix = Prefix::new(pod, &self.layout, settings);
+        }
+        return &self.prefix;
+    }
+}
+
+/// print the line prefixed by the pod name
+pub async fn print_pod_line(
+    line: String,
+    settings: &settings::SettingsValidated,
+    pod_prefix: &Prefix,
+    out: &writer::Writer,
+    pod: &kubernetes::Pod,
+) -> Result<(), Errors> {
+    let (prefix, line) = match settings.encoder.encode(pod, pod_prefix, line) {
+        encoder::Encoded::Record(record) => {
+            metrics::add(&metrics::OUTPUT_BYTES, record.len() + 1);
+            return out.record(pod.stats.clone(), record);
+        }
+        encoder::Encoded::Colored { prefix, line } => (prefix, line),
+    };
+    let line = if settings.line_numbers {
+        format!("{} {line}", pod.next_line_number())
+    } else {
+        line
+    };
+    let line = format_continuation(line, &prefix, settings);
+    metrics::add(&metrics::OUTPUT_BYTES, prefix.len() + line.len() + 1);
+    return out.line(
+        pod.stats.clone(),
+        pod_prefix.pod_id.clone(),
+        pod.color,
+        pod_prefix.style.clone(),
+        prefix,
+        line,
+    );
+}
+
+/// printing state of a single pod log stream
+pub struct PodPrinter<'a> {
+    pod: &'a kubernetes::Pod,
+    settings: &'a settings::SettingsValidated,
+    streams: &'a types::ArcMutex<Streams>,
+    /// cloned once so that printing a line does not lock the streams
+    out: writer::Writer,
+    prefix: PrefixCache,
+    dedup: Option<lineproc::Dedup>,
+    sampler: Option<lineproc::Sampler>,
+    printed: u64,
+    matched: u64,
+    pod_file: Option<Arc<std::sync::Mutex<sinks::PodFile>>>,
+    recorder: Option<Arc<replay::Recorder>>,
+    /// the replay buffer and the pod shared by its entries
+    replay: Option<(Arc<std::sync::Mutex<ReplayBuffer>>, Arc<kubernetes::Pod>)>,
+    last_line: tokio::time::Instant,
+    idle_warned: bool,
+    lag_warned: bool,
+    /// date of the last line checkpointed, so that printing a line does not lock the pods
+    last_seen: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+impl<'a> PodPrinter<'a> {
+    pub async fn new(
+        pod: &'a kubernetes::Pod,
+        settings: &'a settings::SettingsValidated,
+        pods: &'a types::ArcMutex<kubernetes::Pods>,
+        streams: &'a types::ArcMutex<Streams>,
+    ) -> Result<PodPrinter<'a>, Errors> {
+        let pod_file = match streams.lock().await.output_files.clone() {
+            Some(output_files) => Some(output_files.open(pod)?),
+            None => None,
+        };
+        let replay = streams
+            .lock()
+            .await
+            .replay_buffer
+            .clone()
+            .map(|replay_buffer| (replay_buffer, Arc::new(pod.clone())));
+        let (layout, last_seen) = {
+            let mut pods = pods.lock().await;
+            pods.merge_checkpoint();
+            let last_seen = pods.checkpoint.as_ref().and_then(|checkpoint| checkpoint.last_seen(&pod.id()));
+            (pods.layout.clone(), last_seen)
+        };
+        return Ok(PodPrinter {
+            pod,
+            settings,
+            streams,
+            out: streams.lock().await.out.clone(),
+            prefix: PrefixCache::new(pod, layout, settings),
+            dedup: if settings.dedup { Some(lineproc::Dedup::new()) } else { None },
+            sampler: settings.sample.map(lineproc::Sampler::new),
+            printed: 0,
+            matched: 0,
+            pod_file,
+            recorder: streams.lock().await.recorder.clone(),
+            replay,
+            last_line: tokio::time::Instant::now(),
+            idle_warned: false,
+            lag_warned: false,
+            last_seen,
+        });
+    }
+
+    /// true if some state must be flushed or checked periodically when the stream is idle
+    pub fn needs_flush(&self) -> bool {
+        return self.dedup.is_some() || self.settings.warn_idle.is_some();
+    }
+
+    /// warn once when no line was received for the warn_idle duration
+    pub async fn check_idle(&mut self) -> Result<(), Errors> {
+        if let Some(warn_idle) = self.settings.warn_idle {
+            let idle = self.last_line.elapsed();
+            if !self.idle_warned && idle >= warn_idle {
+                self.idle_warned = true;
+                let mut streams = self.streams.lock().await;
+                let message = format!("... {} idle for {}s", self.pod.id(), idle.as_secs());
+                print_status(&mut streams, Some(self.pod.color), message).await?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// warn once when the lines are received warn_lag after their timestamp, then once they are on time again
+    async fn check_lag(&mut self, lag: std::time::Duration, warn_lag: std::time::Duration) -> Result<(), Errors> {
+        let message = if !self.lag_warned && lag >= warn_lag {
+            format!("... {} lagging, lines received {}s after their timestamp", self.pod.id(), lag.as_secs())
+        } else if self.lag_warned && lag < warn_lag {
+            format!("... {} on time again", self.pod.id())
+        } else {
+            return Ok(());
+        };
+        self.lag_warned = !self.lag_warned;
+        let mut streams = self.streams.lock().await;
+        return print_status(&mut streams, Some(self.pod.color), message).await;
+    }
+
+    /// kept is the line kept by the filters if it was, unfiltered the line for the sinks with their own filter
+    async fn send_to_sinks(&self, date: Option<chrono::DateTime<chrono::FixedOffset>>, kept: Option<&str>, unfiltered: Option<&str>) {
+        if let Some(line) = sink_line(&self.settings.sink_http_filter, kept, unfiltered).filter(|_| self.settings.sink_http.is_some()) {
+            let mut streams = self.streams.lock().await;
+            let full_batch = streams.http_sink.as_mut().and_then(|http_sink| {
+                return http_sink
+                    .push(sinks::sink_record(self.pod, line, &self.settings.sink_labels))
+                    .map(|batch| (http_sink.target.clone(), batch));
+            });
+            if let Some((target, batch)) = full_batch {
+                tokio::spawn(sinks::send_http_batch(target, batch, self.streams.clone()));
+            }
+        }
+        if let Some(line) = sink_line(&self.settings.sink_syslog_filter, kept, unfiltered).filter(|_| self.settings.sink_syslog.is_some()) {
+            let streams = self.streams.lock().await;
+            if let Some(syslog_sink) = &streams.syslog_sink {
+                // the receiver only stops when the forwarding task failled, which was already reported
+                let _ = syslog_sink.send(sinks::syslog_message(self.pod, line, &self.settings.sink_labels));
+            }
+        }
+        if let Some(line) = sink_line(&self.settings.sink_sqlite_filter, kept, unfiltered).filter(|_| self.settings.sink_sqlite.is_some()) {
+            let streams = self.streams.lock().await;
+            if let Some(sqlite_sink) = &streams.sqlite_sink {
+                // same as syslog, the writing thread only stops on an error which was already reported
+                let _ = sqlite_sink.send(capture::CapturedLine::new(self.pod, date, line, &self.settings.sink_labels));
             }
-            false => {
-                padding_cnt = pods.padding - pod.name.len();
-                "".to_string()
+        }
+    }
+
+    pub async fn print(&mut self, line: String) -> Result<(), Errors> {
+        self.pod.stats.record_received(&line);
+        metrics::inc(&metrics::LINES_RECEIVED);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(self.pod, &line)?;
+        }
+        if self.idle_warned {
+            self.idle_warned = false;
+            let mut streams = self.streams.lock().await;
+            let message = format!("... {} resumed after {}s", self.pod.id(), self.last_line.elapsed().as_secs());
+            print_status(&mut streams, Some(self.pod.color), message).await?;
+        }
+        self.last_line = tokio::time::Instant::now();
+        // when ordering, checkpointing, continuing the previous lines or measuring the lag, timestamps are always requested and only kept if asked
+        let (date, line) = if self.settings.live_timestamps() {
+            let (timestamp, content) = lineproc::split_timestamp(&line);
+            let date = timestamp.and_then(|timestamp| chrono::DateTime::parse_from_rfc3339(timestamp).ok());
+            let content_start = line.len() - content.len();
+            let mut line = line;
+            if !self.settings.timestamps {
+                line.replace_range(..content_start, "");
             }
+            (date, line)
+        } else {
+            (None, line)
         };
+        // the stream starts at the second of the last previous line, the lines up to it were already printed
+        if date.is_some_and(|date| self.pod.history_end().is_some_and(|history_end| date <= history_end)) {
+            return Ok(());
+        }
+        let lag = date.map(line_lag);
+        if let (Some(lag), Some(warn_lag)) = (lag, self.settings.warn_lag) {
+            self.check_lag(lag, warn_lag).await?;
+        }
+        if let (Some(date), Some(_)) = (date, &self.settings.state_file) {
+            // the lines up to the last one seen by a previous invocation or a previous stream of the pod were already printed
+            if self.last_seen.is_some_and(|last_seen| date <= last_seen) {
+                return Ok(());
+            }
+            self.last_seen = Some(date);
+            self.pod.record_last_seen(date);
+        }
+        if let Some(pod_file) = &self.pod_file {
+            let raw_line = lineproc::redact_raw(&self.settings.redactor, &line);
+            if self.settings.sink_file_filter.as_ref().map_or(true, |filter| filter.is_match(&raw_line)) {
+                let mut pod_file = pod_file.lock().map_err(|err| Errors::Other(format!("writing output file: {err}")))?;
+                pod_file.write_line(&raw_line)?;
+            }
+        }
+        if let Some((replay_buffer, pod)) = &self.replay {
+            let entry = ReplayEntry {
+                line: line.clone(),
+                pod: pod.clone(),
+                prefix: self.prefix.get(self.pod, self.settings).clone(),
+            };
+            replay_buffer
+                .lock()
+                .map_err(|err| Errors::Other(format!("writing replay buffer: {err}")))?
+                .push(entry);
+        }
+        // the sinks with their own filter branch off before the filters
+        let unfiltered = if self.settings.has_sink_filters() {
+            process_sink_line(line.clone(), self.settings)
+        } else {
+            None
+        };
+        // sinks, actions and dedup work on the line without the replace markers, only the printed line keeps them
+        let (kept, marked) = match process_log_line_marked(line, self.settings) {
+            Some(marked) if self.settings.replace_highlight => (Some(lineproc::strip_replace_markers(&marked).into_owned()), Some(marked)),
+            kept => (kept, None),
+        };
+        self.send_to_sinks(date, kept.as_deref(), unfiltered.as_deref()).await;
+        let Some(line) = kept else {
+            metrics::inc(&metrics::LINES_FILTERED);
+            self.pod.stats.filtered_lines.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            return Ok(());
+        };
+        self.matched += 1;
+        self.pod.stats.matched_lines.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        actions::check_fail_on(self.settings, &line);
+        if let Some(on_match) = &self.settings.on_match {
+            if on_match.pattern.is_match(&line) {
+                actions::exec_on_match(&on_match.command, self.pod, &line, self.streams);
+            }
+        }
+        if let Some(notify) = &self.settings.notify {
+            if notify.is_match(&line) {
+                actions::notify(&self.settings.notify_mode, self.pod, &line, self.streams).await?;
+            }
+        }
+        if let Some(dedup) = &mut self.dedup {
+            // the timestamps kept with --timestamps or by the structured outputs differ on every line
+            if dedup.is_repeated(lineproc::split_timestamp(&line).1) {
+                return Ok(());
+            }
+            if let Some(repeated) = dedup.take_repeated() {
+                self.print_repeated(repeated).await?;
+            }
+        }
+        let mut line = marked.unwrap_or(line);
+        let aggregate_key = self.settings.aggregate.map(|_| aggregate_key(&line, &self.settings.aggregate_pattern));
+        if let Some(sampler) = &mut self.sampler {
+            match sampler.keep() {
+                Some(0) => {}
+                Some(skipped) => line = format!("[{skipped} skipped] {line}"),
+                None => return Ok(()),
+            }
+        }
+        if let Some(lag) = lag.filter(|_| self.settings.show_lag) {
+            line = format!("[+{:.1}s] {line}", lag.as_secs_f64());
+        }
+        if let Some(aggregate_key) = aggregate_key {
+            let mut streams = self.streams.lock().await;
+            let prefix = self.prefix.get(self.pod, self.settings);
+            let entry = streams.aggregate_buffer.entry(aggregate_key).or_insert_with(|| AggregateEntry {
+                received: tokio::time::Instant::now(),
+                line,
+                pod: self.pod.clone(),
+                prefix: prefix.clone(),
+                pods: vec![],
+                count: 0,
+            });
+            entry.count += 1;
+            if !entry.pods.contains(&self.pod.display_name) {
+                entry.pods.push(self.pod.display_name.clone());
+            }
+        } else {
+            match date.filter(|_| self.settings.ordered.is_some()) {
+                Some(date) => {
+                    let mut streams = self.streams.lock().await;
+                    streams.reorder_buffer.push(ReorderEntry {
+                        received: tokio::time::Instant::now(),
+                        date,
+                        line,
+                        pod: self.pod.clone(),
+                        prefix: self.prefix.get(self.pod, self.settings).clone(),
+                    });
+                }
+                None => print_pod_line(line, self.settings, self.prefix.get(self.pod, self.settings), &self.out, self.pod).await?,
+            }
+        }
+        self.printed += 1;
+        self.pod.stats.printed_lines.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        return Ok(());
     }
-    let padding_str = " ".repeat(padding_cnt);
-    let message = format!("{namespace}{}:{padding_str} {line}", &pod.name);
-    {
-        let mut streams = streams.lock().await;
-        let stdout = &mut streams.out;
-        print_color(stdout, Some(pod.color), message).await?;
+
+    /// returns the max lines setting if this many lines were printed
+    pub fn max_lines_reached(&self) -> Option<u64> {
+        return self.settings.max_lines.filter(|max_lines| self.printed >= *max_lines);
+    }
+
+    /// returns the max matches setting if this many lines matched the filters
+    pub fn max_matches_reached(&self) -> Option<u64> {
+        return self.settings.max_matches.filter(|max_matches| self.matched >= *max_matches);
+    }
+
+    pub async fn flush(&mut self) -> Result<(), Errors> {
+        if let Some(repeated) = self.dedup.as_mut().and_then(|dedup| dedup.take_repeated()) {
+            self.print_repeated(repeated).await?;
+        }
+        return Ok(());
+    }
+
+    async fn print_repeated(&mut self, repeated: usize) -> Result<(), Errors> {
+        let message = format!("last line repeated {repeated} times");
+        return print_pod_line(message, self.settings, self.prefix.get(self.pod, self.settings), &self.out, self.pod).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_colors(pod_cnt: usize) -> Colors {
+        let hue_intervals: Vec<Hsl> = (0..360)
+            .map(|value| Hsl {
+                h: Hue { value },
+                s: Saturation { value: 80 },
+                l: Lightness { value: 50 },
+            })
+            .collect();
+        let mut colors_params = ColorParams {
+            pod_cnt: pod_cnt as u8,
+            hue_intervals,
+            color_map: vec![],
+            state: ColorGeneratorState {
+                step: 0,
+                offset: 0,
+                pod_cnt: pod_cnt as u16,
+                hue_count: 360,
+                hue_generated: HashSet::new(),
+            },
+        };
+        return Colors::new(&mut colors_params);
+    }
+
+    #[test]
+    fn allocated_colors_are_distinct() {
+        let mut colors = new_colors(3);
+        let allocated: Vec<_> = (0..6).map(|_| colors.get_new_color().as_tuple()).collect();
+        for (idx, color) in allocated.iter().enumerate() {
+            assert!(!allocated[idx + 1..].contains(color), "color {color:?} allocated twice");
+        }
+    }
+
+    #[test]
+    fn released_colors_are_reused_oldest_first() {
+        let mut colors = new_colors(3);
+        let (first, second, third) = (colors.get_new_color(), colors.get_new_color(), colors.get_new_color());
+        colors.set_color_to_unused(second);
+        colors.set_color_to_unused(first);
+        assert_eq!(colors.get_new_color().as_tuple(), second.as_tuple());
+        assert_eq!(colors.get_new_color().as_tuple(), first.as_tuple());
+        assert_ne!(colors.get_new_color().as_tuple(), third.as_tuple());
+    }
+
+    #[test]
+    fn double_release_is_ignored() {
+        let mut colors = new_colors(2);
+        let (first, _) = (colors.get_new_color(), colors.get_new_color());
+        colors.set_color_to_unused(first);
+        colors.set_color_to_unused(first);
+        assert_eq!(colors.get_new_color().as_tuple(), first.as_tuple());
+        assert_ne!(colors.get_new_color().as_tuple(), first.as_tuple());
+    }
+
+    #[test]
+    fn shared_color_is_kept_until_its_last_pod_is_released() {
+        let mut colors = new_colors(1);
+        let shared = colors.get_new_color();
+        // a previous color given again to a second pod of the same workload
+        colors.used.push(shared);
+        colors.set_color_to_unused(shared);
+        assert!(colors.is_used(&shared));
+        assert_ne!(colors.get_new_color().as_tuple(), shared.as_tuple());
+        colors.set_color_to_unused(shared);
+        assert!(!colors.is_used(&shared));
+        assert_eq!(colors.get_new_color().as_tuple(), shared.as_tuple());
     }
-    return Ok(());
 }
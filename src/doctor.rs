@@ -0,0 +1,142 @@
+use std::io::IsTerminal;
+
+use crate::error::Errors;
+use crate::{config, kubernetes, settings};
+
+/// result of a check of the doctor command, with how to fix it when it failled
+enum Check {
+    Passed(String),
+    Warning(String, &'static str),
+    Failled(String, &'static str),
+}
+
+fn print_check(name: &str, check: &Check) {
+    match check {
+        Check::Passed(detail) => println!("✓ {name}: {detail}"),
+        Check::Warning(detail, hint) => println!("! {name}: {detail}\n    → {hint}"),
+        Check::Failled(detail, hint) => println!("✗ {name}: {detail}\n    → {hint}"),
+    }
+}
+
+fn check_config(settings: &settings::Settings) -> Check {
+    let Some(config_path) = config::resolve_config_path(settings.config.as_ref()) else {
+        return Check::Passed("no config file".to_string());
+    };
+    return match settings::Settings::check_config(&config_path) {
+        Ok(_) => Check::Passed(format!("{} is valid", config_path.display())),
+        Err(err) => Check::Failled(
+            format!("{}: {err}", config_path.display()),
+            "the keys are the long argument names, fix or remove the invalid ones (the checks below ran without the file)",
+        ),
+    };
+}
+
+/// stdout is printed with colors in the default output, whatever it is
+fn check_terminal() -> Check {
+    if !std::io::stdout().is_terminal() {
+        return Check::Warning(
+            "stdout is not a terminal, the colors are still written as ansi codes".to_string(),
+            "use --output raw, json, logfmt or csv when the output is read by another program",
+        );
+    }
+    if std::env::var("TERM").is_ok_and(|term| term == "dumb") {
+        return Check::Warning(
+            "TERM is dumb, the colors are probably not displayed".to_string(),
+            "set TERM to a color terminal (eg: xterm-256color)",
+        );
+    }
+    return Check::Passed("stdout is a terminal supporting colors".to_string());
+}
+
+/// print a checklist of what is needed to follow the logs and exit with an error if a check failled,
+/// the cluster checks stop at the first one failling as the next ones depend on it
+pub async fn run(settings: settings::Settings) -> Result<(), Errors> {
+    let mut checks = vec![("config file", check_config(&settings)), ("terminal", check_terminal())];
+    let validated = match settings.to_validated() {
+        Ok(validated) => Some(validated),
+        Err(err) => {
+            checks.push((
+                "settings",
+                Check::Failled(err.to_string(), "fix the invalid argument, environment variable or config key"),
+            ));
+            None
+        }
+    };
+    if let Some(validated) = validated {
+        for (name, check) in check_clusters(&validated).await {
+            checks.push((name, check));
+        }
+    }
+    let mut failled = 0;
+    for (name, check) in checks.iter() {
+        print_check(name, check);
+        if let Check::Failled(_, _) = check {
+            failled += 1;
+        }
+    }
+    if failled > 0 {
+        return Err(Errors::Validation(format!("{failled} checks failled")));
+    }
+    return Ok(());
+}
+
+async fn check_clusters(settings: &settings::SettingsValidated) -> Vec<(&'static str, Check)> {
+    let mut checks = vec![];
+    let clients = match kubernetes::new_clients(settings).await {
+        Ok(clients) => {
+            let contexts: Vec<String> = clients.iter().filter_map(|client| client.context.clone()).collect();
+            let detail = match contexts.len() {
+                0 => "parsed, using the current context".to_string(),
+                _ => format!("parsed, using the contexts {}", contexts.join(", ")),
+            };
+            checks.push(("kubeconfig", Check::Passed(detail)));
+            clients
+        }
+        Err(err) => {
+            checks.push((
+                "kubeconfig",
+                Check::Failled(
+                    err.to_string(),
+                    "check --kubeconfig or KUBECONFIG, and that kubectl config current-context is set",
+                ),
+            ));
+            return checks;
+        }
+    };
+    let mut connected = true;
+    for (context, version) in kubernetes::server_versions(&clients).await {
+        let label = context.map_or("cluster".to_string(), |context| format!("cluster {context}"));
+        checks.push((
+            "connection",
+            match version {
+                Ok((version, true)) => Check::Passed(format!("{label} answered, version {version}")),
+                Ok((version, false)) => Check::Warning(
+                    format!("{label} answered, version {version} is outside of the supported versions"),
+                    "the streams may drop in odd ways, use a build for this kubernetes version",
+                ),
+                Err(err) => {
+                    connected = false;
+                    Check::Failled(
+                        format!("{label}: {err}"),
+                        "check the cluster is reachable (vpn, proxy) and the credentials are valid: kubectl get --raw /version",
+                    )
+                }
+            },
+        ));
+    }
+    if !connected {
+        return checks;
+    }
+    let namespaces = kubernetes::Namespaces::new(&clients, settings);
+    checks.push((
+        "permissions",
+        match namespaces.check_permissions().await {
+            Ok(_) => Check::Passed("the pods can be listed and their logs read".to_string()),
+            Err(err) => Check::Failled(
+                err.to_string(),
+                "ask for a role granting list on pods and get on pods/log in these namespaces",
+            ),
+        },
+    ));
+    return checks;
+}
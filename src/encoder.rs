@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use crate::display::{OutputMode, Prefix, Template, TemplateValues};
+use crate::error::Errors;
+use crate::{kubernetes, lineproc, sinks};
+
+/// a log line once encoded for the output
+pub enum Encoded {
+    /// written after its prefix in the color of the pod, line numbers and wrapping are applied to it
+    Colored { prefix: Arc<str>, line: String },
+    /// written as is, without color
+    Record(String),
+}
+
+/// turns the log lines of the pods into output lines, one implementation per output format chosen with --output
+pub trait Encoder: Send + Sync {
+    /// printed once before the first line, eg: the csv header
+    fn header(&self) -> Option<String> {
+        return None;
+    }
+
+    fn encode(&self, pod: &kubernetes::Pod, prefix: &Prefix, line: String) -> Encoded;
+}
+
+/// the encoder of the output setting, the template alone keeps selecting the template encoder as before it was an output
+pub fn new_encoder(output: &OutputMode, template: &Option<Template>) -> Result<Arc<dyn Encoder>, Errors> {
+    return Ok(match (output, template) {
+        (OutputMode::Template, None) => return Err(Errors::Validation("the template output needs a template".to_string())),
+        (OutputMode::Template | OutputMode::Default, Some(template)) => Arc::new(TemplateEncoder { template: template.clone() }),
+        (OutputMode::Default | OutputMode::Wide, _) => Arc::new(HumanEncoder {}),
+        (OutputMode::Json, _) => Arc::new(JsonEncoder {}),
+        (OutputMode::Raw, _) => Arc::new(RawEncoder {}),
+        (OutputMode::Logfmt, _) => Arc::new(LogfmtEncoder {}),
+        (OutputMode::Csv, _) => Arc::new(CsvEncoder {}),
+    });
+}
+
+/// the pod prefix then the line, the prefix holds the node and the pod ip in the wide output
+struct HumanEncoder {}
+
+impl Encoder for HumanEncoder {
+    fn encode(&self, _pod: &kubernetes::Pod, prefix: &Prefix, line: String) -> Encoded {
+        return Encoded::Colored {
+            prefix: prefix.rendered(),
+            line,
+        };
+    }
+}
+
+struct TemplateEncoder {
+    template: Template,
+}
+
+impl Encoder for TemplateEncoder {
+    fn encode(&self, pod: &kubernetes::Pod, prefix: &Prefix, line: String) -> Encoded {
+        let (timestamp, message) = lineproc::split_timestamp(&line);
+        let (prefix, line) = self.template.render(&TemplateValues {
+            namespace: &pod.namespace.name,
+            pod: &pod.display_name,
+            container: &pod.default_container(),
+            node: &pod.node_name(),
+            timestamp: timestamp.unwrap_or(""),
+            message,
+            padding: prefix.padding(),
+        });
+        return Encoded::Colored {
+            prefix: Arc::from(prefix),
+            line,
+        };
+    }
+}
+
+struct JsonEncoder {}
+
+impl Encoder for JsonEncoder {
+    fn encode(&self, pod: &kubernetes::Pod, _prefix: &Prefix, line: String) -> Encoded {
+        return Encoded::Record(sinks::json_record(pod, &lineproc::strip_replace_markers(&line)).to_string());
+    }
+}
+
+struct RawEncoder {}
+
+impl Encoder for RawEncoder {
+    fn encode(&self, _pod: &kubernetes::Pod, _prefix: &Prefix, line: String) -> Encoded {
+        return Encoded::Record(lineproc::strip_replace_markers(&line).into_owned());
+    }
+}
+
+/// the fields of the json output, in the same order
+fn record_fields(pod: &kubernetes::Pod, line: &str) -> Vec<(&'static str, String)> {
+    let (timestamp, message) = lineproc::split_timestamp(line);
+    let mut fields = vec![];
+    if let Some(context) = &pod.namespace.context {
+        fields.push(("context", context.clone()));
+    }
+    fields.push(("ns", pod.namespace.name.clone()));
+    fields.push(("pod", pod.name.clone()));
+    fields.push(("container", pod.default_container()));
+    fields.push(("ts", timestamp.unwrap_or("").to_string()));
+    fields.push(("msg", message.trim_end_matches("\n").to_string()));
+    return fields;
+}
+
+/// key=value pairs, the values with spaces, quotes or equal signs are quoted
+struct LogfmtEncoder {}
+
+impl Encoder for LogfmtEncoder {
+    fn encode(&self, pod: &kubernetes::Pod, _prefix: &Prefix, line: String) -> Encoded {
+        let fields = record_fields(pod, &lineproc::strip_replace_markers(&line));
+        let pairs: Vec<String> = fields
+            .into_iter()
+            .map(|(key, value)| {
+                if value.len() == 0 || value.contains(|char: char| char.is_whitespace() || char == '"' || char == '=') {
+                    return format!("{key}=\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"));
+                }
+                return format!("{key}={value}");
+            })
+            .collect();
+        return Encoded::Record(pairs.join(" "));
+    }
+}
+
+/// one row per line with the ts, ns, pod, container and msg columns, the ns is prefixed by the context when there is one
+struct CsvEncoder {}
+
+impl Encoder for CsvEncoder {
+    fn header(&self) -> Option<String> {
+        return Some("ts,ns,pod,container,msg".to_string());
+    }
+
+    fn encode(&self, pod: &kubernetes::Pod, _prefix: &Prefix, line: String) -> Encoded {
+        let fields = record_fields(pod, &lineproc::strip_replace_markers(&line));
+        let columns: Vec<String> = ["ts", "ns", "pod", "container", "msg"]
+            .iter()
+            .map(|column| {
+                let value = fields.iter().find(|(key, _)| key == column).map_or("", |(_, value)| value.as_str());
+                let value = match (*column, &pod.namespace.context) {
+                    ("ns", Some(context)) => format!("{context}/{value}"),
+                    _ => value.to_string(),
+                };
+                if value.contains(|char: char| char == ',' || char == '"' || char == '\n' || char == '\r') {
+                    return format!("\"{}\"", value.replace('"', "\"\""));
+                }
+                return value;
+            })
+            .collect();
+        return Encoded::Record(columns.join(","));
+    }
+}
@@ -1,19 +1,170 @@
+use std::io::Write;
+
+use clap::ValueEnum;
 use thiserror::Error;
 
+/// exit codes of the program, 0 when the streams ended normally
+pub const EXIT_ERROR: i32 = 1;
+/// a line matched the fail_on pattern
+pub const EXIT_FAIL_ON: i32 = 2;
+/// invalid arguments, config file, rules file or kubeconfig
+pub const EXIT_CONFIG: i32 = 3;
+/// the api refused the credentials
+pub const EXIT_AUTH: i32 = 4;
+/// no pod matched the search while nothing would be waited for
+pub const EXIT_NO_POD: i32 = 5;
+/// listing pods or streaming their logs failled
+pub const EXIT_STREAM: i32 = 6;
+/// a job followed with the job setting failed
+pub const EXIT_JOB_FAILED: i32 = 7;
+/// stopped by ctrl + c or SIGTERM
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// the messages of the errors are sanitized, the api errors may echo urls or headers holding credentials
 #[derive(Error, Debug)]
 pub enum Errors {
-    #[error("`{0}`")]
+    #[error("`{}`", sanitize(.0))]
     Validation(String),
 
-    #[error("`{0}`")]
+    #[error("`{}`", sanitize(.0))]
     LogError(String),
 
-    #[error("`{0}`")]
+    #[error("`{}`", sanitize(.0))]
     StdErr(String),
 
-    #[error("api failled for `{0}`, detail: `{1}`")]
-    Kubernetes(String, String),
+    #[error("api failled for `{0}`, detail: `{}`", sanitize(&.1.to_string()))]
+    Kubernetes(String, #[source] kube::Error),
+
+    #[error("reading failled for `{0}`, detail: `{}`", sanitize(&.1.to_string()))]
+    Io(String, #[source] std::io::Error),
+
+    #[error("kubernetes configuration failled for `{0}`, detail: `{}`", sanitize(.1))]
+    KubeConfig(String, String),
+
+    #[error("missing permissions: `{0}`")]
+    Forbidden(String),
+
+    #[error("no pod matched `{0}`")]
+    NoPodMatched(String),
 
-    #[error("`{0}`")]
+    #[error("job failled: `{0}`")]
+    JobFailed(String),
+
+    #[error("`{}`", sanitize(.0))]
     Other(String),
 }
+
+/// mask bearer tokens, authorization headers, basic auth credentials of urls and token query parameters
+pub fn sanitize(text: &str) -> String {
+    static PATTERNS: std::sync::OnceLock<Vec<(regex::Regex, &str)>> = std::sync::OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        return vec![
+            (
+                regex::Regex::new(r"(?i)(authorization[\x22']?\s*[:=]\s*[\x22']?)(?:bearer|basic)?\s*[^\s\x22',]+").unwrap(),
+                "${1}***",
+            ),
+            (regex::Regex::new(r"(?i)\bbearer\s+[A-Za-z0-9\-._~+/]+=*").unwrap(), "Bearer ***"),
+            (regex::Regex::new(r"(?i)\bbasic\s+[A-Za-z0-9+/]{8,}=*").unwrap(), "Basic ***"),
+            (
+                regex::Regex::new(r"(?i)\b([a-z][a-z0-9+.-]*://)[^/\s:@]+:[^/\s@]+@").unwrap(),
+                "${1}***:***@",
+            ),
+            (
+                regex::Regex::new(r"(?i)([?&](?:access_token|token|password|secret|api_key)=)[^&\s]+").unwrap(),
+                "${1}***",
+            ),
+        ];
+    });
+    let mut text = text.to_string();
+    for (pattern, mask) in patterns.iter() {
+        text = pattern.replace_all(&text, *mask).into_owned();
+    }
+    return text;
+}
+
+/// stderr writer of the diagnostics, each formatted event is sanitized before being written
+pub struct SanitizedStderr;
+
+impl std::io::Write for SanitizedStderr {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::io::stderr().write_all(sanitize(&String::from_utf8_lossy(buf)).as_bytes())?;
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return std::io::stderr().flush();
+    }
+}
+
+impl Errors {
+    fn is_auth(&self) -> bool {
+        return match self {
+            Errors::Kubernetes(_, kube::Error::Api(response)) => response.code == 401 || response.code == 403,
+            Errors::Kubernetes(_, kube::Error::Auth(_)) => true,
+            Errors::Forbidden(_) => true,
+            _ => false,
+        };
+    }
+
+    /// whether doing the same request again later may succeed: throttling, server errors and connection failures
+    pub fn is_retryable(&self) -> bool {
+        return match self {
+            Errors::Kubernetes(_, kube::Error::Api(response)) => [408, 429, 500, 502, 503, 504].contains(&response.code),
+            Errors::Kubernetes(_, kube::Error::HyperError(_) | kube::Error::Service(_) | kube::Error::ReadEvents(_)) => true,
+            // the stream was opened, failing to read it means the connection was lost
+            Errors::Io(_, _) => true,
+            _ => false,
+        };
+    }
+
+    /// exit code of the program when this error stops it
+    pub fn exit_code(&self) -> i32 {
+        if self.is_auth() {
+            return EXIT_AUTH;
+        }
+        return match self {
+            Errors::Validation(_) | Errors::KubeConfig(_, _) => EXIT_CONFIG,
+            Errors::Kubernetes(_, _) | Errors::Io(_, _) | Errors::LogError(_) => EXIT_STREAM,
+            Errors::Forbidden(_) => EXIT_AUTH,
+            Errors::NoPodMatched(_) => EXIT_NO_POD,
+            Errors::JobFailed(_) => EXIT_JOB_FAILED,
+            Errors::StdErr(_) | Errors::Other(_) => EXIT_ERROR,
+        };
+    }
+
+    /// name of the error category in json errors
+    pub fn kind(&self) -> &'static str {
+        if self.is_auth() {
+            return "auth";
+        }
+        return match self {
+            Errors::Validation(_) | Errors::KubeConfig(_, _) => "config",
+            Errors::Kubernetes(_, _) | Errors::Io(_, _) | Errors::LogError(_) => "stream",
+            Errors::Forbidden(_) => "auth",
+            Errors::NoPodMatched(_) => "no_pod",
+            Errors::JobFailed(_) => "job_failed",
+            Errors::StdErr(_) | Errors::Other(_) => "other",
+        };
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+/// print the error which stops the program to stderr
+pub fn report(err: &Errors, format: &ErrorFormat) {
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {err}"),
+        ErrorFormat::Json => eprintln!(
+            "{}",
+            serde_json::json!({
+                "error": err.kind(),
+                "message": err.to_string(),
+                "exit_code": err.exit_code(),
+            })
+        ),
+    }
+}
@@ -1,84 +1,808 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
 use colors_transform::Rgb;
-use futures::{AsyncBufReadExt, TryStreamExt};
-use k8s_openapi::api::core::v1::Pod as ApiPod;
-use kube::api::ListParams;
+use futures::AsyncBufReadExt;
+use k8s_openapi::api::apps::v1::ReplicaSet;
+use k8s_openapi::api::authorization::v1::{ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec};
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{Namespace as ApiNamespace, Pod as ApiPod};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{ListParams, ObjectList, PartialObjectMeta};
 use kube::config::{KubeConfigOptions, Kubeconfig};
 use kube::{Api, Client, Config};
 use regex::Regex;
 
+use crate::checkpoint::Checkpoint;
 use crate::error::Errors;
-use crate::{display, settings, types};
+use crate::retry::RetryPolicy;
+use crate::{cache, display, lineproc, metrics, settings, source, stats, types};
+
+/// delay after which pending multiline blocks and repeated lines counts are printed if no new line arrived
+const FLUSH_DELAY: tokio::time::Duration = tokio::time::Duration::from_millis(500);
+
+/// delay between two writes of the state file
+const CHECKPOINT_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(1);
+
+/// pods annotated with it set to "true" are never followed, unless respect_skip_annotation is disabled
+pub const SKIP_ANNOTATION: &str = "rusty-stern.io/skip";
 
 fn get_pod_name(pod: &ApiPod) -> String {
     return pod.metadata.name.clone().unwrap_or("NO_NAME".to_string());
 }
 
+/// strip the replicaset hash from deployment pod names: api-7f9c6d4b8-x2j4q => api-x2j4q
+fn short_pod_name(name: &str) -> String {
+    let replicaset_hash = Regex::new(r"^(.+)-[a-z0-9]{6,10}-([a-z0-9]{5})$").unwrap();
+    return replicaset_hash.replace(name, "$1-$2").to_string();
+}
+
 #[derive(Clone)]
 pub struct Namespace {
     pub name: String,
+    /// kubeconfig context of the namespace when several contexts are followed
+    pub context: Option<String>,
+    /// context/namespace when several contexts are followed, the namespace otherwise: identifies the namespace in the ids and prefixes
+    pub qualified_name: String,
     pub api: Api<ApiPod>,
+    /// lists the pods and streams their logs: the api, the fixtures of the offline mode or the sources given to the tailer builder
+    pub source: Arc<dyn source::PodSource>,
+    /// used to find the deployment owning a pod replicaset when grouping by owner
+    replica_sets: Api<ReplicaSet>,
+    /// used to know when the jobs followed with the job setting have ended
+    jobs: Api<Job>,
+    /// shared by every namespace when the api cache is enabled
+    pod_cache: Option<Arc<cache::PodCache>>,
+}
+
+/// state of the jobs matching the job setting
+pub enum JobsState {
+    /// no job matched yet or one of them has not ended
+    Running,
+    Complete,
+    /// namespace/name of the failed jobs
+    Failed(Vec<String>),
+}
+
+fn has_job_condition(job: &Job, condition_type: &str) -> bool {
+    return job
+        .status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())
+        .is_some_and(|conditions| {
+            conditions
+                .iter()
+                .any(|condition| condition.type_ == condition_type && condition.status == "True")
+        });
+}
+
+/// name shown in the prefix
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum GroupBy {
+    /// the pod name
+    Pod,
+    /// the workload owning the pod and the pod suffix: deployment-name/x2j4q
+    Owner,
+}
+
+#[derive(Debug, Clone)]
+enum LabelRequirement {
+    Exists(String),
+    NotExists(String),
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+}
+
+/// label selector with the kubectl syntax (eg: `app in (api,worker),!canary,tier=backend`), validated locally so that a typo
+/// is reported before any request, then sent to the api with the pod listings
+#[derive(Debug, Clone)]
+pub struct LabelSelector {
+    requirements: Vec<LabelRequirement>,
+}
+
+/// label name, or value when not empty: 63 alphanumeric characters at most, with dashes, underscores and dots inside
+fn validate_label_name(name: &str, what: &str, selector: &str) -> Result<(), Errors> {
+    let pattern = Regex::new(r"^[A-Za-z0-9]([-A-Za-z0-9_.]*[A-Za-z0-9])?$").unwrap();
+    if name.len() > 63 || !pattern.is_match(name) {
+        return Err(Errors::Validation(format!("invalid label {what} {name} in selector {selector}")));
+    }
+    return Ok(());
+}
+
+/// optional dns subdomain prefix followed by a slash, then the name (eg: app.kubernetes.io/name)
+fn validate_label_key(key: &str, selector: &str) -> Result<String, Errors> {
+    let name = match key.split_once("/") {
+        Some((prefix, name)) => {
+            let prefix_pattern = Regex::new(r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?(\.[a-z0-9]([-a-z0-9]*[a-z0-9])?)*$").unwrap();
+            if prefix.len() > 253 || !prefix_pattern.is_match(prefix) {
+                return Err(Errors::Validation(format!("invalid label prefix {prefix} in selector {selector}")));
+            }
+            name
+        }
+        None => key,
+    };
+    validate_label_name(name, "key", selector)?;
+    return Ok(key.to_string());
+}
+
+fn validate_label_value(value: &str, selector: &str) -> Result<String, Errors> {
+    if value.len() > 0 {
+        validate_label_name(value, "value", selector)?;
+    }
+    return Ok(value.to_string());
+}
+
+/// the requirements are separated by the commas which are not inside a set of values
+fn split_requirements(selector: &str) -> Result<Vec<&str>, Errors> {
+    let mut requirements = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, current) in selector.char_indices() {
+        match current {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(Errors::Validation(format!("unexpected ) in selector {selector}"))),
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                requirements.push(&selector[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err(Errors::Validation(format!("unclosed ( in selector {selector}")));
+    }
+    requirements.push(&selector[start..]);
+    return Ok(requirements);
+}
+
+impl FromStr for LabelSelector {
+    type Err = Errors;
+    fn from_str(selector: &str) -> Result<Self, Self::Err> {
+        let set_pattern = Regex::new(r"^(\S+)\s+(in|notin)\s*\((.*)\)$").unwrap();
+        let mut requirements = vec![];
+        for requirement in split_requirements(selector)? {
+            let requirement = requirement.trim();
+            if requirement.len() == 0 {
+                return Err(Errors::Validation(format!("empty requirement in selector {selector}")));
+            }
+            requirements.push(if let Some(captures) = set_pattern.captures(requirement) {
+                let key = validate_label_key(&captures[1], selector)?;
+                if captures[3].trim().len() == 0 {
+                    return Err(Errors::Validation(format!("empty set of values for {key} in selector {selector}")));
+                }
+                let values = captures[3]
+                    .split(",")
+                    .map(|value| validate_label_value(value.trim(), selector))
+                    .collect::<Result<Vec<String>, Errors>>()?;
+                if &captures[2] == "in" {
+                    LabelRequirement::In(key, values)
+                } else {
+                    LabelRequirement::NotIn(key, values)
+                }
+            } else if let Some(key) = requirement.strip_prefix("!") {
+                LabelRequirement::NotExists(validate_label_key(key.trim(), selector)?)
+            } else if let Some((key, value)) = requirement.split_once("!=") {
+                LabelRequirement::NotEquals(validate_label_key(key.trim(), selector)?, validate_label_value(value.trim(), selector)?)
+            } else if let Some((key, value)) = requirement.split_once("==").or_else(|| requirement.split_once("=")) {
+                LabelRequirement::Equals(validate_label_key(key.trim(), selector)?, validate_label_value(value.trim(), selector)?)
+            } else {
+                LabelRequirement::Exists(validate_label_key(requirement, selector)?)
+            });
+        }
+        return Ok(LabelSelector { requirements });
+    }
+}
+
+/// the selector in the form expected by the api
+impl std::fmt::Display for LabelSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let requirements: Vec<String> = self
+            .requirements
+            .iter()
+            .map(|requirement| match requirement {
+                LabelRequirement::Exists(key) => key.clone(),
+                LabelRequirement::NotExists(key) => format!("!{key}"),
+                LabelRequirement::Equals(key, value) => format!("{key}={value}"),
+                LabelRequirement::NotEquals(key, value) => format!("{key}!={value}"),
+                LabelRequirement::In(key, values) => format!("{key} in ({})", values.join(",")),
+                LabelRequirement::NotIn(key, values) => format!("{key} notin ({})", values.join(",")),
+            })
+            .collect();
+        return write!(f, "{}", requirements.join(","));
+    }
+}
+
+/// a pod returned by the namespaces listing, before it is followed
+pub struct ListedPod {
+    pub name: String,
+    pub namespace: Namespace,
+    pub pod_api: ApiPod,
+    /// workload/suffix when grouping by owner
+    pub group_name: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct Namespaces {
     pub items: Vec<Namespace>,
+    retry: RetryPolicy,
+    page_size: u32,
+    metadata_listing: bool,
+    respect_skip_annotation: bool,
+    with_labels: Vec<(String, String)>,
+    with_annotations: Vec<(String, String)>,
+    /// sent with the pod listings, so that the api only returns the pods matching it
+    selector: Option<String>,
+    job: Option<Regex>,
+    explain: bool,
+    /// last decision printed for each pod with explain
+    explained: Arc<std::sync::Mutex<HashMap<String, String>>>,
+    /// explanations waiting to be printed with the status messages, see take_explanations
+    explanations: Arc<std::sync::Mutex<Vec<String>>>,
+    group_by_owner: bool,
+    /// namespace/replicaset => deployment, the owner of a replicaset never changes
+    deployments: Arc<std::sync::Mutex<HashMap<String, String>>>,
+}
+
+impl Namespace {
+    fn new(context_client: &ContextClient, name: &str, pod_cache: &Option<Arc<cache::PodCache>>) -> Namespace {
+        let client = &context_client.client;
+        return Namespace {
+            name: name.to_string(),
+            context: context_client.context.clone(),
+            qualified_name: match &context_client.context {
+                Some(context) => format!("{context}/{name}"),
+                None => name.to_string(),
+            },
+            api: Api::namespaced(client.clone(), name),
+            source: Arc::new(source::KubeSource::new(Api::namespaced(client.clone(), name))),
+            replica_sets: Api::namespaced(client.clone(), name),
+            jobs: Api::namespaced(client.clone(), name),
+            pod_cache: pod_cache.clone(),
+        };
+    }
+
+    /// false only when the api reports that the namespace does not exist, a namespace which can't be read is assumed to exist
+    pub async fn exists(&self) -> bool {
+        let namespaces: Api<ApiNamespace> = Api::all(self.api.clone().into_client());
+        return !matches!(namespaces.get_opt(&self.name).await, Ok(None));
+    }
+
+    /// with the api cache, only the metadata of the pod is fetched when its resource version did not change since it was cached
+    pub async fn get_pod(&self, name: &str) -> Result<ApiPod, kube::Error> {
+        let Some(pod_cache) = &self.pod_cache else {
+            return self.source.get(name).await;
+        };
+        let metadata = self.api.get_metadata(name).await?;
+        if let Some(pod) = pod_cache.get(&metadata.metadata) {
+            return Ok(pod);
+        }
+        let pod = self.source.get(name).await?;
+        pod_cache.insert(&pod);
+        return Ok(pod);
+    }
 }
 
 impl Namespaces {
-    pub fn new(client: &kube::Client, namespaces_names: &Vec<String>) -> Namespaces {
+    /// the namespaces of the settings (or the default one of the context) in every context
+    pub fn new(clients: &[ContextClient], settings: &settings::SettingsValidated) -> Namespaces {
         let mut namespaces: Vec<Namespace> = vec![];
-        let namespaces_mut: &mut Vec<Namespace> = namespaces.as_mut();
-        if namespaces_names.len() == 0 {
-            let default_namespace = client.default_namespace();
-            namespaces_mut.push(Namespace {
-                name: default_namespace.to_string(),
-                api: Api::namespaced(client.clone(), &default_namespace),
-            });
+        let pod_cache = if settings.api_cache {
+            Some(Arc::new(cache::PodCache::default()))
         } else {
-            for namespace in namespaces_names {
-                namespaces_mut.push(Namespace {
-                    name: namespace.clone(),
-                    api: Api::namespaced(client.clone(), &namespace.clone()),
+            None
+        };
+        for context_client in clients {
+            if settings.namespaces.len() == 0 {
+                namespaces.push(Namespace::new(context_client, context_client.client.default_namespace(), &pod_cache));
+            }
+            for namespace in settings.namespaces.iter() {
+                namespaces.push(Namespace::new(context_client, namespace, &pod_cache));
+            }
+        }
+        return Namespaces {
+            items: namespaces,
+            retry: settings.retry,
+            page_size: settings.list_page_size,
+            metadata_listing: settings.metadata_listing,
+            respect_skip_annotation: settings.respect_skip_annotation,
+            with_labels: settings.with_labels.clone(),
+            with_annotations: settings.with_annotations.clone(),
+            selector: settings.selector.as_ref().map(|selector| selector.to_string()),
+            job: settings.job.clone(),
+            explain: settings.explain,
+            explained: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            explanations: Arc::new(std::sync::Mutex::new(vec![])),
+            group_by_owner: settings.group_by == GroupBy::Owner,
+            deployments: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        };
+    }
+
+    /// a namespace for each directory of the offline fixtures, only the ones of the namespaces setting if given
+    pub fn fixtures(dir: &PathBuf, settings: &settings::SettingsValidated) -> Result<Namespaces, Errors> {
+        let entries = std::fs::read_dir(dir).map_err(|err| Errors::Validation(format!("failled to read fixture dir {}: {err}", dir.display())))?;
+        let mut names: Vec<(Option<String>, String)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| (None, entry.file_name().to_string_lossy().to_string()))
+            .filter(|(_, name)| settings.namespaces.len() == 0 || settings.namespaces.contains(name))
+            .collect();
+        names.sort();
+        let mut namespaces = Namespaces::offline(&names, settings)?;
+        for namespace in namespaces.items.iter_mut() {
+            namespace.source = Arc::new(source::FixtureSource::new(dir.join(&namespace.name), namespace.name.clone()));
+        }
+        return Ok(namespaces);
+    }
+
+    /// a namespace for each source given to the tailer builder
+    pub fn from_sources(sources: &[(String, Arc<dyn source::PodSource>)], settings: &settings::SettingsValidated) -> Result<Namespaces, Errors> {
+        let names: Vec<(Option<String>, String)> = sources.iter().map(|(name, _)| (None, name.clone())).collect();
+        let mut namespaces = Namespaces::offline(&names, settings)?;
+        for (namespace, (_, source)) in namespaces.items.iter_mut().zip(sources) {
+            namespace.source = source.clone();
+        }
+        return Ok(namespaces);
+    }
+
+    /// namespaces whose client never reaches a cluster, for the lines replayed from a file and the offline fixtures
+    pub fn offline(names: &[(Option<String>, String)], settings: &settings::SettingsValidated) -> Result<Namespaces, Errors> {
+        let client = Client::try_from(Config::new("http://127.0.0.1:1".parse().unwrap()))
+            .map_err(|err| Errors::KubeConfig("creating the offline client".to_string(), err.to_string()))?;
+        let mut namespaces = Namespaces::new(&[], settings);
+        for (context, name) in names {
+            let context_client = ContextClient {
+                context: context.clone(),
+                client: client.clone(),
+                has_default_namespace: true,
+            };
+            namespaces.items.push(Namespace::new(&context_client, name, &None));
+        }
+        return Ok(namespaces);
+    }
+
+    /// workload/suffix (eg: api/x2j4q) when grouping by owner, the owning replicaset is resolved to its deployment
+    async fn group_name(&self, namespace: &Namespace, pod: &ApiPod) -> Option<String> {
+        if !self.group_by_owner {
+            return None;
+        }
+        let owner = pod.metadata.owner_references.as_ref()?.first()?;
+        let name = get_pod_name(pod);
+        let suffix = name.strip_prefix(&format!("{}-", owner.name)).unwrap_or(&name).to_string();
+        if owner.kind != "ReplicaSet" {
+            return Some(format!("{}/{}", owner.name, suffix));
+        }
+        let key = format!("{}/{}", namespace.qualified_name, owner.name);
+        let cached = self.deployments.lock().ok().and_then(|deployments| deployments.get(&key).cloned());
+        let workload = match cached {
+            Some(workload) => workload,
+            None => {
+                let workload = match namespace.replica_sets.get(&owner.name).await {
+                    Ok(replica_set) => replica_set
+                        .metadata
+                        .owner_references
+                        .as_ref()
+                        .and_then(|owners| owners.iter().find(|owner| owner.kind == "Deployment"))
+                        .map(|deployment| deployment.name.clone())
+                        .unwrap_or(owner.name.clone()),
+                    Err(err) => {
+                        // not cached, so that it's resolved again with the next new pod
+                        tracing::debug!(replica_set = %key, error = %err, "failled to get the replicaset owning a pod");
+                        return Some(format!("{}/{}", owner.name, suffix));
+                    }
+                };
+                if let Ok(mut deployments) = self.deployments.lock() {
+                    deployments.insert(key, workload.clone());
+                }
+                workload
+            }
+        };
+        return Some(format!("{}/{}", workload, suffix));
+    }
+
+    /// the first label or annotation value asked for with with_label and with_annotation that the pod doesn't have
+    fn missing_metadata(&self, metadata: &ObjectMeta) -> Option<String> {
+        let missing = |map: &Option<std::collections::BTreeMap<String, String>>, selected: &Vec<(String, String)>, kind: &str| {
+            return selected
+                .iter()
+                .find(|(key, value)| !map.as_ref().and_then(|map| map.get(key)).is_some_and(|found| found == value))
+                .map(|(key, value)| format!("{kind} {key}={value}"));
+        };
+        return missing(&metadata.labels, &self.with_labels, "label")
+            .or_else(|| missing(&metadata.annotations, &self.with_annotations, "annotation"));
+    }
+
+    /// log why a pod seen by the discovery is followed or not, also queued to be printed with explain when it changes
+    fn explain(&self, namespace: &Namespace, pod: &str, decision: &str) {
+        tracing::debug!(namespace = %namespace.name, pod = %pod, "{}", decision);
+        if !self.explain {
+            return;
+        }
+        let pod_id = format!("{}/{}", namespace.qualified_name, pod);
+        let Ok(mut explained) = self.explained.lock() else {
+            return;
+        };
+        if explained.get(&pod_id).is_some_and(|previous| previous == decision) {
+            return;
+        }
+        if let Ok(mut explanations) = self.explanations.lock() {
+            explanations.push(format!("explain: {pod_id} {decision}"));
+        }
+        explained.insert(pod_id, decision.to_string());
+    }
+
+    /// the explanations queued since the last call, printed as status messages so that they don't interleave with the lines
+    pub fn take_explanations(&self) -> Vec<String> {
+        return match self.explanations.lock() {
+            Ok(mut explanations) => explanations.drain(..).collect(),
+            Err(_) => vec![],
+        };
+    }
+
+    /// the pods whose name matches the search and having the selected labels and annotations, without the ones opting out with the skip annotation
+    fn is_selected(&self, namespace: &Namespace, search: &Regex, metadata: &ObjectMeta) -> bool {
+        let name = metadata.name.as_deref().unwrap_or_default();
+        let skipped = if !search.is_match(name) {
+            Some(format!("skipped: name does not match the pod search {}", search.as_str()))
+        } else if self.respect_skip_annotation && is_pod_skipped(metadata) {
+            Some(format!("skipped: {} annotation", SKIP_ANNOTATION))
+        } else if let Some(missing) = self.missing_metadata(metadata) {
+            Some(format!("skipped: no {missing}"))
+        } else {
+            match &self.job {
+                Some(job)
+                    if !metadata
+                        .owner_references
+                        .iter()
+                        .flatten()
+                        .any(|owner| owner.kind == "Job" && job.is_match(&owner.name)) =>
+                {
+                    Some(format!("skipped: not owned by a job matching {}", job.as_str()))
+                }
+                _ => None,
+            }
+        };
+        if let Some(skipped) = &skipped {
+            self.explain(namespace, name, skipped);
+        }
+        return skipped.is_none();
+    }
+
+    /// a job has ended once its Complete or Failed condition is set, the jobs are running until one matches the job setting
+    pub async fn jobs_state(&self) -> Result<JobsState, Errors> {
+        let Some(job_search) = &self.job else {
+            return Ok(JobsState::Running);
+        };
+        let mut found = false;
+        let mut failed = vec![];
+        for namespace in self.items.iter() {
+            let jobs = self
+                .retry
+                .run("get jobs list", || async move {
+                    return namespace
+                        .jobs
+                        .list(&ListParams::default())
+                        .await
+                        .map_err(|err| Errors::Kubernetes(format!("get jobs list on namespace {}", namespace.name), err));
+                })
+                .await?;
+            for job in jobs.items.iter() {
+                let name = job.metadata.name.as_deref().unwrap_or_default();
+                if !job_search.is_match(name) {
+                    continue;
+                }
+                found = true;
+                if has_job_condition(job, "Failed") {
+                    failed.push(format!("{}/{}", namespace.qualified_name, name));
+                } else if !has_job_condition(job, "Complete") {
+                    return Ok(JobsState::Running);
+                }
+            }
+        }
+        return Ok(match (found, failed.len()) {
+            (false, _) => JobsState::Running,
+            (true, 0) => JobsState::Complete,
+            (true, _) => JobsState::Failed(failed),
+        });
+    }
+
+    /// every page of a listing of the namespace pods, the items are selected as the pages arrive
+    async fn list_pages<T, F, Fut>(&self, namespace: &Namespace, list: F, selected: impl Fn(&T) -> bool) -> Result<Vec<T>, Errors>
+    where
+        T: Clone,
+        F: Fn(ListParams) -> Fut,
+        Fut: std::future::Future<Output = Result<ObjectList<T>, kube::Error>>,
+    {
+        let list = &list;
+        let mut items = vec![];
+        let mut continue_token: Option<String> = None;
+        loop {
+            let mut params = ListParams::default();
+            if self.page_size > 0 {
+                params = params.limit(self.page_size);
+            }
+            if let Some(token) = &continue_token {
+                params = params.continue_token(token);
+            }
+            if let Some(selector) = &self.selector {
+                params = params.labels(selector);
+            }
+            let params = &params;
+            let page = self
+                .retry
+                .run("get pods list", || async move {
+                    return list(params.clone())
+                        .await
+                        .map_err(|err| Errors::Kubernetes(format!("get pods list on namespace {}", namespace.name), err));
+                })
+                .await;
+            let page = match page {
+                // the continue token expired (the list took longer than the api server keeps its snapshot), list again from the start
+                Err(Errors::Kubernetes(_, kube::Error::Api(response))) if response.code == 410 && continue_token.is_some() => {
+                    tracing::debug!(namespace = %namespace.name, "continue token expired, listing the pods again");
+                    items.clear();
+                    continue_token = None;
+                    continue;
+                }
+                page => page?,
+            };
+            continue_token = page.metadata.continue_.filter(|token| token.len() > 0);
+            items.extend(page.items.into_iter().filter(|item| selected(item)));
+            if continue_token.is_none() {
+                return Ok(items);
+            }
+        }
+    }
+
+    /// selected pods of the namespace, see is_selected. with metadata listing only the metadata of the pods is listed,
+    /// the full objects are then fetched for the selected pods which are not known yet
+    async fn list_namespace_pods(&self, namespace: &Namespace, search: &Regex, known: impl Fn(&str) -> bool) -> Result<Vec<ApiPod>, Errors> {
+        if !self.metadata_listing {
+            let pods = self
+                .list_pages(
+                    namespace,
+                    |params| async move { namespace.source.list(&params).await },
+                    |pod: &ApiPod| self.is_selected(namespace, search, &pod.metadata),
+                )
+                .await?;
+            if let Some(pod_cache) = &namespace.pod_cache {
+                pods.iter().for_each(|pod| pod_cache.insert(pod));
+            }
+            return Ok(pods);
+        }
+        let listed = self
+            .list_pages(
+                namespace,
+                |params| async move { namespace.api.list_metadata(&params).await },
+                |pod: &PartialObjectMeta<ApiPod>| {
+                    return !known(pod.metadata.name.as_deref().unwrap_or_default()) && self.is_selected(namespace, search, &pod.metadata);
+                },
+            )
+            .await?;
+        let mut pods = vec![];
+        for listed_pod in listed {
+            if let Some(pod) = namespace.pod_cache.as_ref().and_then(|pod_cache| pod_cache.get(&listed_pod.metadata)) {
+                pods.push(pod);
+                continue;
+            }
+            let name = &listed_pod.metadata.name.unwrap_or_default();
+            let pod = self
+                .retry
+                .run("get pod", || async move {
+                    return namespace
+                        .source
+                        .get(name)
+                        .await
+                        .map_err(|err| Errors::Kubernetes(format!("get pod {name} on namespace {}", namespace.name), err));
+                })
+                .await;
+            match pod {
+                Ok(pod) => {
+                    if let Some(pod_cache) = &namespace.pod_cache {
+                        pod_cache.insert(&pod);
+                    }
+                    pods.push(pod);
+                }
+                // deleted since the listing
+                Err(Errors::Kubernetes(_, kube::Error::Api(response))) if response.code == 404 => {}
+                Err(err) => return Err(err),
+            }
+        }
+        return Ok(pods);
+    }
+
+    pub async fn get_pod_names(&self, search: &Regex) -> Result<Vec<String>, Errors> {
+        return Ok(self.list_pods(search).await?.into_iter().map(|listed| listed.name).collect());
+    }
+
+    /// check that the pods can be listed and their logs read in every namespace, so that a missing role is reported precisely
+    /// instead of failing on the first listing, a cluster which can't review the access is not checked
+    pub async fn check_permissions(&self) -> Result<(), Errors> {
+        let mut missing = vec![];
+        for namespace in self.items.iter() {
+            for (verb, resource, subresource) in [("list", "pods", None), ("get", "pods", Some("log"))] {
+                let review = SelfSubjectAccessReview {
+                    spec: SelfSubjectAccessReviewSpec {
+                        resource_attributes: Some(ResourceAttributes {
+                            namespace: Some(namespace.name.clone()),
+                            verb: Some(verb.to_string()),
+                            resource: Some(resource.to_string()),
+                            subresource: subresource.map(|subresource| subresource.to_string()),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                let reviews: Api<SelfSubjectAccessReview> = Api::all(namespace.api.clone().into_client());
+                let status = match reviews.create(&kube::api::PostParams::default(), &review).await {
+                    Ok(review) => review.status,
+                    Err(err) => {
+                        tracing::warn!(namespace = %namespace.qualified_name, error = %err, "permissions not checked");
+                        return Ok(());
+                    }
+                };
+                if status.as_ref().is_some_and(|status| status.allowed) {
+                    continue;
+                }
+                let permission = match subresource {
+                    Some(subresource) => format!("{resource}/{subresource}"),
+                    None => resource.to_string(),
+                };
+                let reason = status.and_then(|status| status.reason).filter(|reason| reason.len() > 0);
+                missing.push(match reason {
+                    Some(reason) => format!("{verb} {permission} in namespace {} ({reason})", namespace.qualified_name),
+                    None => format!("{verb} {permission} in namespace {}", namespace.qualified_name),
                 });
             }
         }
-        return Namespaces { items: namespaces };
+        if missing.len() > 0 {
+            return Err(Errors::Forbidden(missing.join(", ")));
+        }
+        return Ok(());
     }
 
-    pub async fn get_pods_cnt(&self, search: &Regex) -> Result<usize, Errors> {
-        let mut cnt: usize = 0;
+    /// pods of every namespace whose name matches the search, whatever their phase
+    pub async fn list_pods(&self, search: &Regex) -> Result<Vec<ListedPod>, Errors> {
+        let mut listed = vec![];
         for namespace in self.items.iter() {
-            let pod_list = namespace
-                .api
-                .list(&ListParams::default())
-                .await
-                .map_err(|err| Errors::Kubernetes(format!("get pods list on namespace {}", namespace.name), err.to_string()))?;
+            tracing::debug!(namespace = %namespace.name, "listing pods");
+            let pod_list = self.list_namespace_pods(namespace, search, |_| false).await?;
 
             for pod in pod_list {
-                let name = get_pod_name(&pod);
-                if search.is_match(name.as_str()) {
-                    cnt += 1;
+                listed.push(ListedPod {
+                    name: get_pod_name(&pod),
+                    namespace: namespace.clone(),
+                    group_name: self.group_name(namespace, &pod).await,
+                    pod_api: pod,
+                });
+            }
+        }
+        return Ok(listed);
+    }
+}
+
+/// names of the namespaces of every context
+pub async fn list_namespace_names(clients: &[ContextClient]) -> Result<Vec<String>, Errors> {
+    let mut names = vec![];
+    for context_client in clients {
+        let api: Api<k8s_openapi::api::core::v1::Namespace> = Api::all(context_client.client.clone());
+        for namespace in api
+            .list(&ListParams::default())
+            .await
+            .map_err(|err| Errors::Kubernetes("get namespaces list".to_string(), err))?
+        {
+            if let Some(name) = namespace.metadata.name {
+                if !names.contains(&name) {
+                    names.push(name);
                 }
             }
         }
-        return Ok(cnt);
     }
+    return Ok(names);
+}
+
+/// when no namespace is given and a context doesn't set one, the namespaces are picked by the user instead of falling back
+/// to the default namespace, without a terminal the accessible namespaces are listed in the error
+pub async fn pick_namespaces(clients: &[ContextClient], settings: &mut settings::SettingsValidated) -> Result<(), Errors> {
+    if settings.namespaces.len() > 0 || clients.iter().all(|context_client| context_client.has_default_namespace) {
+        return Ok(());
+    }
+    let names = list_namespace_names(clients).await?;
+    if !std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+        return Err(Errors::Validation(format!(
+            "no namespace given and the kubeconfig context has no default namespace, pass one of them with --namespaces: {}",
+            names.join(", ")
+        )));
+    }
+    settings.namespaces = crate::picker::pick_namespaces(names)?;
+    return Ok(());
+}
+
+/// namespaces of the cluster or pods of the namespaces starting with the prefix
+pub async fn complete(
+    clients: &[ContextClient],
+    namespaces: &Namespaces,
+    kind: &settings::CompleteKind,
+    prefix: &str,
+) -> Result<Vec<String>, Errors> {
+    let names: Vec<String> = match kind {
+        settings::CompleteKind::Namespaces => list_namespace_names(clients).await?,
+        settings::CompleteKind::Pods => namespaces.get_pod_names(&Regex::new(".*").unwrap()).await?,
+    };
+    return Ok(names.into_iter().filter(|name| name.starts_with(prefix)).collect());
+}
+
+/// why a pod log stream ended
+pub enum StreamEnd {
+    /// the api closed the stream (eg the pod stopped)
+    Closed,
+    /// the max number of lines to print for this pod was reached
+    MaxLines(u64),
+    /// the max number of lines matching the filters for this pod was reached
+    MaxMatches(u64),
+}
+
+/// a line received from a pod, as produced by the library stream api
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    /// kubeconfig context when several contexts are followed
+    pub context: Option<String>,
+    pub namespace: String,
+    pub pod: String,
+    pub container: String,
+    pub timestamp: DateTime<FixedOffset>,
+    pub message: String,
 }
 
 #[derive(Clone)]
 pub struct Pod {
     pub name: String,
     pub namespace: Namespace,
-    pub pod_api: ApiPod,
+    /// name used in the prefix, without the replicaset hash if short names are requested or workload/suffix if grouped by owner
+    pub display_name: String,
+    /// only the fields used from the api object are kept, it is cloned in every stream task
+    uid: String,
+    phase: String,
+    containers: Vec<String>,
+    default_container: String,
+    node_name: String,
+    pod_ip: String,
+    owner: Option<String>,
+    /// name of the workload owning the pod, the deployment for the pods of a replicaset
+    workload: Option<String>,
+    /// index of the replica when the pod belongs to a statefulset
+    ordinal: Option<u32>,
+    /// sum of the restarts of every container
+    restarts: i32,
+    created: Option<DateTime<chrono::Utc>>,
+    /// image of the default container
+    image: String,
+    labels: BTreeMap<String, String>,
+    container_states: Vec<ContainerState>,
+    /// Ready condition at the last listing, None if the pod has no such condition yet
+    ready: Option<bool>,
+    /// timestamp of the last previous line printed before following the pod, the live stream starts after it
+    history_end: Option<DateTime<FixedOffset>>,
     pub color: Rgb,
+    /// number of lines printed for this pod, shared between clones
+    pub line_count: Arc<AtomicU64>,
+    pub stats: Arc<stats::PodStats>,
+    /// nanoseconds timestamp of the last line printed (0 before the first one), recorded without locking the pods
+    /// and merged into the checkpoint when it's saved, shared between clones
+    last_seen: Arc<AtomicI64>,
 }
 
 impl PartialEq for Pod {
     fn eq(&self, other: &Self) -> bool {
-        self.name == other.name && self.namespace.name == other.namespace.name
+        self.name == other.name && self.namespace.qualified_name == other.namespace.qualified_name
     }
 }
 impl Eq for Pod {}
@@ -92,6 +816,193 @@ pub fn get_pod_status(pod: &ApiPod) -> Option<&String> {
     return None;
 }
 
+/// largest unit only, like kubectl: 45s, 2m, 3h, 12d
+fn format_age(age: chrono::Duration) -> String {
+    return if age.num_days() > 0 {
+        format!("{}d", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{}h", age.num_hours())
+    } else if age.num_minutes() > 0 {
+        format!("{}m", age.num_minutes())
+    } else {
+        format!("{}s", age.num_seconds().max(0))
+    };
+}
+
+/// status of the Ready condition and its reason (or message) when the pod is not ready
+fn pod_readiness(pod: &ApiPod) -> Option<(bool, String)> {
+    let condition = pod
+        .status
+        .as_ref()?
+        .conditions
+        .as_ref()?
+        .iter()
+        .find(|condition| condition.type_ == "Ready")?;
+    let reason = condition.reason.clone().or(condition.message.clone()).unwrap_or_default();
+    return Some((condition.status == "True", reason));
+}
+
+/// sum of the restarts of every container
+fn pod_restarts(pod: &ApiPod) -> i32 {
+    return pod
+        .status
+        .as_ref()
+        .and_then(|status| status.container_statuses.as_ref())
+        .map(|statuses| statuses.iter().map(|status| status.restart_count).sum())
+        .unwrap_or(0);
+}
+
+/// state of a container at the listing, shown by ls
+#[derive(Clone)]
+pub struct ContainerState {
+    pub name: String,
+    /// running, waiting or terminated, followed by the reason if any (eg: waiting: CrashLoopBackOff)
+    pub state: String,
+    pub restarts: i32,
+    pub image: String,
+}
+
+/// the containers of the spec (init containers excluded) with their status, a container without status yet is waiting
+fn container_states(pod: &ApiPod) -> Vec<ContainerState> {
+    let Some(spec) = &pod.spec else {
+        return vec![];
+    };
+    let statuses = pod.status.as_ref().and_then(|status| status.container_statuses.as_ref());
+    return spec
+        .containers
+        .iter()
+        .map(|container| {
+            let status = statuses.and_then(|statuses| statuses.iter().find(|status| status.name == container.name));
+            let state = status.and_then(|status| status.state.as_ref());
+            let state = if let Some(waiting) = state.and_then(|state| state.waiting.as_ref()) {
+                waiting
+                    .reason
+                    .as_ref()
+                    .map_or("waiting".to_string(), |reason| format!("waiting: {reason}"))
+            } else if let Some(terminated) = state.and_then(|state| state.terminated.as_ref()) {
+                let reason = terminated.reason.clone().unwrap_or("Error".to_string());
+                format!("terminated: {} (exit code {})", reason, terminated.exit_code)
+            } else if state.is_some_and(|state| state.running.is_some()) {
+                "running".to_string()
+            } else {
+                "waiting".to_string()
+            };
+            return ContainerState {
+                name: container.name.clone(),
+                state,
+                restarts: status.map_or(0, |status| status.restart_count),
+                image: container.image.clone().unwrap_or_default(),
+            };
+        })
+        .collect();
+}
+
+/// the last termination of a container which failled, eg: api: OOMKilled (exit code 137)
+fn last_crash(pod: &ApiPod) -> Option<String> {
+    return pod.status.as_ref()?.container_statuses.as_ref()?.iter().find_map(|status| {
+        let terminated = status.last_state.as_ref()?.terminated.as_ref()?;
+        if terminated.exit_code == 0 {
+            return None;
+        }
+        let reason = terminated.reason.clone().unwrap_or("Error".to_string());
+        return Some(format!("{}: {} (exit code {})", status.name, reason, terminated.exit_code));
+    });
+}
+
+/// keys of the colors persisted across invocations: namespace/pod, then namespace/kind/owner so that a new pod of the same workload gets its color
+fn color_keys(namespace: &Namespace, pod: &ApiPod) -> Vec<String> {
+    let mut keys = vec![format!("{}/{}", namespace.qualified_name, get_pod_name(pod))];
+    if let Some(owner) = pod.metadata.owner_references.as_ref().and_then(|owners| owners.first()) {
+        keys.push(format!("{}/{}/{}", namespace.qualified_name, owner.kind, owner.name));
+    }
+    return keys;
+}
+
+/// the ordinal suffix of a pod owned by a statefulset (db-0 => 0)
+/// the replicaset of a deployment is named after it and the pod-template-hash label, which avoids fetching the replicaset
+fn workload_name(pod: &ApiPod) -> Option<String> {
+    let owner = pod.metadata.owner_references.as_ref()?.first()?;
+    if owner.kind == "ReplicaSet" {
+        let hash = pod.metadata.labels.as_ref().and_then(|labels| labels.get("pod-template-hash"));
+        if let Some(deployment) = hash.and_then(|hash| owner.name.strip_suffix(&format!("-{hash}"))) {
+            return Some(deployment.to_string());
+        }
+    }
+    return Some(owner.name.clone());
+}
+
+fn statefulset_ordinal(pod: &ApiPod) -> Option<u32> {
+    let owner = pod.metadata.owner_references.as_ref()?.iter().find(|owner| owner.kind == "StatefulSet")?;
+    return get_pod_name(pod).strip_prefix(&format!("{}-", owner.name))?.parse::<u32>().ok();
+}
+
+/// the color of the color map rule matching the pod, else a shade of the namespace hue band if requested, else the color of its ordinal
+/// if requested and the pod belongs to a statefulset, else the one of its previous invocations or a new one
+fn pod_color(colors: &mut display::Colors, namespace: &Namespace, pod: &ApiPod, statefulset_hue: bool, namespace_hue: bool) -> Rgb {
+    if let Some(color) = colors.get_mapped_color(&get_pod_name(pod)) {
+        return color;
+    }
+    let ordinal = statefulset_ordinal(pod).filter(|_| statefulset_hue);
+    if namespace_hue {
+        return colors.get_namespace_color(&namespace.qualified_name, ordinal);
+    }
+    return match ordinal {
+        Some(ordinal) => colors.get_ordinal_color(ordinal),
+        _ => colors.get_color_for(&color_keys(namespace, pod)),
+    };
+}
+
+fn is_pod_skipped(metadata: &ObjectMeta) -> bool {
+    return metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(SKIP_ANNOTATION))
+        .is_some_and(|value| value == "true");
+}
+
+fn not_running_decision(pod: &ApiPod) -> String {
+    return format!(
+        "skipped: not running (phase {})",
+        get_pod_status(pod).map(|phase| phase.as_str()).unwrap_or("unknown")
+    );
+}
+
+/// why a pending pod can't be streamed yet and the detail given with it: the waiting reason of its first waiting container
+/// (init containers first, eg: ImagePullBackOff, ContainerCreating), or why it's not scheduled
+fn waiting_reason(pod: &ApiPod) -> Option<(String, String)> {
+    if get_pod_status(pod).map(|phase| phase.as_str()) != Some("Pending") {
+        return None;
+    }
+    let status = pod.status.as_ref()?;
+    let waiting = |statuses: &Option<Vec<k8s_openapi::api::core::v1::ContainerStatus>>| {
+        return statuses
+            .iter()
+            .flatten()
+            .find_map(|container| container.state.as_ref().and_then(|state| state.waiting.clone()));
+    };
+    if let Some(waiting) = waiting(&status.init_container_statuses) {
+        return Some((
+            format!("Init:{}", waiting.reason.unwrap_or("Waiting".to_string())),
+            waiting.message.unwrap_or_default(),
+        ));
+    }
+    if let Some(waiting) = waiting(&status.container_statuses) {
+        return Some((waiting.reason.unwrap_or("Waiting".to_string()), waiting.message.unwrap_or_default()));
+    }
+    let unscheduled = status
+        .conditions
+        .iter()
+        .flatten()
+        .find(|condition| condition.type_ == "PodScheduled" && condition.status == "False");
+    if let Some(condition) = unscheduled {
+        return Some((
+            condition.reason.clone().unwrap_or("Unschedulable".to_string()),
+            condition.message.clone().unwrap_or_default(),
+        ));
+    }
+    return Some(("Pending".to_string(), String::new()));
+}
+
 pub fn is_pod_running(pod: &ApiPod) -> bool {
     if let Some(phase) = get_pod_status(pod) {
         return phase == "Running";
@@ -99,9 +1010,333 @@ pub fn is_pod_running(pod: &ApiPod) -> bool {
     return false;
 }
 
+/// lines of a log stream read as bytes, so that a line which is not valid utf8 is decoded according to the binary setting
+/// instead of stopping the stream
+pub struct LogLines<R> {
+    reader: std::pin::Pin<Box<R>>,
+    buffer: Vec<u8>,
+    binary: lineproc::BinaryMode,
+    /// counts the truncated and undecodable lines of the pod
+    stats: Arc<stats::PodStats>,
+}
+
+impl<R: futures::AsyncBufRead> LogLines<R> {
+    pub fn new(reader: R, binary: &lineproc::BinaryMode, stats: Arc<stats::PodStats>) -> LogLines<R> {
+        return LogLines {
+            reader: Box::pin(reader),
+            buffer: vec![],
+            binary: binary.clone(),
+            stats,
+        };
+    }
+
+    /// None at the end of the stream, the bytes of a line are kept until it's complete so that a call can be cancelled (eg: by a timeout)
+    pub async fn next_line(&mut self) -> Result<Option<String>, std::io::Error> {
+        loop {
+            let read = match self.reader.read_until(b'\n', &mut self.buffer).await {
+                Ok(read) => read,
+                Err(err) => {
+                    // the stream is over, the beginning of the line read so far is lost
+                    if !self.buffer.is_empty() {
+                        self.stats.truncated_lines.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return Err(err);
+                }
+            };
+            if read == 0 && self.buffer.is_empty() {
+                return Ok(None);
+            }
+            let mut bytes = std::mem::take(&mut self.buffer);
+            if bytes.last() == Some(&b'\n') {
+                bytes.pop();
+            } else {
+                // read_until only stops before the end of line when the stream ended
+                self.stats.truncated_lines.fetch_add(1, Ordering::Relaxed);
+            }
+            match lineproc::decode_line(bytes, &self.binary) {
+                Some(line) => return Ok(Some(line)),
+                None => {
+                    self.stats.undecodable_lines.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!("line skipped: not valid utf8");
+                }
+            }
+        }
+    }
+
+    /// the next line if it arrives within the delay, None once the delay elapsed (the part of the line read so far is kept)
+    pub async fn next_line_within(&mut self, wait: Option<tokio::time::Duration>) -> Option<Result<Option<String>, std::io::Error>> {
+        return match wait {
+            Some(wait) => tokio::time::timeout(wait, self.next_line()).await.ok(),
+            None => Some(self.next_line().await),
+        };
+    }
+}
+
 impl Pod {
+    pub fn new(name: String, pod_api: &ApiPod, namespace: Namespace, color: Rgb, short_names: bool, group_name: Option<String>) -> Pod {
+        let display_name = match group_name {
+            Some(group_name) => group_name,
+            None if short_names => short_pod_name(&name),
+            None => name.clone(),
+        };
+        let containers: Vec<String> = match &pod_api.spec {
+            Some(spec) => spec.containers.iter().map(|container| container.name.clone()).collect(),
+            None => vec![],
+        };
+        // the container whose logs are streamed: the one from the default-container annotation or the first one
+        let default_container = pod_api
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get("kubectl.kubernetes.io/default-container").cloned())
+            .unwrap_or_else(|| containers.first().cloned().unwrap_or_default());
+        let image = pod_api
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.containers.iter().find(|container| container.name == default_container))
+            .and_then(|container| container.image.clone())
+            .unwrap_or_default();
+        return Pod {
+            name,
+            display_name,
+            namespace,
+            uid: pod_api.metadata.uid.clone().unwrap_or_default(),
+            phase: get_pod_status(pod_api).cloned().unwrap_or_default(),
+            containers,
+            default_container,
+            node_name: pod_api.spec.as_ref().and_then(|spec| spec.node_name.clone()).unwrap_or_default(),
+            pod_ip: pod_api.status.as_ref().and_then(|status| status.pod_ip.clone()).unwrap_or_default(),
+            owner: pod_api
+                .metadata
+                .owner_references
+                .as_ref()
+                .and_then(|owners| owners.first())
+                .map(|owner| format!("{}/{}", owner.kind, owner.name)),
+            workload: workload_name(pod_api),
+            ordinal: statefulset_ordinal(pod_api),
+            restarts: pod_restarts(pod_api),
+            created: pod_api.metadata.creation_timestamp.as_ref().map(|created| created.0),
+            image,
+            labels: pod_api.metadata.labels.clone().unwrap_or_default(),
+            container_states: container_states(pod_api),
+            ready: pod_readiness(pod_api).map(|(ready, _)| ready),
+            history_end: None,
+            color,
+            line_count: Arc::new(AtomicU64::new(0)),
+            stats: Arc::new(stats::PodStats::default()),
+            last_seen: Arc::new(AtomicI64::new(0)),
+        };
+    }
+
+    /// phase at the time the pod was listed
     pub fn is_running(&self) -> bool {
-        return is_pod_running(&self.pod_api);
+        return self.phase == "Running";
+    }
+
+    /// namespace, then the statefulset and ordinal of its replicas or the pod name
+    fn sort_key(&self) -> (String, String, u32) {
+        return match (self.ordinal, self.name.rsplit_once('-')) {
+            (Some(ordinal), Some((statefulset, _))) => (self.namespace.qualified_name.clone(), statefulset.to_string(), ordinal),
+            _ => (self.namespace.qualified_name.clone(), self.name.clone(), 0),
+        };
+    }
+
+    pub fn history_end(&self) -> Option<DateTime<FixedOffset>> {
+        return self.history_end;
+    }
+
+    /// namespace/name, context/namespace/name when several contexts are followed
+    pub fn id(&self) -> String {
+        return format!("{}/{}", self.namespace.qualified_name, self.name);
+    }
+
+    /// the container whose logs are streamed: the one from the default-container annotation or the first one
+    pub fn default_container(&self) -> String {
+        return self.default_container.clone();
+    }
+
+    pub fn containers(&self) -> Vec<String> {
+        return self.containers.clone();
+    }
+
+    /// states of the containers when the pod was listed
+    pub fn container_states(&self) -> &Vec<ContainerState> {
+        return &self.container_states;
+    }
+
+    /// the containers matching the search which restarted, the only ones with previous terminated logs
+    pub fn restarted_containers(&self, search: &Regex) -> Vec<String> {
+        return self
+            .container_states
+            .iter()
+            .filter(|container| container.restarts > 0 && search.is_match(&container.name))
+            .map(|container| container.name.clone())
+            .collect();
+    }
+
+    pub fn uid(&self) -> String {
+        return self.uid.clone();
+    }
+
+    pub fn phase(&self) -> String {
+        return self.phase.clone();
+    }
+
+    /// kind/name of the controller owning the pod (eg: ReplicaSet/api-5d8f9c), None for bare pods
+    pub fn owner(&self) -> Option<String> {
+        return self.owner.clone();
+    }
+
+    pub fn workload(&self) -> Option<String> {
+        return self.workload.clone();
+    }
+
+    /// restarts=3 age=2m image=foo:1.2.3, from the pod listing
+    pub fn start_details(&self) -> String {
+        return format!("restarts={} age={} image={}", self.restarts, self.age(), self.image);
+    }
+
+    pub fn age(&self) -> String {
+        return match self.created {
+            Some(created) => format_age(chrono::Utc::now() - created),
+            None => "?".to_string(),
+        };
+    }
+
+    pub fn restarts(&self) -> i32 {
+        return self.restarts;
+    }
+
+    pub fn node_name(&self) -> String {
+        return self.node_name.clone();
+    }
+
+    /// image of the default container
+    pub fn image(&self) -> String {
+        return self.image.clone();
+    }
+
+    /// the labels of the pod among the given keys
+    pub fn labels(&self, keys: &[String]) -> BTreeMap<String, String> {
+        return keys
+            .iter()
+            .filter_map(|key| self.labels.get(key).map(|value| (key.clone(), value.clone())))
+            .collect();
+    }
+
+    pub fn next_line_number(&self) -> u64 {
+        return self.line_count.fetch_add(1, Ordering::Relaxed) + 1;
+    }
+
+    /// record the date of a printed line for the checkpoint
+    pub fn record_last_seen(&self, date: DateTime<FixedOffset>) {
+        if let Some(nanos) = date.timestamp_nanos_opt() {
+            self.last_seen.fetch_max(nanos, Ordering::Relaxed);
+        }
+    }
+
+    /// date of the last line printed, not merged into the checkpoint yet
+    pub fn last_seen(&self) -> Option<DateTime<FixedOffset>> {
+        return match self.last_seen.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(chrono::TimeZone::timestamp_nanos(&chrono::Utc, nanos).into()),
+        };
+    }
+
+    /// namespace/name or name, truncated with … to max_prefix_width
+    pub fn prefix_label(&self, print_namespace: bool, max_prefix_width: Option<usize>) -> String {
+        let label = if print_namespace {
+            format!("{}/{}", self.namespace.qualified_name, self.display_name)
+        } else {
+            self.display_name.clone()
+        };
+        return match max_prefix_width {
+            Some(max_prefix_width) if label.chars().count() > max_prefix_width => {
+                let mut truncated: String = label.chars().take(max_prefix_width.saturating_sub(1)).collect();
+                truncated.push('…');
+                truncated
+            }
+            _ => label,
+        };
+    }
+
+    pub fn pod_ip(&self) -> String {
+        return self.pod_ip.clone();
+    }
+
+    /// through the node proxy of the kubelet with via_node_proxy, the pods/log api is used when the proxy request fails
+    async fn open_log_stream(&self, log_params: &kube::api::LogParams, settings: &settings::SettingsValidated) -> Result<source::LogReader, Errors> {
+        if settings.via_node_proxy && settings.offline.is_none() && self.node_name.len() > 0 {
+            let container = log_params.container.clone().unwrap_or_else(|| self.default_container());
+            let client = self.namespace.api.clone().into_client();
+            match node_proxy_log_stream(client, &self.node_name, &self.namespace.name, &self.name, &container, log_params).await {
+                Ok(reader) => return Ok(reader),
+                Err(err) => tracing::info!(pod = %self.id(), node = %self.node_name, error = %err, "node proxy log stream failled, using pods/log"),
+            }
+        }
+        return settings
+            .retry
+            .run("opening log stream", || async move {
+                return self
+                    .namespace
+                    .source
+                    .log_stream(&self.name, log_params)
+                    .await
+                    .map_err(|err| Errors::Kubernetes(format!("opening log stream of {}", self.id()), err));
+            })
+            .await;
+    }
+
+    /// checked again before reopening an idle stream, the pod may have stopped since it was listed
+    async fn is_still_running(&self) -> bool {
+        return match self.namespace.get_pod(&self.name).await {
+            Ok(pod) => is_pod_running(&pod),
+            Err(err) => {
+                tracing::debug!(pod = %self.id(), error = %err, "failled to get the pod status");
+                false
+            }
+        };
+    }
+
+    /// the date of the last line the container logged, probed with a one line tail when the stream is silent
+    async fn last_log_date(&self, log_params: &kube::api::LogParams, settings: &settings::SettingsValidated) -> Option<DateTime<FixedOffset>> {
+        let probe_params = kube::api::LogParams {
+            container: log_params.container.clone(),
+            previous: false,
+            timestamps: true,
+            tail_lines: Some(1),
+            ..kube::api::LogParams::default()
+        };
+        let reader = match self.namespace.source.log_stream(&self.name, &probe_params).await {
+            Ok(reader) => reader,
+            Err(err) => {
+                tracing::debug!(pod = %self.id(), error = %err, "failled to probe the last log line");
+                return None;
+            }
+        };
+        let mut lines = LogLines::new(reader, &settings.binary, self.stats.clone());
+        let mut last_date = None;
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(date) = lineproc::split_timestamp(&line)
+                .0
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+            {
+                last_date = Some(date);
+            }
+        }
+        return last_date;
+    }
+
+    async fn reopen_log_stream(
+        &self,
+        log_params: &kube::api::LogParams,
+        last_date: DateTime<FixedOffset>,
+        settings: &settings::SettingsValidated,
+    ) -> Result<LogLines<source::LogReader>, Errors> {
+        metrics::inc(&metrics::RECONNECTS);
+        let log_params = resume_log_param(log_params.clone(), Some(last_date));
+        let reader = self.open_log_stream(&log_params, settings).await?;
+        return Ok(LogLines::new(reader, &settings.binary, self.stats.clone()));
     }
 
     pub async fn print_logs(
@@ -110,55 +1345,321 @@ impl Pod {
         settings: settings::SettingsValidated,
         pods: types::ArcMutex<Pods>,
         streams: types::ArcMutex<display::Streams>,
+    ) -> Result<StreamEnd, Errors> {
+        tracing::info!(pod = %self.id(), since_seconds = ?log_params.since_seconds, tail_lines = ?log_params.tail_lines, "opening log stream");
+        let mut stream = LogLines::new(self.open_log_stream(&log_params, &settings).await?, &settings.binary, self.stats.clone());
+        let mut printer = display::PodPrinter::new(self, &settings, &pods, &streams).await?;
+        let mut grouper = settings.multiline_start.clone().map(lineproc::MultilineGrouper::new);
+        let use_flush = grouper.is_some() || printer.needs_flush();
+        // a reopened stream starts from the last line received, or from the opening of the first one if nothing was received
+        let mut last_date: DateTime<FixedOffset> = chrono::Utc::now().into();
+        let mut last_received = tokio::time::Instant::now();
+        // the watchdog probes the pod once per period of silence, not after each probe
+        let mut last_checked = tokio::time::Instant::now();
+        // the lines of the second of the last line are sent again by a reopened stream
+        let mut skip_until: Option<DateTime<FixedOffset>> = None;
+        loop {
+            let mut wait = if use_flush { Some(FLUSH_DELAY) } else { None };
+            if let Some(idle_timeout) = settings.stream_idle_timeout {
+                let remaining = idle_timeout.saturating_sub(last_received.elapsed());
+                wait = Some(wait.map_or(remaining, |wait| wait.min(remaining)));
+            }
+            if let Some(watchdog) = settings.stream_watchdog {
+                let remaining = watchdog.saturating_sub(last_checked.elapsed());
+                wait = Some(wait.map_or(remaining, |wait| wait.min(remaining)));
+            }
+            let next = stream.next_line_within(wait).await;
+            match next {
+                Some(next) => {
+                    let line = match next.map_err(|err| Errors::Io(format!("log stream of {}", self.id()), err))? {
+                        Some(line) => lineproc::normalize_line(&line, settings.strip_ansi),
+                        None => break,
+                    };
+                    last_received = tokio::time::Instant::now();
+                    last_checked = last_received;
+                    if settings.stream_idle_timeout.is_some() || settings.stream_watchdog.is_some() {
+                        if let Some(date) = lineproc::split_timestamp(&line)
+                            .0
+                            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                        {
+                            if skip_until.is_some_and(|skip_until| date <= skip_until) {
+                                continue;
+                            }
+                            skip_until = None;
+                            last_date = date;
+                        }
+                    }
+                    let line = match &mut grouper {
+                        Some(grouper) => grouper.push(line),
+                        None => Some(line),
+                    };
+                    if let Some(line) = line {
+                        printer.print(line).await?;
+                    }
+                    if let Some(max_lines) = printer.max_lines_reached() {
+                        printer.flush().await?;
+                        return Ok(StreamEnd::MaxLines(max_lines));
+                    }
+                    if let Some(max_matches) = printer.max_matches_reached() {
+                        printer.flush().await?;
+                        return Ok(StreamEnd::MaxMatches(max_matches));
+                    }
+                }
+                None => {
+                    if let Some(block) = grouper.as_mut().and_then(|grouper| grouper.flush()) {
+                        printer.print(block).await?;
+                    }
+                    printer.flush().await?;
+                    printer.check_idle().await?;
+                    let idle_timeout = settings
+                        .stream_idle_timeout
+                        .filter(|idle_timeout| last_received.elapsed() >= *idle_timeout);
+                    if let Some(idle_timeout) = idle_timeout {
+                        last_received = tokio::time::Instant::now();
+                        last_checked = last_received;
+                        if self.is_still_running().await {
+                            tracing::info!(pod = %self.id(), idle_seconds = idle_timeout.as_secs(), since = %last_date, "reopening idle log stream");
+                            stream = self.reopen_log_stream(&log_params, last_date, &settings).await?;
+                            skip_until = Some(last_date);
+                        }
+                    }
+                    // the stream is dead when the pod logged lines after the last one it sent
+                    if settings.stream_watchdog.is_some_and(|watchdog| last_checked.elapsed() >= watchdog) {
+                        last_checked = tokio::time::Instant::now();
+                        if let Some(logged) = self.last_log_date(&log_params, &settings).await.filter(|logged| *logged > last_date) {
+                            tracing::warn!(pod = %self.id(), since = %last_date, last_logged = %logged, "reviving silent log stream");
+                            stream = self.reopen_log_stream(&log_params, last_date, &settings).await?;
+                            skip_until = Some(last_date);
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(block) = grouper.as_mut().and_then(|grouper| grouper.flush()) {
+            printer.print(block).await?;
+        }
+        printer.flush().await?;
+        tracing::info!(pod = %self.id(), "log stream closed by the api");
+        return Ok(StreamEnd::Closed);
+    }
+
+    /// send the lines matching the filters to the sender instead of printing them, the log params must request timestamps.
+    /// the sender is bounded so that a slow consumer holds the stream back, the error ending the stream is returned
+    pub async fn stream_logs(
+        &self,
+        log_params: kube::api::LogParams,
+        settings: settings::SettingsValidated,
+        sender: tokio::sync::mpsc::Sender<Result<LogLine, Errors>>,
     ) -> Result<(), Errors> {
-        let mut stream = self
-            .namespace
-            .api
-            .log_stream(&self.name, &log_params)
+        let mut stream = LogLines::new(self.open_log_stream(&log_params, &settings).await?, &settings.binary, self.stats.clone());
+        let container = log_params.container.clone().unwrap_or_else(|| self.default_container());
+        while let Some(raw_line) = stream
+            .next_line()
             .await
-            .map_err(|err| Errors::LogError(err.to_string()))?
-            .lines();
-        while let Some(line) = stream.try_next().await.map_err(|err| Errors::LogError(err.to_string()))? {
-            display::print_log_line(&line, &settings, &pods, &streams, self).await?;
+            .map_err(|err| Errors::Io(format!("log stream of {}", self.id()), err))?
+        {
+            let raw_line = lineproc::normalize_line(&raw_line, settings.strip_ansi);
+            let (date_str, content) = raw_line
+                .split_once(" ")
+                .ok_or_else(|| Errors::LogError(format!("failled to split a line of {}", self.id())))?;
+            let timestamp = chrono::DateTime::parse_from_rfc3339(date_str)
+                .map_err(|err| Errors::LogError(format!("invalid timestamp in a line of {}: {err}", self.id())))?;
+            let message = match display::process_log_line(content.to_string(), &settings) {
+                Some(message) => message,
+                None => {
+                    self.stats.filtered_lines.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            let line = LogLine {
+                context: self.namespace.context.clone(),
+                namespace: self.namespace.name.clone(),
+                pod: self.name.clone(),
+                container: container.clone(),
+                timestamp,
+                message,
+            };
+            if sender.send(Ok(line)).await.is_err() {
+                // the consumer dropped the stream
+                break;
+            }
         }
         return Ok(());
     }
 
-    pub async fn get_previous_log_lines(
+    /// send the previous lines of the pod in their order, dated in utc whatever the offset the pod emits so that the lines
+    /// of several pods can be merged. a line is held until the next dated one so that the continuation lines can be appended
+    /// to it, the sending stops once the receiver is dropped
+    pub async fn send_previous_log_lines(
         &self,
         log_param: &kube::api::LogParams,
         settings: &settings::SettingsValidated,
-    ) -> Result<Vec<(DateTime<FixedOffset>, String, Pod)>, Errors> {
-        let mut lines = vec![];
-        for raw_line in self
-            .namespace
-            .api
-            .logs(&self.name, &log_param)
+        sender: tokio::sync::mpsc::Sender<PreviousLine>,
+    ) -> Result<(), Errors> {
+        let mut pending: Option<PreviousLine> = None;
+        // shared by the lines instead of cloned for each of them
+        let pod = Arc::new(self.clone());
+        // lines whose timestamp can't be parsed (eg the continuation lines of a stack trace) are appended to the previous line
+        // or reuse the date of the previous line of the pod when there is none, so that they keep their receive order
+        let mut last_date = self.created.unwrap_or_default().fixed_offset();
+        let mut stream = LogLines::new(self.open_log_stream(log_param, settings).await?, &settings.binary, self.stats.clone());
+        while let Some(raw_line) = stream
+            .next_line()
             .await
-            .map_err(|err| Errors::Kubernetes("getting log sync".to_string(), err.to_string()))?
-            .split("\n")
-            .filter(|line| line.len() != 0)
+            .map_err(|err| Errors::Io(format!("log stream of {}", self.id()), err))?
         {
-            let date_str = raw_line.split(" ").next().ok_or(Errors::LogError("failled to split line".to_string()))?;
-            let mut line = raw_line;
-            if !settings.timestamps {
-                line = &line[date_str.len() + 1..];
+            let raw_line = lineproc::normalize_line(&raw_line, settings.strip_ansi);
+            if raw_line.len() == 0 {
+                continue;
             }
-            let date = chrono::DateTime::parse_from_rfc3339(date_str).map_err(|err| Errors::LogError(err.to_string()))?;
-            lines.push((date, line.to_string(), self.clone()));
+            let raw_line = raw_line.as_str();
+            let (date_str, content) = raw_line.split_once(" ").unwrap_or((raw_line, ""));
+            let date = match chrono::DateTime::parse_from_rfc3339(date_str) {
+                Ok(date) => to_utc(date),
+                Err(err) if settings.strict_timestamps => {
+                    return Err(Errors::LogError(format!(
+                        "invalid timestamp {date_str} in the logs of {}: {err}",
+                        self.id()
+                    )));
+                }
+                Err(err) => {
+                    tracing::debug!(pod = %self.id(), "unparseable timestamp {date_str}: {err}");
+                    match &mut pending {
+                        Some(last) => {
+                            last.1.push('\n');
+                            last.1.push_str(raw_line);
+                        }
+                        None => pending = Some((last_date, raw_line.to_string(), pod.clone())),
+                    }
+                    continue;
+                }
+            };
+            last_date = date;
+            let line = if settings.timestamps { raw_line } else { content };
+            if let (Some(start), Some(last)) = (&settings.multiline_start, &mut pending) {
+                if !start.is_match(content) {
+                    last.1.push('\n');
+                    last.1.push_str(content);
+                    continue;
+                }
+            }
+            if let Some(last) = pending.replace((date, line.to_string(), pod.clone())) {
+                if sender.send(last).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(last) = pending {
+            let _ = sender.send(last).await;
         }
-        return Ok(lines);
+        return Ok(());
     }
 }
 
+/// a line of the logs printed before following the pods, with its date and its pod
+pub type PreviousLine = (DateTime<FixedOffset>, String, Arc<Pod>);
+
+/// the logs of a container read from the kubelet through the nodes/<name>/proxy api, for the clusters where pods/log is
+/// rate-limited or restricted
+async fn node_proxy_log_stream(
+    client: Client,
+    node: &str,
+    namespace: &str,
+    pod: &str,
+    container: &str,
+    log_params: &kube::api::LogParams,
+) -> Result<source::LogReader, kube::Error> {
+    let mut query = vec![];
+    if log_params.follow {
+        query.push("follow=true".to_string());
+    }
+    if log_params.previous {
+        query.push("previous=true".to_string());
+    }
+    if log_params.timestamps {
+        query.push("timestamps=true".to_string());
+    }
+    if let Some(since_seconds) = log_params.since_seconds {
+        query.push(format!("sinceSeconds={since_seconds}"));
+    }
+    if let Some(tail_lines) = log_params.tail_lines {
+        query.push(format!("tailLines={tail_lines}"));
+    }
+    if let Some(limit_bytes) = log_params.limit_bytes {
+        query.push(format!("limitBytes={limit_bytes}"));
+    }
+    let path = format!(
+        "/api/v1/nodes/{node}/proxy/containerLogs/{namespace}/{pod}/{container}?{}",
+        query.join("&")
+    );
+    let request = k8s_openapi::http::Request::get(path)
+        .body(vec![])
+        .map_err(|err| kube::Error::BuildRequest(kube::core::request::Error::BuildRequest(err)))?;
+    let reader = client.request_stream(request).await?;
+    return Ok(Box::pin(reader) as source::LogReader);
+}
+
+/// same instant with a +00:00 offset
+fn to_utc(date: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+    return date.with_timezone(&chrono::Utc).fixed_offset();
+}
+
+/// what the pod prefixes depend on, shared with the printers so that they can cache their prefix without locking the pods
+#[derive(Default)]
+pub struct PrefixLayout {
+    /// incremented each time the pod set changes
+    pub generation: AtomicU64,
+    /// length of the longest label
+    pub padding: AtomicUsize,
+    pub print_namespace: AtomicBool,
+}
+
 #[derive(Clone)]
 pub struct Pods {
     pub items: Vec<Pod>,
-    pub padding: usize,
-    pub print_namespace: bool,
+    pub layout: Arc<PrefixLayout>,
     pub namespaces: Namespaces,
     pub pod_search: Regex,
     pub colors: display::Colors,
+    pub short_names: bool,
+    pub statefulset_hue: bool,
+    pub namespace_hue: bool,
+    pub max_prefix_width: Option<usize>,
+    /// label of the lines read from stdin, padded like the pods
+    pub stdin_label: Option<String>,
+    pub checkpoint: Option<Checkpoint>,
+    /// ids of the pods which do not match the reloaded settings anymore, their streams must be stopped
+    pub dropped_ids: Vec<String>,
+    /// readiness changes of the followed pods seen by the refreshes, printed with the logs
+    pub readiness_changes: Vec<ReadinessChange>,
+    /// restarts of the followed pods seen by the refreshes, printed with the logs
+    pub restart_changes: Vec<RestartChange>,
+    /// reason last reported for each matched pod which is not running yet, so that it's only printed again when it changes
+    waiting: HashMap<String, String>,
+    /// matched pods which are not running yet seen by the listings, printed with the logs
+    pub waiting_changes: Vec<WaitingChange>,
+}
+
+pub struct WaitingChange {
+    pub pod_id: String,
+    /// waiting reason followed by its detail (eg: ImagePullBackOff: Back-off pulling image "api:1.2")
+    pub reason: String,
+}
+
+pub struct RestartChange {
+    pub pod_id: String,
+    pub color: Rgb,
+    pub restarts: i32,
+    /// the last failled termination of a container if any, see last_crash
+    pub crash: Option<String>,
+}
+
+pub struct ReadinessChange {
+    pub pod_id: String,
+    pub color: Rgb,
+    pub ready: bool,
+    /// why the pod is not ready, may be empty
+    pub reason: String,
 }
 
 impl Pods {
@@ -167,100 +1668,249 @@ impl Pods {
     }
 
     fn set_global_fields(&mut self) {
+        // the replicas of a statefulset are listed in the order of their ordinals (db-2 before db-10)
+        self.items.sort_by_key(|pod| pod.sort_key());
         let print_namespace = self.namespaces.items.len() > 1;
-        let mut max_len = 0;
-        let mut namespaces = HashSet::new();
+        let mut max_len = self.stdin_label.as_ref().map(|label| label.chars().count()).unwrap_or(0);
         for pod in self.items.iter() {
-            namespaces.insert(pod.namespace.name.clone());
-            let mut len = pod.name.len();
-            if print_namespace {
-                len += pod.namespace.name.len();
-            }
+            let len = pod.prefix_label(print_namespace, self.max_prefix_width).chars().count();
             if len > max_len {
                 max_len = len;
             }
         }
-        self.padding = max_len;
-        self.print_namespace = self.print_namespace;
+        self.layout.print_namespace.store(print_namespace, Ordering::Relaxed);
+        self.layout.padding.store(max_len, Ordering::Relaxed);
+        self.layout.generation.fetch_add(1, Ordering::Release);
     }
 
-    pub async fn new(namespaces: Namespaces, pod_search: &Regex, mut colors: display::Colors) -> Result<Pods, Errors> {
-        let mut pod_list = vec![];
-        let pods_mut: &mut Vec<Pod> = pod_list.as_mut();
-        for namespace in namespaces.clone().items {
-            let pod_list = namespace
-                .api
-                .list(&ListParams::default())
-                .await
-                .map_err(|err| Errors::Kubernetes(format!("get pods list on namespace {}", namespace.name), err.to_string()))?;
+    /// true if the refreshes saw changes which were not handled yet
+    pub fn has_pending_changes(&self) -> bool {
+        return self.dropped_ids.len() > 0 || self.readiness_changes.len() > 0 || self.restart_changes.len() > 0 || self.waiting_changes.len() > 0;
+    }
 
-            for pod in pod_list {
-                let name = get_pod_name(&pod);
-                if pod_search.is_match(name.as_str()) && is_pod_running(&pod) {
-                    pods_mut.push(Pod {
-                        name: name.clone(),
-                        pod_api: pod,
-                        namespace: namespace.clone(),
-                        color: colors.get_new_color(),
-                    });
+    /// the running pods of the listing made at startup, which also gave the pod count used to generate the colors
+    pub fn new(
+        namespaces: Namespaces,
+        settings: &settings::SettingsValidated,
+        mut colors: display::Colors,
+        listed: Vec<ListedPod>,
+    ) -> Result<Pods, Errors> {
+        let pod_search = &settings.pod_search;
+        if let Some(color_file) = &settings.color_file {
+            colors.load_color_file(color_file)?;
+        }
+        if settings.namespace_hue {
+            colors.set_namespace_bands(namespaces.items.iter().map(|namespace| namespace.qualified_name.clone()).collect());
+        }
+        let mut pod_list = vec![];
+        let mut waiting = vec![];
+        for listed_pod in listed {
+            if !is_pod_running(&listed_pod.pod_api) {
+                namespaces.explain(&listed_pod.namespace, &listed_pod.name, &not_running_decision(&listed_pod.pod_api));
+                if let Some(reason) = waiting_reason(&listed_pod.pod_api).filter(|_| pod_search.is_match(&listed_pod.name)) {
+                    waiting.push((format!("{}/{}", listed_pod.namespace.qualified_name, listed_pod.name), reason));
                 }
             }
+            if pod_search.is_match(listed_pod.name.as_str()) && is_pod_running(&listed_pod.pod_api) {
+                namespaces.explain(&listed_pod.namespace, &listed_pod.name, "followed");
+                let color = pod_color(
+                    &mut colors,
+                    &listed_pod.namespace,
+                    &listed_pod.pod_api,
+                    settings.statefulset_hue,
+                    settings.namespace_hue,
+                );
+                pod_list.push(Pod::new(
+                    listed_pod.name,
+                    &listed_pod.pod_api,
+                    listed_pod.namespace,
+                    color,
+                    settings.short_names,
+                    listed_pod.group_name,
+                ));
+            }
         }
         let mut pods = Pods {
             items: pod_list,
-            padding: 0,
-            print_namespace: false,
+            layout: Arc::new(PrefixLayout::default()),
             namespaces: namespaces.clone(),
             pod_search: pod_search.clone(),
             colors: colors,
+            short_names: settings.short_names,
+            statefulset_hue: settings.statefulset_hue,
+            namespace_hue: settings.namespace_hue,
+            max_prefix_width: settings.max_prefix_width,
+            stdin_label: settings.stdin.clone(),
+            checkpoint: match &settings.state_file {
+                Some(state_file) => Some(Checkpoint::load(state_file, settings.resume)?),
+                None => None,
+            },
+            dropped_ids: vec![],
+            readiness_changes: vec![],
+            restart_changes: vec![],
+            waiting: HashMap::new(),
+            waiting_changes: vec![],
         };
+        pods.set_waiting(waiting);
         pods.set_global_fields();
         return Ok(pods);
     }
 
-    pub async fn remove_pod(&mut self, pod: &Pod) {
-        if let Some(pod_idx) = self
+    /// record the timestamp of the last previous line printed for the pod
+    pub fn set_history_end(&mut self, pod_id: &str, date: DateTime<FixedOffset>) {
+        if let Some(pod) = self.items.iter_mut().find(|pod| pod.id() == pod_id) {
+            pod.history_end = Some(pod.history_end.map_or(date, |history_end| history_end.max(date)));
+        }
+    }
+
+    /// tell why a followed pod is not followed anymore, see explain
+    pub fn explain_pod(&self, pod: &Pod, decision: &str) {
+        self.namespaces.explain(&pod.namespace, &pod.name, decision);
+    }
+
+    /// only the first stream of a pod continues after its previous lines, a reconnection continues from its last line
+    pub fn take_history_end(&mut self, pod_id: &str) -> Option<DateTime<FixedOffset>> {
+        return self
+            .items
+            .iter_mut()
+            .find(|pod| pod.id() == pod_id)
+            .and_then(|pod| pod.history_end.take());
+    }
+
+    /// false when the pod was already removed, its color must then not be released again
+    pub async fn remove_pod(&mut self, pod: &Pod) -> bool {
+        let Some(pod_idx) = self
             .items
             .iter()
-            .position(|item| item.name == pod.name && item.namespace.name == pod.namespace.name)
-        {
-            self.items.remove(pod_idx);
-            self.set_global_fields();
+            .position(|item| item.name == pod.name && item.namespace.qualified_name == pod.namespace.qualified_name)
+        else {
+            return false;
+        };
+        let removed = self.items.remove(pod_idx);
+        advance_checkpoint(&mut self.checkpoint, &removed);
+        self.set_global_fields();
+        return true;
+    }
+
+    /// advance the checkpoint with the last line printed for each followed pod
+    pub fn merge_checkpoint(&mut self) {
+        for pod in self.items.iter() {
+            advance_checkpoint(&mut self.checkpoint, pod);
         }
     }
 
+    /// replace the pod search and namespaces, the pods not matching them anymore are dropped
+    pub fn reload(&mut self, namespaces: Namespaces, pod_search: Regex) {
+        let (kept, dropped): (Vec<Pod>, Vec<Pod>) = self.items.drain(..).partition(|pod| {
+            let namespace_kept = namespaces
+                .items
+                .iter()
+                .any(|namespace| namespace.qualified_name == pod.namespace.qualified_name);
+            return namespace_kept && pod_search.is_match(&pod.name);
+        });
+        for pod in dropped {
+            advance_checkpoint(&mut self.checkpoint, &pod);
+            self.colors.set_color_to_unused(pod.color);
+            self.dropped_ids.push(pod.id());
+        }
+        self.items = kept;
+        self.namespaces = namespaces;
+        self.pod_search = pod_search;
+        self.set_global_fields();
+    }
+
     fn pod_already_exists(&self, pod_name: &String, namespace: &Namespace) -> bool {
         let pod_name = pod_name.clone();
         return self
             .items
             .iter()
-            .filter(|pod| pod.name == pod_name && pod.namespace.name == namespace.name)
+            .filter(|pod| pod.name == pod_name && pod.namespace.qualified_name == namespace.qualified_name)
             .next()
             .is_some();
     }
 
+    /// keep the waiting reasons of the pods of the last listing, the new reasons are reported
+    fn set_waiting(&mut self, waiting: Vec<(String, (String, String))>) {
+        let mut previous = std::mem::take(&mut self.waiting);
+        for (pod_id, (reason, detail)) in waiting {
+            if previous.remove(&pod_id).as_ref() != Some(&reason) {
+                tracing::info!(pod = %pod_id, reason = %reason, "pod waiting to run");
+                self.waiting_changes.push(WaitingChange {
+                    pod_id: pod_id.clone(),
+                    reason: if detail.is_empty() {
+                        reason.clone()
+                    } else {
+                        format!("{reason}: {detail}")
+                    },
+                });
+            }
+            self.waiting.insert(pod_id, reason);
+        }
+    }
+
     pub async fn refresh(&mut self) -> Result<(), Errors> {
         let mut found_one = false;
+        let mut waiting = vec![];
         for namespace in self.namespaces.items.iter() {
-            let pod_list = namespace
-                .api
-                .list(&ListParams::default())
-                .await
-                .map_err(|err| Errors::Kubernetes(format!("get pods list on namespace {}", namespace.name), err.to_string()))?;
+            tracing::debug!(namespace = %namespace.name, "refreshing pods");
+            let items = &self.items;
+            let followed = |name: &str| {
+                items
+                    .iter()
+                    .any(|pod| pod.name == name && pod.namespace.qualified_name == namespace.qualified_name)
+            };
+            let pod_list = self.namespaces.list_namespace_pods(namespace, &self.pod_search, followed).await?;
 
             for pod in pod_list {
                 let name = get_pod_name(&pod);
-                if self.pod_search.is_match(name.as_str()) && is_pod_running(&pod) && !self.pod_already_exists(&name, namespace) {
+                let followed = self
+                    .items
+                    .iter_mut()
+                    .find(|followed| followed.name == name && followed.namespace.qualified_name == namespace.qualified_name);
+                if let Some(followed) = followed {
+                    let restarts = pod_restarts(&pod);
+                    if restarts > followed.restarts {
+                        let crash = last_crash(&pod);
+                        tracing::info!(pod = %followed.id(), restarts, crash = ?crash, "pod restarted");
+                        followed.restarts = restarts;
+                        self.restart_changes.push(RestartChange {
+                            pod_id: followed.id(),
+                            color: followed.color,
+                            restarts,
+                            crash,
+                        });
+                    }
+                    if let Some((ready, reason)) = pod_readiness(&pod) {
+                        if followed.ready != Some(ready) {
+                            tracing::info!(pod = %followed.id(), ready, reason = %reason, "readiness changed");
+                            followed.ready = Some(ready);
+                            self.readiness_changes.push(ReadinessChange {
+                                pod_id: followed.id(),
+                                color: followed.color,
+                                ready,
+                                reason,
+                            });
+                        }
+                        continue;
+                    }
+                }
+                if !is_pod_running(&pod) {
+                    self.namespaces.explain(namespace, &name, &not_running_decision(&pod));
+                    if let Some(reason) = waiting_reason(&pod) {
+                        waiting.push((format!("{}/{}", namespace.qualified_name, name), reason));
+                    }
+                } else if !self.pod_already_exists(&name, namespace) {
+                    tracing::info!(namespace = %namespace.name, pod = %name, "new pod found");
+                    self.namespaces.explain(namespace, &name, "followed");
                     found_one = true;
-                    self.items.push(Pod {
-                        name: name.clone(),
-                        pod_api: pod,
-                        namespace: namespace.clone(),
-                        color: self.colors.get_new_color(),
-                    });
+                    let group_name = self.namespaces.group_name(namespace, &pod).await;
+                    let color = pod_color(&mut self.colors, namespace, &pod, self.statefulset_hue, self.namespace_hue);
+                    self.items
+                        .push(Pod::new(name, &pod, namespace.clone(), color, self.short_names, group_name));
                 }
             }
         }
+        self.set_waiting(waiting);
         if found_one {
             self.set_global_fields();
         }
@@ -274,7 +1924,7 @@ pub fn new_log_param(settings: &settings::SettingsValidated, previous_line_set:
             container: None,
             limit_bytes: None,
             pretty: false,
-            previous: settings.previous,
+            previous: settings.previous || settings.previous_container.is_some(),
             follow: false,
             timestamps: true,
             since_seconds: settings.since_seconds,
@@ -287,31 +1937,221 @@ pub fn new_log_param(settings: &settings::SettingsValidated, previous_line_set:
             pretty: false,
             previous: settings.previous,
             follow: true,
-            timestamps: settings.timestamps,
+            timestamps: settings.live_timestamps(),
             since_seconds: None,
             tail_lines: Some(0),
         }
     };
 }
 
+/// fetch everything since the start of the export window, lines after its end are dropped while writing
+pub fn new_export_log_param(settings: &settings::SettingsValidated, export: &settings::Export) -> kube::api::LogParams {
+    return kube::api::LogParams {
+        since_seconds: Some((chrono::Utc::now() - export.since_time.with_timezone(&chrono::Utc)).num_seconds().max(0) + 1),
+        tail_lines: None,
+        ..new_log_param(settings, true)
+    };
+}
+
+/// continue from the last line seen by a previous invocation, lines of the first second which were already seen are skipped by the checkpoint
+pub fn resume_log_param(log_params: kube::api::LogParams, last_seen: Option<DateTime<FixedOffset>>) -> kube::api::LogParams {
+    return match last_seen {
+        Some(last_seen) => kube::api::LogParams {
+            since_seconds: Some((chrono::Utc::now() - last_seen.with_timezone(&chrono::Utc)).num_seconds().max(0) + 1),
+            tail_lines: None,
+            ..log_params
+        },
+        None => log_params,
+    };
+}
+
+/// the first lines of a pod found after startup, which were logged before its stream was opened
+pub fn backfill_log_param(log_params: kube::api::LogParams, new_pod_tail: &settings::NewPodTail) -> kube::api::LogParams {
+    return match new_pod_tail {
+        settings::NewPodTail::Lines(tail_lines) => kube::api::LogParams {
+            tail_lines: Some(*tail_lines),
+            ..log_params
+        },
+        settings::NewPodTail::Since => kube::api::LogParams {
+            tail_lines: None,
+            since_seconds: None,
+            ..log_params
+        },
+    };
+}
+
+fn advance_checkpoint(checkpoint: &mut Option<Checkpoint>, pod: &Pod) {
+    if let (Some(checkpoint), Some(last_seen)) = (checkpoint, pod.last_seen()) {
+        checkpoint.advance(&pod.id(), last_seen);
+    }
+}
+
+pub async fn save_checkpoint(pods: &types::ArcMutex<Pods>) -> Result<(), Errors> {
+    let mut pods = pods.lock().await;
+    pods.merge_checkpoint();
+    if let Some(checkpoint) = &mut pods.checkpoint {
+        checkpoint.save()?;
+    }
+    pods.colors.save_color_file()?;
+    return Ok(());
+}
+
+/// write the state file at a regular interval so that it's up to date when the process is killed
+pub async fn save_checkpoint_loop(pods: types::ArcMutex<Pods>) -> Result<(), Errors> {
+    loop {
+        tokio::time::sleep(CHECKPOINT_DELAY).await;
+        save_checkpoint(&pods).await?;
+    }
+}
+
+/// a client with the kubeconfig context it was built for
+#[derive(Clone)]
+pub struct ContextClient {
+    /// None when the current context is used
+    pub context: Option<String>,
+    pub client: Client,
+    /// false when the kubeconfig context doesn't set a namespace, in which case the client falls back to "default"
+    pub has_default_namespace: bool,
+}
+
+/// whether the context (the current one if None) sets a namespace
+fn context_has_namespace(kconf: &Kubeconfig, context: Option<&String>) -> bool {
+    let Some(context) = context.or(kconf.current_context.as_ref()) else {
+        return false;
+    };
+    return kconf
+        .contexts
+        .iter()
+        .find(|named| named.name == *context)
+        .and_then(|named| named.context.as_ref())
+        .is_some_and(|found| found.namespace.is_some());
+}
+
+/// a client for each kubeconfig context matching the context regex, or the client of the current context
+pub async fn new_clients(settings: &crate::settings::SettingsValidated) -> Result<Vec<ContextClient>, Errors> {
+    let Some(context_regex) = &settings.context_regex else {
+        let kconf = match &settings.kubeconfig {
+            Some(val) => Kubeconfig::read_from(val).ok(),
+            None => Kubeconfig::read().ok(),
+        };
+        return Ok(vec![ContextClient {
+            context: None,
+            client: new_client(settings).await?,
+            has_default_namespace: kconf.map_or(true, |kconf| context_has_namespace(&kconf, None)),
+        }]);
+    };
+    let kconf = match &settings.kubeconfig {
+        Some(val) => Kubeconfig::read_from(val),
+        None => Kubeconfig::read(),
+    }
+    .map_err(|err| Errors::KubeConfig("reading config file".to_string(), err.to_string()))?;
+    let mut clients = vec![];
+    for context in kconf.contexts.iter().filter(|context| context_regex.is_match(&context.name)) {
+        let kconfopt = KubeConfigOptions {
+            context: Some(context.name.clone()),
+            ..Default::default()
+        };
+        let conf = Config::from_custom_kubeconfig(kconf.clone(), &kconfopt)
+            .await
+            .map_err(|err| Errors::KubeConfig(format!("parsing context {}", context.name), err.to_string()))?;
+        tracing::info!(context = %context.name, "context selected");
+        clients.push(ContextClient {
+            context: Some(context.name.clone()),
+            client: client_from_config(conf)?,
+            has_default_namespace: context_has_namespace(&kconf, Some(&context.name)),
+        });
+    }
+    if clients.len() == 0 {
+        return Err(Errors::KubeConfig(
+            "selecting contexts".to_string(),
+            format!("no context matched {}", context_regex.as_str()),
+        ));
+    }
+    return Ok(clients);
+}
+
 pub async fn new_client(settings: &crate::settings::SettingsValidated) -> Result<Client, Errors> {
-    let mut conf = match &settings.kubeconfig {
+    let conf = match &settings.kubeconfig {
         Some(val) => {
-            let kconf = Kubeconfig::read_from(val).map_err(|err| Errors::Kubernetes("reading config file".to_string(), err.to_string()))?;
+            let kconf = Kubeconfig::read_from(val).map_err(|err| Errors::KubeConfig("reading config file".to_string(), err.to_string()))?;
             let kconfopt = &KubeConfigOptions::default();
             Config::from_custom_kubeconfig(kconf, kconfopt)
                 .await
-                .map_err(|err| Errors::Kubernetes("parsing config file".to_string(), err.to_string()))?
+                .map_err(|err| Errors::KubeConfig("parsing config file".to_string(), err.to_string()))?
         }
         None => Config::infer()
             .await
-            .map_err(|err| Errors::Kubernetes("getting default config".to_string(), err.to_string()))?,
+            .map_err(|err| Errors::KubeConfig("getting default config".to_string(), err.to_string()))?,
     };
+    return client_from_config(conf);
+}
+
+#[cfg(all(feature = "rustls", feature = "openssl"))]
+compile_error!("the rustls and openssl features both select the tls backend, build with --no-default-features --features openssl");
+#[cfg(not(any(feature = "rustls", feature = "openssl")))]
+compile_error!("no tls backend selected, enable the rustls or the openssl feature");
+
+/// tls backend of the kubernetes client and the http sink, selected with the cargo features
+#[cfg(feature = "rustls")]
+pub const TLS_BACKEND: &str = "rustls";
+#[cfg(feature = "openssl")]
+pub const TLS_BACKEND: &str = "openssl";
+
+/// minor version of the kubernetes api the client is built for, see the k8s-openapi feature in Cargo.toml
+pub const API_MINOR: u32 = 25;
+/// clusters older or newer by more minor versions than this are not supported, same policy as kubectl
+const MAX_MINOR_SKEW: u32 = 1;
+
+/// version of the api server of each client (eg: v1.27.3) and whether it is within the supported skew
+pub async fn server_versions(clients: &[ContextClient]) -> Vec<(Option<String>, Result<(String, bool), Errors>)> {
+    let mut versions = vec![];
+    for context_client in clients {
+        let version = context_client
+            .client
+            .apiserver_version()
+            .await
+            .map_err(|err| Errors::Kubernetes("get server version".to_string(), err))
+            .map(|info| {
+                // managed clusters suffix the minor version (eg: 27+)
+                let minor = info.minor.trim_end_matches('+').parse::<u32>().ok();
+                let supported = info.major == "1" && minor.map_or(true, |minor| minor.abs_diff(API_MINOR) <= MAX_MINOR_SKEW);
+                return (info.git_version, supported);
+            });
+        versions.push((context_client.context.clone(), version));
+    }
+    return versions;
+}
+
+/// warnings about the clusters outside of the supported skew, their streams are known to drop in odd ways
+pub async fn version_skew_warnings(clients: &[ContextClient]) -> Vec<String> {
+    let mut warnings = vec![];
+    for (context, version) in server_versions(clients).await {
+        match version {
+            Ok((version, false)) => {
+                let cluster = context.map_or("the cluster".to_string(), |context| format!("the cluster of context {context}"));
+                warnings.push(format!(
+                    "{cluster} runs kubernetes {version}, rusty_stern is built for 1.{API_MINOR} (+/- {MAX_MINOR_SKEW})"
+                ));
+            }
+            Ok(_) => {}
+            Err(err) => tracing::warn!(context = ?context, error = %err, "server version not checked"),
+        }
+    }
+    return warnings;
+}
+
+fn client_from_config(mut conf: Config) -> Result<Client, Errors> {
     conf.read_timeout = None;
     conf.write_timeout = None;
     conf.connect_timeout = None;
 
-    let client = Client::try_from(conf).map_err(|err| Errors::Kubernetes("using kubernetes configuration".to_string(), err.to_string()))?;
+    tracing::info!(
+        cluster_url = %conf.cluster_url,
+        default_namespace = %conf.default_namespace,
+        tls_backend = TLS_BACKEND,
+        "kubernetes configuration loaded"
+    );
+    let client = Client::try_from(conf).map_err(|err| Errors::KubeConfig("using kubernetes configuration".to_string(), err.to_string()))?;
     return Ok(client);
 }
 
@@ -319,3 +2159,78 @@ pub fn new_running_pods() -> types::ArcMutex<HashSet<String>> {
     let hash = HashSet::new();
     return std::sync::Arc::new(tokio::sync::Mutex::new(hash));
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn the_pending_block_is_flushed_once_the_stream_is_quiet() {
+        let (sender, receiver) = futures::channel::mpsc::unbounded::<Result<Vec<u8>, std::io::Error>>();
+        let mut stream = LogLines::new(
+            receiver.into_async_read(),
+            &lineproc::BinaryMode::Lossy,
+            Arc::new(stats::PodStats::default()),
+        );
+        let mut grouper = lineproc::MultilineGrouper::new(Regex::new(lineproc::MULTILINE_AUTO_START).unwrap());
+        sender
+            .unbounded_send(Ok(b"2024-01-01 10:00:00 error: boom\n  at main.rs:10\n  at lib".to_vec()))
+            .unwrap();
+        for _ in 0..2 {
+            let line = stream.next_line_within(Some(FLUSH_DELAY)).await.unwrap().unwrap().unwrap();
+            assert_eq!(grouper.push(line), None);
+        }
+        // the stream stays open: the delay elapses in the middle of the last line
+        assert!(stream.next_line_within(Some(FLUSH_DELAY)).await.is_none());
+        assert_eq!(grouper.flush(), Some("2024-01-01 10:00:00 error: boom\n  at main.rs:10".to_string()));
+        sender.unbounded_send(Ok(b".rs:20\n".to_vec())).unwrap();
+        let line = stream.next_line_within(Some(FLUSH_DELAY)).await.unwrap().unwrap().unwrap();
+        assert_eq!(line, "  at lib.rs:20");
+        drop(sender);
+        assert!(matches!(stream.next_line_within(Some(FLUSH_DELAY)).await, Some(Ok(None))));
+    }
+
+    fn selector(selector: &str) -> Result<String, Errors> {
+        return selector.parse::<LabelSelector>().map(|selector| selector.to_string());
+    }
+
+    #[test]
+    fn label_selectors_are_normalized_for_the_api() {
+        assert_eq!(selector("app in (a,b),!canary").unwrap(), "app in (a,b),!canary");
+        assert_eq!(
+            selector("app in ( a , b ), tier == backend, env!=prod, ! canary, app.kubernetes.io/name").unwrap(),
+            "app in (a,b),tier=backend,env!=prod,!canary,app.kubernetes.io/name"
+        );
+        assert_eq!(selector("x notin (a),version=").unwrap(), "x notin (a),version=");
+    }
+
+    #[test]
+    fn label_selectors_reject_empty_sets_and_unbalanced_parentheses() {
+        assert!(selector("x notin ()").is_err());
+        assert!(selector("x in ( )").is_err());
+        assert!(selector("app in (a,b").is_err());
+        assert!(selector("app in a,b)").is_err());
+        assert!(selector("app in ((a),b)").is_err());
+    }
+
+    #[test]
+    fn label_selectors_reject_invalid_requirements() {
+        let long_key = "a".repeat(64);
+        for invalid in [
+            "",
+            "app,",
+            ",app",
+            "-app=x",
+            "app=x-",
+            "app=a b",
+            "Example.com/app=x",
+            "a/b/c",
+            long_key.as_str(),
+        ] {
+            assert!(selector(invalid).is_err(), "{invalid} should not parse");
+        }
+        assert!(selector(&"a".repeat(63)).is_ok());
+    }
+}
@@ -0,0 +1,31 @@
+pub mod actions;
+pub mod cache;
+pub mod capture;
+pub mod checkpoint;
+pub mod config;
+pub mod control;
+pub mod display;
+pub mod doctor;
+pub mod encoder;
+pub mod error;
+pub mod kubernetes;
+pub mod lineproc;
+pub mod metrics;
+pub mod picker;
+pub mod poll;
+pub mod replay;
+pub mod retry;
+pub mod rules;
+pub mod settings;
+pub mod sinks;
+pub mod source;
+pub mod state;
+pub mod stats;
+pub mod stdin;
+pub mod tailer;
+pub mod timeline;
+pub mod types;
+pub mod writer;
+
+pub use kubernetes::LogLine;
+pub use tailer::{Tailer, TailerBuilder};
@@ -0,0 +1,143 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::error::Errors;
+
+pub static LINES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+/// lines dropped by the filters
+pub static LINES_FILTERED: AtomicU64 = AtomicU64::new(0);
+/// streams started again for a pod which was already followed
+pub static RECONNECTS: AtomicU64 = AtomicU64::new(0);
+pub static ACTIVE_STREAMS: AtomicU64 = AtomicU64::new(0);
+/// log streams which ended with an error
+pub static API_ERRORS: AtomicU64 = AtomicU64::new(0);
+/// pods found in the api cache with the same resource version, or fetched again
+pub static POD_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+pub static POD_CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+/// bytes of the lines printed and written to the output files, checked against max_output_bytes
+pub static OUTPUT_BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub fn inc(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn dec(counter: &AtomicU64) {
+    counter.fetch_sub(1, Ordering::Relaxed);
+}
+
+pub fn add(counter: &AtomicU64, value: usize) {
+    counter.fetch_add(value as u64, Ordering::Relaxed);
+}
+
+pub fn get(counter: &AtomicU64) -> u64 {
+    return counter.load(Ordering::Relaxed);
+}
+
+/// the counters are process wide, they start again from 0 with each run so that max_output_bytes and the rates
+/// only count the lines of the current one. the active streams gauge follows the streams themselves
+pub fn reset() {
+    for counter in [
+        &LINES_RECEIVED,
+        &LINES_FILTERED,
+        &RECONNECTS,
+        &API_ERRORS,
+        &POD_CACHE_HITS,
+        &POD_CACHE_MISSES,
+        &OUTPUT_BYTES,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+/// prometheus text exposition format
+fn render() -> String {
+    let metrics = [
+        (
+            "rusty_stern_lines_received_total",
+            "counter",
+            "log lines received from kubernetes",
+            &LINES_RECEIVED,
+        ),
+        (
+            "rusty_stern_lines_filtered_total",
+            "counter",
+            "log lines dropped by the filters",
+            &LINES_FILTERED,
+        ),
+        (
+            "rusty_stern_reconnects_total",
+            "counter",
+            "log streams started again for an already followed pod",
+            &RECONNECTS,
+        ),
+        ("rusty_stern_active_streams", "gauge", "log streams currently followed", &ACTIVE_STREAMS),
+        (
+            "rusty_stern_api_errors_total",
+            "counter",
+            "log streams which ended with an error",
+            &API_ERRORS,
+        ),
+        (
+            "rusty_stern_pod_cache_hits_total",
+            "counter",
+            "pods found in the api cache, which were not fetched again",
+            &POD_CACHE_HITS,
+        ),
+        (
+            "rusty_stern_pod_cache_misses_total",
+            "counter",
+            "pods missing from the api cache or changed since cached",
+            &POD_CACHE_MISSES,
+        ),
+        (
+            "rusty_stern_output_bytes_total",
+            "counter",
+            "bytes of the lines printed and written to the output files",
+            &OUTPUT_BYTES,
+        ),
+    ];
+    let mut body = String::new();
+    for (name, kind, help, value) in metrics {
+        body.push_str(&format!(
+            "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {}\n",
+            value.load(Ordering::Relaxed)
+        ));
+    }
+    return body;
+}
+
+/// pause after a failled accept of the metrics server
+const ACCEPT_RETRY_DELAY: tokio::time::Duration = tokio::time::Duration::from_millis(100);
+
+pub async fn bind(addr: &str) -> Result<tokio::net::TcpListener, Errors> {
+    return tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|err| Errors::Other(format!("binding metrics address {addr}: {err}")));
+}
+
+/// answer every request with the metrics, whatever the path. a failled accept (eg: too many open files) is logged
+/// and the next connection waited for, after a short pause so that a lasting failure doesn't spin
+pub async fn serve(listener: tokio::net::TcpListener) {
+    loop {
+        let mut socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(err) => {
+                tracing::warn!(error = %err, "accepting metrics connection failled");
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await;
+            let body = render();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{BufRead, Write};
+
+use regex::Regex;
+
+use crate::error::Errors;
+
+/// true if the query chars appear in the name in the same order
+fn fuzzy_match(query: &str, name: &str) -> bool {
+    let mut name_chars = name.chars();
+    return query.chars().all(|query_char| name_chars.any(|name_char| name_char == query_char));
+}
+
+/// parse "1 3 4" into indexes of the listed names, None if it's not a list of numbers
+fn parse_selection(input: &str, listed_cnt: usize) -> Option<Vec<usize>> {
+    let mut selection = vec![];
+    for number in input.split_whitespace() {
+        let number = number.parse::<usize>().ok()?;
+        if number == 0 || number > listed_cnt {
+            return None;
+        }
+        selection.push(number - 1);
+    }
+    return Some(selection);
+}
+
+/// let the user narrow the pods with fuzzy queries then select some of them,
+/// returns a regex matching exactly the selected pod names
+pub fn pick_pods(names: Vec<String>) -> Result<Regex, Errors> {
+    let names: Vec<String> = pick(names, "pods")?.iter().map(|name| regex::escape(name)).collect();
+    return Regex::new(&format!("^({})$", names.join("|"))).map_err(|err| Errors::Validation(err.to_string()));
+}
+
+/// let the user narrow the namespaces with fuzzy queries then select some of them
+pub fn pick_namespaces(names: Vec<String>) -> Result<Vec<String>, Errors> {
+    return pick(names, "namespaces");
+}
+
+/// what is the kind of the names (pods, namespaces) shown in the prompt
+fn pick(names: Vec<String>, what: &str) -> Result<Vec<String>, Errors> {
+    let mut stderr = std::io::stderr();
+    let stdin = std::io::stdin();
+    let mut query = String::new();
+    loop {
+        let listed: Vec<&String> = names.iter().filter(|name| fuzzy_match(&query, name)).collect();
+        for (idx, name) in listed.iter().enumerate() {
+            writeln!(stderr, "{:>3}) {}", idx + 1, name).map_err(|err| Errors::StdErr(err.to_string()))?;
+        }
+        write!(
+            stderr,
+            "query [{query}], type a new query, numbers to select {what} or enter to select all listed {what}: "
+        )
+        .map_err(|err| Errors::StdErr(err.to_string()))?;
+        stderr.flush().map_err(|err| Errors::StdErr(err.to_string()))?;
+
+        let mut input = String::new();
+        stdin
+            .lock()
+            .read_line(&mut input)
+            .map_err(|err| Errors::Other(format!("reading stdin: {err}")))?;
+        let input = input.trim();
+        let selected: Vec<&String> = if input.len() == 0 {
+            listed
+        } else {
+            match parse_selection(input, listed.len()) {
+                Some(selection) => selection.into_iter().map(|idx| listed[idx]).collect(),
+                None => {
+                    query = input.to_string();
+                    continue;
+                }
+            }
+        };
+        if selected.len() == 0 {
+            writeln!(stderr, "no {what} selected").map_err(|err| Errors::StdErr(err.to_string()))?;
+            query.clear();
+            continue;
+        }
+        return Ok(selected.into_iter().cloned().collect());
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// share of the pause added or removed at random, so that the instances started together don't list the pods at the same time
+const JITTER: f64 = 0.2;
+
+/// pause between two pod listings: it doubles after each listing which found no change, up to max, and goes back to min after a change
+pub struct PollPacer {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl PollPacer {
+    pub fn new(min: Duration, max: Duration) -> PollPacer {
+        return PollPacer {
+            min,
+            max: max.max(min),
+            current: min,
+        };
+    }
+
+    pub fn record(&mut self, changed: bool) {
+        self.current = if changed {
+            self.min
+        } else {
+            self.current.saturating_mul(2).min(self.max)
+        };
+    }
+
+    pub fn next_pause(&self) -> Duration {
+        return self.current.mul_f64(1.0 - JITTER + 2.0 * JITTER * random_ratio());
+    }
+}
+
+/// between 0 and 1, the hasher keys are random so a real random generator is not needed
+fn random_ratio() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    return (random % 10_000) as f64 / 10_000.0;
+}
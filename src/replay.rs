@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::error::Errors;
+use crate::{display, kubernetes, settings, sinks, source, types};
+
+/// a line of the replay file, as printed by the json output
+#[derive(Deserialize)]
+struct ReplayRecord {
+    context: Option<String>,
+    ns: String,
+    pod: String,
+    #[serde(default)]
+    container: String,
+    ts: Option<String>,
+    msg: String,
+    /// milliseconds since the start of the recording, only in the files written by --record
+    at: Option<u64>,
+}
+
+impl ReplayRecord {
+    /// same as the id of the pod it is replayed as
+    fn pod_id(&self) -> String {
+        return match &self.context {
+            Some(context) => format!("{}/{}/{}", context, self.ns, self.pod),
+            None => format!("{}/{}", self.ns, self.pod),
+        };
+    }
+}
+
+/// writes the lines received from the pods to the record file in the format of the replay file, with the milliseconds
+/// since the start of the recording so that the replay keeps their pace
+pub struct Recorder {
+    path: PathBuf,
+    file: std::sync::Mutex<std::fs::File>,
+    start: std::time::Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &PathBuf) -> Result<Recorder, Errors> {
+        let file =
+            std::fs::File::create(path).map_err(|err| Errors::Validation(format!("failled to create record file {}: {err}", path.display())))?;
+        return Ok(Recorder {
+            path: path.clone(),
+            file: std::sync::Mutex::new(file),
+            start: std::time::Instant::now(),
+        });
+    }
+
+    /// the line as received, before the filters, so that the replay can use other ones
+    pub fn record(&self, pod: &kubernetes::Pod, line: &str) -> Result<(), Errors> {
+        let mut record = sinks::json_record(pod, line);
+        record["at"] = serde_json::json!(self.start.elapsed().as_millis() as u64);
+        // a single write per line, so that the file is usable whenever rusty_stern is stopped
+        return self
+            .file
+            .lock()
+            .unwrap()
+            .write_all(format!("{record}\n").as_bytes())
+            .map_err(|err| Errors::Other(format!("failled to write record file {}: {err}", self.path.display())));
+    }
+}
+
+/// print the lines of a file recorded with the json output as if they were streamed by their pods, through the same filters,
+/// colors, actions and sinks, without any cluster access. the lines are printed as fast as they are read
+pub async fn replay_file(
+    settings: &settings::SettingsValidated,
+    path: &PathBuf,
+    streams_lock: &types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let content =
+        std::fs::read_to_string(path).map_err(|err| Errors::Validation(format!("failled to read replay file {}: {err}", path.display())))?;
+    let mut records = vec![];
+    for (idx, line) in content.lines().enumerate().filter(|(_, line)| line.trim().len() > 0) {
+        let record: ReplayRecord = serde_json::from_str(line)
+            .map_err(|err| Errors::Validation(format!("failled to parse line {} of replay file {}: {err}", idx + 1, path.display())))?;
+        records.push(record);
+    }
+    // the namespaces and pods in the order they first appear
+    let mut namespace_names: Vec<(Option<String>, String)> = vec![];
+    let mut pod_records: Vec<&ReplayRecord> = vec![];
+    for record in records.iter() {
+        let namespace = (record.context.clone(), record.ns.clone());
+        if !namespace_names.contains(&namespace) {
+            namespace_names.push(namespace);
+        }
+        if !pod_records.iter().any(|known| known.pod_id() == record.pod_id()) {
+            pod_records.push(record);
+        }
+    }
+    let namespaces = kubernetes::Namespaces::offline(&namespace_names, settings)?;
+    let listed = pod_records
+        .iter()
+        .filter_map(|record| {
+            let namespace = namespaces
+                .items
+                .iter()
+                .find(|namespace| namespace.context == record.context && namespace.name == record.ns)?;
+            return Some(kubernetes::ListedPod {
+                name: record.pod.clone(),
+                namespace: namespace.clone(),
+                pod_api: source::running_pod(&record.ns, &record.pod, &record.container),
+                group_name: None,
+            });
+        })
+        .collect();
+    let mut colors_params = display::ColorParams::new(settings, pod_records.len());
+    let colors = display::Colors::new(&mut colors_params);
+    let pods_lock = kubernetes::Pods::new(namespaces, settings, colors, listed)?.to_mutex();
+    let followed = pods_lock.lock().await.items.clone();
+    {
+        let mut streams = streams_lock.lock().await;
+        let message = format!("replaying {} lines of {} pods from {}", records.len(), followed.len(), path.display());
+        display::print_status(&mut streams, None, message).await?;
+    }
+    // the pods which don't match the pod search have no printer, their lines are skipped
+    let mut printers = HashMap::new();
+    for pod in followed.iter() {
+        printers.insert(pod.id(), display::PodPrinter::new(pod, settings, &pods_lock, streams_lock).await?);
+    }
+    let start = tokio::time::Instant::now();
+    for record in records {
+        // the recorded lines are replayed at their pace, divided by the speed
+        if let (Some(speed), Some(at)) = (settings.replay_speed, record.at) {
+            tokio::time::sleep_until(start + std::time::Duration::from_secs_f64(at as f64 / 1000.0 / speed)).await;
+        }
+        let pod_id = record.pod_id();
+        let Some(printer) = printers.get_mut(&pod_id) else {
+            continue;
+        };
+        // the lines recorded without timestamp are dated when they are replayed
+        let line = if settings.live_timestamps() {
+            let timestamp = record
+                .ts
+                .unwrap_or_else(|| chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Nanos, true));
+            format!("{timestamp} {}", record.msg)
+        } else {
+            record.msg
+        };
+        printer.print(line).await?;
+        if printer.max_lines_reached().is_some() || printer.max_matches_reached().is_some() {
+            printer.flush().await?;
+            printers.remove(&pod_id);
+        }
+    }
+    for printer in printers.values_mut() {
+        printer.flush().await?;
+    }
+    return Ok(());
+}
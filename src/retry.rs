@@ -0,0 +1,43 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::error::Errors;
+
+/// upper bound of the delay between two attempts
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// how many times a request failing on a transient error is done again, errors which are not retryable (eg: revoked credentials) fail at once
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// the delay doubles after each attempt
+    fn delay(&self, attempt: u32) -> Duration {
+        return self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(MAX_DELAY);
+    }
+
+    pub async fn run<T, F, Fut>(&self, what: &str, mut request: F) -> Result<T, Errors>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Errors>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match request().await {
+                Err(err) if err.is_retryable() && attempt < self.max => {
+                    attempt += 1;
+                    let delay = self.delay(attempt);
+                    tracing::warn!(request = what, error = %err, attempt, delay_ms = delay.as_millis() as u64, "transient api error, retrying");
+                    tokio::time::sleep(delay).await;
+                }
+                res => return res,
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::error::Errors;
+
+/// a named rule from the rules file, each rule can define one or several actions
+#[derive(Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub filter: Option<String>,
+    pub inv_filter: Option<String>,
+    pub replace_pattern: Option<String>,
+    pub replace_value: Option<String>,
+    pub highlight: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RulesFile {
+    pub rules: Vec<Rule>,
+}
+
+impl Rule {
+    fn validate(&self) -> Result<(), Errors> {
+        if self.filter.is_none() && self.inv_filter.is_none() && self.replace_pattern.is_none() && self.highlight.is_none() {
+            return Err(Errors::Validation(format!("rule {} does not define any action", self.name)));
+        }
+        if self.replace_pattern.is_some() != self.replace_value.is_some() {
+            return Err(Errors::Validation(format!(
+                "rule {} must define both replace_pattern and replace_value",
+                self.name
+            )));
+        }
+        return Ok(());
+    }
+}
+
+impl RulesFile {
+    pub fn load(path: &PathBuf) -> Result<RulesFile, Errors> {
+        let content =
+            std::fs::read_to_string(path).map_err(|err| Errors::Validation(format!("failled to read rules file {}: {err}", path.display())))?;
+        let rules_file: RulesFile =
+            serde_yaml::from_str(&content).map_err(|err| Errors::Validation(format!("failled to parse rules file {}: {err}", path.display())))?;
+        for rule in rules_file.rules.iter() {
+            rule.validate()?;
+        }
+        return Ok(rules_file);
+    }
+}
@@ -1,155 +1,1527 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::string::ToString;
+use std::sync::Arc;
+use std::time::Duration;
 
-use clap::Parser;
-use regex::Regex;
-
-use validator::Validate;
+use chrono::{DateTime, FixedOffset};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use regex::{Regex, RegexBuilder};
 
 use crate::{
-    display::{HueInterval, Lightness, Saturation},
-    error::Errors,
+    actions::NotifyMode,
+    config,
+    display::{
+        get_terminal_width, BannerTemplate, ContinuationMode, FocusOthers, HueInterval, HueIntervals, Lightness, OutputMode, Saturation, Template,
+    },
+    encoder,
+    error::{self, ErrorFormat, Errors},
+    kubernetes::{GroupBy, LabelSelector},
+    lineproc::{
+        validate_timestamp_format, BinaryMode, FilterProcessor, InvFilterProcessor, JsonExclude, JsonQuery, LineProcessor, RedactKind, Redactor,
+        StructuredFormat, StructuredRender, TimeZone, TimestampProcessor, MULTILINE_AUTO_START,
+    },
+    retry::RetryPolicy,
+    rules::RulesFile,
+    sinks::{ByteSize, GroupFiles, Rotation, SyslogTarget},
+    source,
+    state::RunState,
 };
 
+/// the version shows the tls backend and the kubernetes api the binary was built with, see kubernetes::TLS_BACKEND and kubernetes::API_MINOR
+#[cfg(feature = "openssl")]
+pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (tls backend: openssl, kubernetes api: 1.25)");
+#[cfg(not(feature = "openssl"))]
+pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (tls backend: rustls, kubernetes api: 1.25)");
+
+/// the binary name kubectl looks for on the PATH to run `kubectl rusty-stern`
+const KUBECTL_PLUGIN_BINARY: &str = "kubectl-rusty_stern";
+
+/// shortest pause accepted between two pod listings
+const MIN_LOOP_PAUSE: Duration = Duration::from_millis(100);
+
+/// run by kubectl as a plugin: the binary is installed as kubectl-rusty_stern, or kubectl sets KUBECTL_PLUGINS_CALLER
+pub fn is_kubectl_plugin() -> bool {
+    if std::env::var_os("KUBECTL_PLUGINS_CALLER").is_some() {
+        return true;
+    }
+    return std::env::args_os()
+        .next()
+        .and_then(|arg| PathBuf::from(arg).file_stem().map(|stem| stem == KUBECTL_PLUGIN_BINARY))
+        .unwrap_or(false);
+}
+
 #[derive(Parser, Debug, Clone)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version = VERSION, disable_version_flag = true, about, long_about = None)]
 pub struct Settings {
     /// regex to match pod names
-    #[arg(short, long, value_name = "reg pattern", default_value = ".+")]
-    pub pod_search: String,
+    #[arg(short, long, value_name = "reg pattern", default_value = ".+", value_parser = parse_regex)]
+    pub pod_search: Regex,
 
     /// path to the kubeconfig file. if the option is not passed, try to infer configuration
-    #[arg(short, long, value_name = "filepath", default_value = "")]
-    pub kubeconfig: String,
+    #[arg(short, long, value_name = "filepath")]
+    pub kubeconfig: Option<PathBuf>,
 
-    /// kubernetes namespaces to use separated by commas. default uses namespace defined in yout config file
-    #[arg(short, long, value_name = "nmspc", default_value = "")]
-    pub namespaces: String,
+    /// follow the pods of every kubeconfig context matching this regex, the context is added to the prefix (context/namespace/pod)
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex)]
+    pub context_regex: Option<Regex>,
+
+    /// kubernetes namespaces to use separated by commas. default uses the namespace of the current kubeconfig context, also as a kubectl plugin
+    /// the namespaces which don't exist yet are waited for while following the logs
+    #[arg(short, long, value_name = "nmspc", value_delimiter = ',')]
+    pub namespaces: Vec<String>,
 
     /// retrieve previous terminated container logs
     #[arg(long, default_value_t = false)]
     pub previous: bool,
 
-    /// a relative time in seconds before the current time from which to show logs
+    /// retrieve the previous terminated logs of the restarted containers matching this regex only (eg: the app and not its sidecars),
+    /// the live logs are still the ones of the default container
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex)]
+    pub previous_container: Option<Regex>,
+
+    /// a relative time in seconds before the current time from which to show logs, the pods found while following
+    /// the logs are also streamed from this date
     #[arg(long, value_name = "seconds")]
     pub since_seconds: Option<i64>,
 
+    /// a relative duration before the current time from which to show logs (eg: 30s, 15m, 2h, 1d), as since_seconds
+    #[arg(long, value_name = "duration", conflicts_with = "since_seconds", value_parser = parse_duration)]
+    pub since: Option<Duration>,
+
+    /// lines printed from the logs of the pods found after startup, logged before their stream was opened (eg: their startup errors):
+    /// a number of lines, or since for every line since the pod started. the since window takes precedence
+    #[arg(long, value_name = "n|since", value_parser = parse_value::<NewPodTail>)]
+    pub new_pod_tail: Option<NewPodTail>,
+
     /// number of lines from the end of the logs to show
     #[arg(long, value_name = "line_cnt")]
     pub tail_lines: Option<i64>,
 
+    /// number of lines from the end of the logs to show for the pods whose name matches the regex, overrides tail_lines
+    /// can be passed multiple times, the first matching regex is used (eg: "ingress-.*=0")
+    #[arg(long, value_name = "regex=line_cnt", value_parser = parse_tail_override)]
+    pub tail_for: Vec<TailOverride>,
+
     /// show timestamp at the begining of each log line
     #[arg(long, default_value_t = false)]
     pub timestamps: bool,
 
-    /// number of seconds between each pod list query (doesn't affect log line display)
-    #[arg(long, value_name = "seconds", default_value_t = 2)]
-    pub loop_pause: u64,
+    /// pause between each pod list query (doesn't affect log line display), a duration like 500ms, 2s or 1m (a bare number is in seconds),
+    /// at least 100ms
+    #[arg(long, value_name = "duration", default_value = "2s", value_parser = parse_loop_pause)]
+    pub loop_pause: Duration,
+
+    /// the pause between two pod list queries doubles while the pod set doesn't change, up to this number of seconds,
+    /// and goes back to loop_pause after a change, a random part of the pause is added or removed so that the instances don't query together
+    #[arg(long, value_name = "seconds", default_value_t = 30)]
+    pub max_loop_pause: u64,
+
+    /// number of pods requested per page when listing a namespace, so that big namespaces are not fetched in a single response (0 disables paging)
+    #[arg(long, value_name = "pods", default_value_t = 500)]
+    pub list_page_size: u32,
+
+    /// list only the metadata of the pods to find the ones to follow, the full pod is fetched for the new matching pods, which cuts
+    /// the bandwidth used on big namespaces. the readiness changes of the followed pods are not reported with it
+    #[arg(long, default_value_t = false)]
+    pub metadata_listing: bool,
+
+    /// keep the pods fetched by uid and resource version, so that a pod which did not change is not fetched again by the metadata listing,
+    /// the idle stream checks and the timeline (only its metadata is). the cache hits are shown by stats
+    #[arg(long, default_value_t = false)]
+    pub api_cache: bool,
+
+    /// number of times a pod list or log stream request failing on a transient error (throttling, server error, lost connection)
+    /// is done again before giving up, errors such as revoked credentials are never retried
+    #[arg(long, value_name = "count", default_value_t = 5)]
+    pub retry_max: u32,
+
+    /// milliseconds to wait before the first retry, doubled after each attempt (up to 30 seconds)
+    #[arg(long, value_name = "milliseconds", default_value_t = 500)]
+    pub retry_base_delay: u64,
+
+    /// reopen the log stream of a running pod from its last line when it did not send anything for this duration (eg: 5m),
+    /// for the load balancers and kubelets which silently drop idle connections
+    #[arg(long, value_name = "duration", value_parser = parse_positive_duration)]
+    pub stream_idle_timeout: Option<Duration>,
+
+    /// when the log stream of a pod did not send anything for this duration (eg: 1m), probe the last line the pod logged and
+    /// reopen the stream from its last line if the pod logged after it, for the streams which stay open but stop receiving
+    #[arg(long, value_name = "duration", value_parser = parse_positive_duration)]
+    pub stream_watchdog: Option<Duration>,
+
+    /// read the logs from the kubelets through the nodes/<name>/proxy api, for the clusters where pods/log is rate-limited or
+    /// restricted. the pods/log api is used for the streams the node proxy fails to open
+    #[arg(long, default_value_t = false)]
+    pub via_node_proxy: bool,
+
+    /// ignore the pods annotated with rusty-stern.io/skip: "true", set it to false to follow them anyway
+    #[arg(long, value_name = "bool", default_value_t = true, action = clap::ArgAction::Set)]
+    pub respect_skip_annotation: bool,
+
+    /// only follow the pods having this label value, checked on the listed pods, can be passed multiple times (all must match)
+    #[arg(long, value_name = "key=value", value_parser = parse_key_value)]
+    pub with_label: Vec<(String, String)>,
+
+    /// only follow the pods having this annotation value, checked on the listed pods, can be passed multiple times (all must match)
+    #[arg(long, value_name = "key=value", value_parser = parse_key_value)]
+    pub with_annotation: Vec<(String, String)>,
+
+    /// only follow the pods matching this label selector, with the kubectl syntax: equality (tier=backend, env!=dev),
+    /// set (app in (api,worker), app notin (db)) and existence (release, !canary) requirements separated by commas
+    #[arg(short = 'l', long, value_name = "selector", value_parser = parse_value::<LabelSelector>)]
+    pub selector: Option<LabelSelector>,
+
+    /// follow the pods of the jobs whose whole name matches this name or regex (retries included), then exit once the jobs
+    /// complete, or with an error if one of them failed
+    #[arg(long, value_name = "name|regex", conflicts_with = "no_follow", value_parser = parse_job)]
+    pub job: Option<Regex>,
+
+    /// print to stderr why each pod seen by the discovery is followed or skipped (pod search, skip annotation, labels and annotations,
+    /// job, phase), again when the decision changes
+    #[arg(long, default_value_t = false)]
+    pub explain: bool,
 
     /// hue (hsl) intervals to pick for color cycle generation
-    /// format is $start-$end(,$start-$end)* where $start>=0 and $end<=359
-    /// eg for powershell: 0-180,280-359
-    #[arg(long, value_name = "intervals", default_value = "0-359")]
-    pub hue_intervals: String,
+    /// format is $start-$end(@$sat,$light)?(,$start-$end(@$sat,$light)?)* where $start>=0 and $end<=359, the optional saturation
+    /// and lightness override the color ones for the hues of the interval, either can be left empty
+    /// eg for powershell: 0-180,280-359 or 0-60@90,40,200-260@,70
+    #[arg(long, value_name = "intervals", default_value = "0-359", value_parser = parse_value::<HueIntervals>)]
+    pub hue_intervals: Vec<HueIntervals>,
 
     /// the color saturation (0-100)
-    #[arg(long, value_name = "sat", default_value_t = 100)]
+    #[arg(long, value_name = "sat", default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
     pub color_saturation: u8,
 
-    /// the color lightness (0-100)
-    #[arg(long, value_name = "light", default_value_t = 50)]
+    /// the color lightness (0-100), the colors of the cycle vary slightly around it to tell close hues apart
+    #[arg(long, value_name = "light", default_value_t = 50, value_parser = clap::value_parser!(u8).range(0..=100))]
     pub color_lightness: u8,
 
-    /// regex string to filter output that match
-    #[arg(long, value_name = "filter", default_value = "")]
-    pub filter: String,
+    /// give the pods of a statefulset the color of their ordinal (db-0, db-1...) instead of the next one of the cycle,
+    /// so that a replica keeps its color across restarts and sessions
+    #[arg(long, default_value_t = false)]
+    pub statefulset_hue: bool,
+
+    /// give each namespace a band of the hue intervals and its pods shades of it (saturation, lightness, then hue within the band),
+    /// so that both the namespace and the pod can be told apart when following several namespaces
+    #[arg(long, default_value_t = false)]
+    pub namespace_hue: bool,
+
+    /// give the pods whose name matches the regex this color instead of the next one of the cycle, can be passed multiple times,
+    /// the first matching one is used. format is $regex=$color with a css hex color, eg: payments-.*=#ff8800
+    #[arg(long, value_name = "regex=color", value_parser = parse_color_mapping)]
+    pub color_map: Vec<ColorMapping>,
+
+    /// give the lines of the pods a style per value of this label on top of their color, eg: label=rollouts-pod-template-hash
+    /// to tell the canary pods from the stable ones of a rollout
+    #[arg(long, value_name = "label=key", value_parser = parse_split_by)]
+    pub split_by: Option<String>,
 
-    /// regex string to filter output that does not match
-    #[arg(long, value_name = "inv_filter", default_value = "")]
-    pub inv_filter: String,
+    /// styles given to the values of the split_by label in order of appearance, cycled through (comma separated)
+    #[arg(
+        long,
+        value_name = "style",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "plain,dimmed,italic,underline"
+    )]
+    pub split_styles: Vec<TextStyle>,
+
+    /// regex string to filter output that match, can be passed multiple times
+    #[arg(long, value_name = "filter", value_parser = parse_regex)]
+    pub filter: Vec<Regex>,
+
+    /// regex string to filter output that does not match, can be passed multiple times
+    #[arg(long, value_name = "inv_filter", value_parser = parse_regex)]
+    pub inv_filter: Vec<Regex>,
+
+    /// how multiple filters are combined: any (at least one must match) or all (every one must match)
+    #[arg(long, value_name = "mode", value_enum, default_value_t = FilterMode::Any)]
+    pub filter_mode: FilterMode,
+
+    /// match filter, inv_filter and highlight regexes case insensitively
+    #[arg(short, long, default_value_t = false)]
+    pub ignore_case: bool,
 
     /// regex string to replace pattern (pattern part)
-    #[arg(long, value_name = "pattern", default_value = "")]
-    pub replace_pattern: String,
+    #[arg(long, value_name = "pattern", requires = "replace_value", value_parser = parse_regex)]
+    pub replace_pattern: Option<Regex>,
 
     /// string to replace the pattern captured (or not) by replace_pattern
     /// check documentation if needed at https://docs.rs/regex/1.3.3/regex/struct.Regex.html#replacement-string-syntax
-    #[arg(long, value_name = "value", default_value = "")]
-    pub replace_value: String,
+    #[arg(long, value_name = "value", requires = "replace_pattern")]
+    pub replace_value: Option<String>,
+
+    /// render the text substituted by replace_pattern (and the rules file replaces) in italic so that transformed fields stand out
+    #[arg(long, default_value_t = false)]
+    pub replace_highlight: bool,
+
+    /// mask sensitive data before printing and writing to files and sinks, comma separated list of emails, tokens, ips, credit-cards
+    #[arg(long, value_name = "kinds", value_enum, value_delimiter = ',')]
+    pub redact: Vec<RedactKind>,
+
+    /// regex string to highlight in output, can be passed multiple times
+    #[arg(long, value_name = "highlight", value_parser = parse_regex)]
+    pub highlight: Vec<Regex>,
+
+    /// path to a yaml file containing named filter, inv_filter, replace and highlight rules
+    /// format is `rules: [{name, filter?, inv_filter?, replace_pattern?, replace_value?, highlight?}]`
+    #[arg(long, value_name = "filepath")]
+    pub rules_file: Option<PathBuf>,
+
+    /// jq like expression evaluated on json or logfmt log lines, lines that are not structured or do not match are filtered out
+    /// eg: '.level == "error" and .status >= 500'
+    #[arg(long, value_name = "query", value_parser = parse_value::<JsonQuery>)]
+    pub json_query: Option<JsonQuery>,
+
+    /// jq like expression evaluated on json or logfmt log lines, matching lines are filtered out, lines that are not structured are kept
+    /// can be passed multiple times, eg: 'logger == "healthcheck"'
+    #[arg(long, value_name = "query", value_parser = parse_value::<JsonQuery>)]
+    pub json_exclude: Vec<JsonQuery>,
+
+    /// fields to keep from structured (json or logfmt) lines separated by commas, nested fields use dots (eg: level,msg,http.status)
+    #[arg(long, value_name = "fields", value_delimiter = ',')]
+    pub fields: Vec<String>,
+
+    /// render structured (json or logfmt) lines in a single format, none keeps lines as is unless fields is set
+    #[arg(long, value_name = "format", value_enum, default_value_t = StructuredFormat::None)]
+    pub normalize: StructuredFormat,
+
+    /// regex matching the first line of a multiline message (eg stack traces), following lines are grouped with it
+    /// auto groups lines that do not start with a date
+    #[arg(long, value_name = "reg pattern", value_parser = parse_multiline_start)]
+    pub multiline_start: Option<Regex>,
+
+    /// fail when a previous log line does not start with a valid timestamp instead of appending it to the line before it
+    #[arg(long, default_value_t = false)]
+    pub strict_timestamps: bool,
+
+    /// how log lines which are not valid utf8 are printed: skip drops them, lossy replaces the invalid bytes and hex prints the line bytes
+    #[arg(long, value_name = "mode", value_enum, default_value_t = BinaryMode::Lossy)]
+    pub binary: BinaryMode,
+
+    /// remove the ansi escape sequences (colors, cursor moves) written by the containers, which break the prefix and can alter the terminal
+    #[arg(long, default_value_t = false)]
+    pub strip_ansi: bool,
+
+    /// collapse consecutive identical lines of a pod into a "last line repeated N times" message, their timestamps are ignored
+    #[arg(long, default_value_t = false)]
+    pub dedup: bool,
+
+    /// print only 1 out of n lines per pod, the number of skipped lines is shown before each printed line
+    #[arg(long, value_name = "n", value_parser = clap::value_parser!(u64).range(1..))]
+    pub sample: Option<u64>,
+
+    /// stop following a pod after n lines were printed for it
+    #[arg(long, value_name = "n")]
+    pub max_lines: Option<u64>,
+
+    /// stop following a pod after n lines matched the filters (like grep -m)
+    #[arg(short, long, value_name = "n")]
+    pub max_matches: Option<u64>,
+
+    /// exit the program as soon as a pod reached max_matches (defaults max_matches to 1)
+    #[arg(long, default_value_t = false)]
+    pub exit_on_match: bool,
+
+    /// regex string, each printed line matching it runs the exec command
+    #[arg(long, value_name = "reg pattern", requires = "exec", value_parser = parse_regex)]
+    pub on_match: Option<Regex>,
+
+    /// shell command run when a line matches on_match
+    /// the pod, namespace and line are available as RUSTY_STERN_POD, RUSTY_STERN_NAMESPACE and RUSTY_STERN_LINE env variables
+    #[arg(long, value_name = "cmd", requires = "on_match")]
+    pub exec: Option<String>,
+
+    /// regex string, each printed line matching it triggers a notification
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex)]
+    pub notify: Option<Regex>,
+
+    /// how to notify: bell rings the terminal bell, desktop sends a desktop notification
+    #[arg(long, value_name = "mode", value_enum, default_value_t = NotifyMode::Bell)]
+    pub notify_mode: NotifyMode,
+
+    /// print the existing logs of the pods found at startup and exit instead of following them
+    #[arg(long, default_value_t = false)]
+    pub no_follow: bool,
+
+    /// regex string, only print the existing lines matching it (on top of the other filters) and exit, implies no_follow
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex)]
+    pub grep: Option<Regex>,
+
+    /// stop following pods after this number of seconds
+    #[arg(long, value_name = "seconds")]
+    pub timeout: Option<u64>,
+
+    /// stop cleanly once the printed lines and the ones written to the output files reach this size (eg: 500M), with a summary
+    /// of what was received and written, so that an unattended capture can't fill the disk
+    #[arg(long, value_name = "size", value_parser = parse_value::<ByteSize>)]
+    pub max_output_bytes: Option<ByteSize>,
+
+    /// stop cleanly after this duration (eg: 8h), with the same summary as max_output_bytes
+    #[arg(long, value_name = "duration", value_parser = parse_positive_duration)]
+    pub max_duration: Option<Duration>,
+
+    /// regex string, if a printed line matches it the program exits with code 2 (use with no_follow or timeout)
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex)]
+    pub fail_on: Option<Regex>,
+
+    /// line format, available variables are {{namespace}}, {{pod}}, {{container}}, {{node}}, {{timestamp}}, {{message}} and {{padding}}
+    /// eg: '{{pod}}@{{node}}{{padding}} {{message}}'
+    #[arg(long, value_name = "template", value_parser = parse_value::<Template>)]
+    pub template: Option<Template>,
+
+    /// status message printed when a pod starts being followed instead of "+++ $id just started ($details)", an empty one disables it
+    /// available variables are {{id}}, {{namespace}}, {{pod}}, {{container}}, {{node}}, {{restarts}}, {{age}} and {{image}}
+    /// eg: 'POD_START pod={{pod}} ns={{namespace}}'
+    #[arg(long, value_name = "template", value_parser = parse_value::<BannerTemplate>)]
+    pub start_banner: Option<BannerTemplate>,
+
+    /// status message printed when a pod stops being followed instead of "--- $id gracefully stopped (maybe)", an empty one disables it,
+    /// with the variables of start_banner
+    #[arg(long, value_name = "template", value_parser = parse_value::<BannerTemplate>)]
+    pub stop_banner: Option<BannerTemplate>,
+
+    /// output format, json prints one object per line ({"ns", "pod", "container", "ts", "msg"}) without colors
+    /// and sends status messages to stderr, as do logfmt (key=value pairs) and csv (a row per line after a header)
+    /// raw prints only the log lines
+    /// wide adds the node name and the pod ip to the prefix
+    /// template formats the lines with the template setting
+    #[arg(short, long, value_name = "mode", value_enum, default_value_t = OutputMode::Default)]
+    pub output: OutputMode,
+
+    /// same as --output raw: print only the log lines, without prefix, colors or status messages
+    #[arg(long, default_value_t = false)]
+    pub only_log_lines: bool,
+
+    /// prefix printed before each line, %n is the namespace, %p the pod, %c the container and %% a literal %
+    /// the namespace is not printed if %n is omitted, even when multiple namespaces are tailed (eg: "%n/%p[%c] ")
+    #[arg(long, value_name = "format", conflicts_with = "template", value_parser = parse_prefix_format)]
+    pub prefix_format: Option<Template>,
+
+    /// print the line number of each pod after the prefix
+    #[arg(long, default_value_t = false)]
+    pub line_numbers: bool,
+
+    /// timezone used to print timestamps: local, UTC or an IANA name (eg Europe/Paris), timestamps are printed as received if not set
+    #[arg(long, value_name = "tz", value_parser = parse_value::<TimeZone>)]
+    pub timezone: Option<TimeZone>,
+
+    /// strftime format used to print timestamps (eg: "%H:%M:%S%.3f"), rfc3339 if not set
+    #[arg(long, value_name = "format", value_parser = parse_timestamp_format)]
+    pub timestamp_format: Option<String>,
+
+    /// strip the replicaset hash from pod names in the prefix (api-7f9c6d4b8-x2j4q => api-x2j4q)
+    #[arg(long, default_value_t = false)]
+    pub short_names: bool,
+
+    /// name shown in the prefix: pod, or owner for the owning workload and the pod suffix (deployment-name/x2j4q)
+    #[arg(long, value_name = "group", value_enum, default_value_t = GroupBy::Pod)]
+    pub group_by: GroupBy,
+
+    /// print the prefix only when the output switches to another pod, following lines of the same pod are indented
+    #[arg(long, default_value_t = false)]
+    pub condensed: bool,
+
+    /// print a colored rule with the pod name each time the output switches to another pod
+    #[arg(long, default_value_t = false)]
+    pub separators: bool,
+
+    /// print a dimmed rule with the time every duration (eg: 1m) before the first line of the interval, to find what happened around
+    /// a given time in a long scrollback
+    #[arg(long, value_name = "duration", value_parser = parse_positive_duration)]
+    pub time_markers: Option<Duration>,
+
+    /// truncate namespace/pod names longer than n characters in the prefix with …
+    #[arg(long, value_name = "n")]
+    pub max_prefix_width: Option<usize>,
+
+    /// wrap long lines to the terminal width, followed when it is resized, continuation lines are indented under the message
+    #[arg(long, default_value_t = false)]
+    pub wrap: bool,
+
+    /// how the lines following the first one of a message holding newlines (eg: grouped lines, stack traces) are printed:
+    /// none prints them as sent, prefix repeats the pod prefix and indent indents them to the message column behind a mark
+    #[arg(long, value_name = "mode", value_enum, default_value_t = ContinuationMode::None)]
+    pub continuation: ContinuationMode,
+
+    /// buffer live lines for the given number of milliseconds and print them sorted by timestamp across pods
+    #[arg(long, value_name = "ms", num_args = 0..=1, default_missing_value = "500", value_parser = clap::value_parser!(u64).range(1..))]
+    pub ordered: Option<u64>,
+
+    /// buffer live lines for the given number of milliseconds and print the lines received from several pods (eg: the same error from
+    /// every replica) once, with the number of times and the pods they came from. the lines are not ordered then
+    #[arg(long, value_name = "ms", num_args = 0..=1, default_missing_value = "2000", value_parser = clap::value_parser!(u64).range(1..))]
+    pub aggregate: Option<u64>,
+
+    /// the parts of the lines matching this regex are ignored when aggregating them (eg: '[0-9a-f-]{36}|\d+ms')
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex, requires = "aggregate")]
+    pub aggregate_pattern: Option<Regex>,
+
+    /// follow only this pod and print its kubernetes events and condition changes (scheduled, ready...) with its log lines,
+    /// sorted by timestamp (ordered defaults to 1000ms)
+    #[arg(long, value_name = "pod", conflicts_with = "pod_search")]
+    pub timeline: Option<String>,
+
+    /// print the lines of a file recorded with --output json as if they came from their pods (filters, colors, actions and sinks
+    /// included) and exit, without any cluster access
+    #[arg(long, value_name = "path")]
+    pub replay_file: Option<PathBuf>,
+
+    /// write the lines received from the pods, before the filters, with their pod and timing to this session file,
+    /// to print them again later with the replay command
+    #[arg(long, value_name = "path")]
+    pub record: Option<PathBuf>,
+
+    /// read the pods and their logs from this directory instead of a cluster, with a sub directory per namespace holding
+    /// <pod>.log (<pod>_<container>.log for its other containers) and an optional <pod>.yaml, the logs are printed once as with no_follow
+    #[arg(
+        long,
+        value_name = "fixture-dir",
+        conflicts_with_all = ["metadata_listing", "api_cache", "timeline", "pick", "replay_file"]
+    )]
+    pub offline: Option<PathBuf>,
+
+    /// also print the lines read from stdin (eg: a local process piped to rusty_stern) with the pod logs, prefixed by this label
+    #[arg(long, value_name = "label", num_args = 0..=1, default_missing_value = "stdin", conflicts_with_all = ["control", "pick"])]
+    pub stdin: Option<String>,
+
+    /// also write the logs of each pod to <dir>/<namespace>_<pod>_<container>.log
+    #[arg(long, value_name = "dir")]
+    pub output_dir: Option<PathBuf>,
+
+    /// rotate output files when they reach this size (eg: 50M, suffixes K, M and G are supported)
+    #[arg(long, value_name = "size", requires = "output_dir", value_parser = parse_value::<ByteSize>)]
+    pub rotate_size: Option<ByteSize>,
+
+    /// rotate output files after this number of seconds
+    #[arg(long, value_name = "seconds", requires = "output_dir")]
+    pub rotate_age: Option<u64>,
+
+    /// number of rotated files to keep for each pod
+    #[arg(long, value_name = "n", default_value_t = 5)]
+    pub rotate_keep: usize,
+
+    /// gzip the output files
+    #[arg(long, default_value_t = false, requires = "output_dir")]
+    pub compress: bool,
+
+    /// how the lines are split between the output files: by-pod, or by-owner for a file per workload (deployment, statefulset...)
+    /// merging the lines of its pods, sorted by timestamp for the previous lines and export, in the order received while following
+    #[arg(long, value_name = "grouping", value_enum, default_value_t = GroupFiles::ByPod, requires = "output_dir")]
+    pub group_files: GroupFiles,
+
+    /// post the log lines by batch as a json array to the given url
+    #[arg(long, value_name = "url", value_parser = reqwest::Url::parse)]
+    pub sink_http: Option<reqwest::Url>,
+
+    /// interval in milliseconds between two posts to the http sink
+    #[arg(long, value_name = "ms", default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub sink_http_interval: u64,
+
+    /// number of lines which triggers a post to the http sink before the interval elapsed
+    #[arg(long, value_name = "n", default_value_t = 100, value_parser = parse_positive_count)]
+    pub sink_http_batch: usize,
+
+    /// forward the log lines as rfc5424 messages to a syslog collector (tcp://host:port or udp://host:port)
+    #[arg(long, value_name = "target", value_parser = parse_value::<SyslogTarget>)]
+    pub sink_syslog: Option<SyslogTarget>,
+
+    /// store the log lines in this sqlite database (created if needed) with their context, namespace, pod, container and timestamp,
+    /// it can be searched afterwards with the query subcommand
+    #[arg(long, value_name = "path")]
+    pub sink_sqlite: Option<PathBuf>,
+
+    /// labels of the pod sent with each line to the http, syslog and sqlite sinks, along with the node and the container image
+    #[arg(long, value_name = "labels", value_delimiter = ',')]
+    pub sink_labels: Vec<String>,
+
+    /// write the lines matching this regex to the files of output_dir instead of all of them, an empty regex keeps them all
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex)]
+    pub sink_file_filter: Option<Regex>,
+
+    /// send the lines matching this regex to the http sink instead of the ones kept by the filters, an empty regex sends them all
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex)]
+    pub sink_http_filter: Option<Regex>,
+
+    /// send the lines matching this regex to the syslog sink instead of the ones kept by the filters, an empty regex sends them all
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex)]
+    pub sink_syslog_filter: Option<Regex>,
+
+    /// store the lines matching this regex in the sqlite sink instead of the ones kept by the filters, an empty regex stores them all
+    #[arg(long, value_name = "reg pattern", value_parser = parse_regex)]
+    pub sink_sqlite_filter: Option<Regex>,
+
+    /// post a json event {event, pod, detail, ts} to the given url when a followed pod appears, disappears, restarts or crashes
+    #[arg(long, value_name = "url", value_parser = reqwest::Url::parse)]
+    pub lifecycle_webhook: Option<reqwest::Url>,
+
+    /// save the timestamp of the last line seen for each pod in this file
+    #[arg(long, value_name = "path")]
+    pub state_file: Option<PathBuf>,
+
+    /// continue where the previous invocation using the same state file stopped
+    #[arg(long, default_value_t = false, requires = "state_file", conflicts_with_all = ["since_seconds", "since", "tail_lines", "tail_for"])]
+    pub resume: bool,
+
+    /// save the color given to each pod and workload in this file, the next invocations using it give them the same colors
+    #[arg(long, value_name = "path")]
+    pub color_file: Option<PathBuf>,
+
+    /// print the lines/sec and bytes/sec of each pod to stderr every n seconds, with the number of lines filtered,
+    /// dropped by the output, truncated and undecodable, and the hits of the api cache when enabled
+    #[arg(long, value_name = "seconds", num_args = 0..=1, default_missing_value = "5", value_parser = clap::value_parser!(u64).range(1..))]
+    pub stats: Option<u64>,
+
+    /// on exit (ctrl + c, timeout or end of the streams), print the lines printed, matched, reconnects and errors of each followed pod,
+    /// and the lines which were not printed: filtered, dropped by the output, truncated and undecodable
+    #[arg(long, default_value_t = false)]
+    pub summary: bool,
+
+    /// print a notice when a followed pod did not log anything for this duration (eg: 60s, 5m), and another when it logs again
+    #[arg(long, value_name = "duration", value_parser = parse_positive_duration)]
+    pub warn_idle: Option<Duration>,
+
+    /// print to stderr every duration (eg: 30s, 5m) how many pods are followed and how many lines were received since the last one,
+    /// to know during quiet periods that the streams are still alive
+    #[arg(long, value_name = "duration", value_parser = parse_positive_duration)]
+    pub heartbeat: Option<Duration>,
+
+    /// learn how many lines each pod logs every duration (eg: 10s) and print an alert in the pod color when its volume spikes
+    /// or drops to zero compared to its average over the last intervals, and another when it's back to normal
+    #[arg(long, value_name = "duration", value_parser = parse_positive_duration)]
+    pub spotlight: Option<Duration>,
+
+    /// print before each line how long after its kubernetes timestamp it was received (eg: [+1.2s]),
+    /// to tell a silent application from a delayed apiserver or logging path
+    #[arg(long, default_value_t = false)]
+    pub show_lag: bool,
+
+    /// print a notice when the lines of a followed pod are received this long (eg: 10s, 1m) after their kubernetes timestamp,
+    /// and another when they are on time again
+    #[arg(long, value_name = "duration", value_parser = parse_positive_duration)]
+    pub warn_lag: Option<Duration>,
+
+    /// expose prometheus metrics about rusty_stern itself on this address (eg: 127.0.0.1:9100)
+    #[arg(long, value_name = "addr")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// read commands on stdin while following pods: type /pattern then enter to change the live filter, / alone to remove it,
+    /// space (or nothing) then enter to pause the output and again to resume it, <n pattern to print again the last n lines received which match
+    /// the current filters and the optional pattern, f pattern to print only the lines of the pod matching the pattern and f alone to print
+    /// all of them again
+    #[arg(long, default_value_t = false)]
+    pub control: bool,
+
+    /// what happens to the lines of the other pods while the output is focused on a pod with the control command f:
+    /// buffer prints them once the focus is removed, drop doesn't print them
+    #[arg(long, value_name = "mode", value_enum, default_value_t = FocusOthers::Buffer)]
+    pub focus_others: FocusOthers,
+
+    /// number of received lines kept in memory to be printed again with the control command <n pattern, 0 disables it
+    #[arg(long, value_name = "lines", default_value_t = 1000, requires = "control")]
+    pub replay_buffer: usize,
+
+    /// list the pods matching the pod search and select the ones to follow with fuzzy queries before streaming
+    #[arg(long, default_value_t = false)]
+    pub pick: bool,
+
+    /// pipe the output through $PAGER (less by default), colors are kept
+    #[arg(long, default_value_t = false)]
+    pub pager: bool,
+
+    /// number of log lines waiting to be written when the terminal can't keep up (slow ssh, tmux copy mode), the oldest ones are
+    /// dropped above it and the number of dropped lines of each pod is reported
+    #[arg(long, value_name = "lines", default_value_t = 10000, value_parser = parse_positive_count)]
+    pub output_buffer: usize,
+
+    /// print the status messages (pods started and stopped, initial search) to stderr so that stdout only contains log lines
+    #[arg(long, default_value_t = false)]
+    pub status_to_stderr: bool,
+
+    /// when a pod flaps, only its first started and stopped messages of this window are printed, its restarts are then counted
+    /// and reported once the window is over (eg: 2m), off by default: every message is printed
+    #[arg(long, value_name = "duration", default_value = "0s", value_parser = parse_duration)]
+    pub status_window: Duration,
+
+    /// style of the errors written to stderr, on top of the color of the pod they are about (comma separated, plain for none)
+    #[arg(long, value_name = "style", value_enum, value_delimiter = ',', default_value = "bold")]
+    pub error_style: Vec<TextStyle>,
+
+    /// do not print the status messages
+    #[arg(short, long, default_value_t = false, conflicts_with = "status_to_stderr")]
+    pub quiet: bool,
+
+    /// same as the ls command, kept for the scripts using it
+    #[arg(long, default_value_t = false, hide = true)]
+    pub list: bool,
+
+    /// path to a toml config file whose keys are the long argument names, defaults to ~/.config/rusty_stern/config.toml
+    /// precedence is defaults < config file < environment < command line
+    #[arg(long, value_name = "path")]
+    pub config: Option<PathBuf>,
+
+    /// the settings of the pods of the namespaces with a [namespace.<name>] section in the config file
+    #[arg(skip)]
+    pub namespace_settings: Vec<(String, Settings)>,
+
+    /// the arguments the settings were parsed from, parsed again when the settings are reloaded
+    #[arg(skip)]
+    pub args: Vec<std::ffi::OsString>,
+
+    /// print the man page (roff format) and exit
+    #[arg(long, default_value_t = false)]
+    pub generate_man: bool,
+
+    /// print internal diagnostics to stderr (api requests, pods skipped by the search, reconnects, stream lifecycle),
+    /// -v for info and -vv for debug, RUST_LOG overrides it
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// format of the error printed to stderr when the program fails, json prints an object with the error kind, message and exit code
+    #[arg(long, value_name = "format", value_enum, default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+
+    /// print the version, then the version of the cluster of each followed context and whether it is supported
+    #[arg(short = 'V', long, default_value_t = false)]
+    pub version: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// the options are the ones of tail, the other commands use the ones related to them (eg: the pod search for ls and export)
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// follow the logs of the matching pods, the default when no command is given
+    Tail,
+    /// print the pods which would be followed (namespace, name, phase, node, color and the state, restarts and image of each container) and exit
+    /// as a json document with --output json, with the owner and workload of the pods
+    Ls,
+    /// fetch the logs of a time window, sort them by timestamp, write them to files and exit
+    Export(ExportArgs),
+    /// search the lines stored by --sink-sqlite, with a sql statement or filters
+    Query(QueryArgs),
+    /// print the lines of a session written by --record at their pace, through the current filters and display settings
+    Replay(ReplayArgs),
+    /// check the config file, the kubeconfig, the connection and authentication to the clusters, the permissions to read the logs
+    /// and the terminal, then print how to fix what failled
+    Doctor,
+    /// print the completion script of the given shell
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// print the live values starting with the prefix, used by the completion scripts
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(value_enum)]
+        kind: CompleteKind,
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum CompleteKind {
+    Namespaces,
+    Pods,
+}
+
+/// completes --namespaces and --pod-search with the values queried from the cluster
+const BASH_DYNAMIC_COMPLETION: &str = r#"
+_rusty_stern_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    case "$prev" in
+        -n|--namespaces) COMPREPLY=($(rusty_stern __complete namespaces "$cur" 2>/dev/null)); return 0 ;;
+        -p|--pod-search) COMPREPLY=($(rusty_stern __complete pods "$cur" 2>/dev/null)); return 0 ;;
+    esac
+    _rusty_stern "$@"
+}
+complete -F _rusty_stern_dynamic -o nosort -o bashdefault -o default rusty_stern
+"#;
+
+const FISH_DYNAMIC_COMPLETION: &str = r#"
+complete -c rusty_stern -s n -l namespaces -f -a "(rusty_stern __complete namespaces (commandline -ct) 2>/dev/null)"
+complete -c rusty_stern -s p -l pod-search -f -a "(rusty_stern __complete pods (commandline -ct) 2>/dev/null)"
+"#;
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportArgs {
+    /// start of the time window (rfc3339 date, eg: 2023-10-01T12:00:00Z)
+    #[arg(long, value_name = "date", value_parser = parse_date)]
+    pub since_time: DateTime<FixedOffset>,
+
+    /// end of the time window (rfc3339 date), defaults to now
+    #[arg(long, value_name = "date", value_parser = parse_date)]
+    pub until: Option<DateTime<FixedOffset>>,
+
+    /// write all the lines to this file, use --output-dir for a file per pod
+    #[arg(short, long, value_name = "path")]
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReplayArgs {
+    /// session file written by --record
+    #[arg(value_name = "path")]
+    pub file: PathBuf,
+
+    /// replay speed, eg: 4x for four times faster, 0 prints the lines without waiting
+    #[arg(long, value_name = "speed", default_value = "1x", value_parser = parse_speed)]
+    pub speed: f64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct QueryArgs {
+    /// sqlite database written by --sink-sqlite
+    #[arg(value_name = "path")]
+    pub db: PathBuf,
+
+    /// run this sql statement and print its rows tab separated instead of filtering the lines (the table is
+    /// lines(id, context, namespace, pod, container, timestamp, received, line, node, image, labels), dates are rfc3339 utc,
+    /// labels is a json object)
+    #[arg(long, value_name = "statement", conflicts_with_all = ["pod", "namespace", "grep", "since_time", "until", "limit"])]
+    pub sql: Option<String>,
+
+    /// regex to match pod names
+    #[arg(short, long, value_name = "regex", value_parser = parse_regex)]
+    pub pod: Option<Regex>,
+
+    /// only the lines of this namespace
+    #[arg(short, long, value_name = "nmspc")]
+    pub namespace: Option<String>,
+
+    /// regex to match the lines
+    #[arg(short, long, value_name = "regex", value_parser = parse_regex)]
+    pub grep: Option<Regex>,
+
+    /// only the lines logged from this date (rfc3339 date, eg: 2023-10-01T12:00:00Z)
+    #[arg(long, value_name = "date", value_parser = parse_date)]
+    pub since_time: Option<DateTime<FixedOffset>>,
+
+    /// only the lines logged until this date (rfc3339 date)
+    #[arg(long, value_name = "date", value_parser = parse_date)]
+    pub until: Option<DateTime<FixedOffset>>,
+
+    /// print at most this many lines
+    #[arg(long, value_name = "n")]
+    pub limit: Option<u64>,
+}
+
+/// what the tailer does once the pods are listed, from the command
+#[derive(Debug, Clone)]
+pub enum Mode {
+    Tail,
+    Ls,
+    Export(Export),
+}
+
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub since_time: DateTime<FixedOffset>,
+    pub until: Option<DateTime<FixedOffset>>,
+    pub file: Option<PathBuf>,
+}
+
+/// the value parsers run while clap parses the arguments, so that an invalid value is reported with its argument and usage
+fn validation_message(err: Errors) -> String {
+    return match err {
+        Errors::Validation(message) => message,
+        err => err.to_string(),
+    };
+}
+
+/// value parser for the types checked by their FromStr implementation
+fn parse_value<T: FromStr<Err = Errors>>(value: &str) -> Result<T, String> {
+    return T::from_str(value).map_err(validation_message);
+}
+
+fn parse_regex(pattern: &str) -> Result<Regex, String> {
+    return Regex::new(pattern).map_err(|err| err.to_string());
+}
+
+/// the job must match the whole name, so that a job name does not also match the jobs it prefixes
+fn parse_job(job: &str) -> Result<Regex, String> {
+    return parse_regex(&format!("^(?:{job})$"));
+}
+
+fn parse_multiline_start(pattern: &str) -> Result<Regex, String> {
+    return match pattern {
+        "auto" => parse_regex(MULTILINE_AUTO_START),
+        pattern => parse_regex(pattern),
+    };
+}
+
+pub fn parse_date(date: &str) -> Result<DateTime<FixedOffset>, String> {
+    return DateTime::parse_from_rfc3339(date).map_err(|err| format!("expected a rfc3339 date (eg: 2023-10-01T12:00:00Z): {err}"));
+}
+
+/// parse a duration like 90s, 15m, 2h or 1d
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let (number, multiplier) = match duration.chars().last() {
+        Some('s') => (&duration[..duration.len() - 1], 1),
+        Some('m') => (&duration[..duration.len() - 1], 60),
+        Some('h') => (&duration[..duration.len() - 1], 60 * 60),
+        Some('d') => (&duration[..duration.len() - 1], 60 * 60 * 24),
+        _ => return Err("expected a number followed by s, m, h or d (eg: 90s, 15m, 2h, 1d)".to_string()),
+    };
+    let value = number
+        .parse::<u64>()
+        .map_err(|err| format!("expected a number followed by s, m, h or d: {err}"))?;
+    let seconds = value.checked_mul(multiplier).ok_or_else(|| format!("{duration} is too long"))?;
+    return Ok(Duration::from_secs(seconds));
+}
+
+/// durations between two checks, which would run continuously with 0
+fn parse_positive_duration(duration: &str) -> Result<Duration, String> {
+    let value = parse_duration(duration)?;
+    if value.is_zero() {
+        return Err("must be greater than 0".to_string());
+    }
+    return Ok(value);
+}
+
+/// the pause between two pod listings, a bare number being seconds like before the durations were accepted,
+/// a shorter pause would flood the api server with list queries
+fn parse_loop_pause(duration: &str) -> Result<Duration, String> {
+    // milliseconds are only accepted here, the other durations are sent to the api or compared in seconds
+    let value = match (duration.parse::<u64>(), duration.strip_suffix("ms")) {
+        (Ok(seconds), _) => Duration::from_secs(seconds),
+        (Err(_), Some(millis)) => {
+            let millis = millis.parse::<u64>().map_err(|err| format!("expected a number followed by ms: {err}"))?;
+            Duration::from_millis(millis)
+        }
+        (Err(_), None) => parse_duration(duration)?,
+    };
+    if value < MIN_LOOP_PAUSE {
+        return Err(format!("must be at least {}ms", MIN_LOOP_PAUSE.as_millis()));
+    }
+    return Ok(value);
+}
+
+fn parse_positive_count(count: &str) -> Result<usize, String> {
+    return match count.parse::<usize>() {
+        Ok(0) => Err("must be greater than 0".to_string()),
+        Ok(value) => Ok(value),
+        Err(err) => Err(err.to_string()),
+    };
+}
+
+fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    return match value.split_once("=") {
+        Some((key, value)) if key != "" => Ok((key.to_string(), value.to_string())),
+        _ => Err("expected key=value".to_string()),
+    };
+}
+
+fn parse_speed(speed: &str) -> Result<f64, String> {
+    let value = speed.strip_suffix("x").unwrap_or(speed);
+    return match value.parse::<f64>() {
+        Ok(value) if value >= 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!("invalid speed {speed}, expected a positive number like 4x or 0.5x")),
+    };
+}
+
+/// only labels can split the pods for now, the key is kept
+fn parse_split_by(value: &str) -> Result<String, String> {
+    return match value.split_once("=") {
+        Some(("label", key)) if key != "" => Ok(key.to_string()),
+        _ => Err("expected label=key".to_string()),
+    };
+}
+
+fn parse_tail_override(value: &str) -> Result<TailOverride, String> {
+    // the count can't contain an equal sign, the regex can
+    let Some((pattern, tail_lines)) = value.rsplit_once("=").filter(|(pattern, _)| *pattern != "") else {
+        return Err("expected regex=line_cnt".to_string());
+    };
+    return Ok(TailOverride {
+        pattern: Regex::new(pattern).map_err(|err| format!("invalid regex {pattern}: {err}"))?,
+        tail_lines: tail_lines
+            .parse::<u32>()
+            .map_err(|err| format!("invalid line count {tail_lines}: {err}"))? as i64,
+    });
+}
+
+fn parse_color_mapping(value: &str) -> Result<ColorMapping, String> {
+    // the color can't contain an equal sign, the regex can
+    let Some((pattern, color)) = value.rsplit_once("=").filter(|(pattern, _)| *pattern != "") else {
+        return Err("expected regex=color".to_string());
+    };
+    return Ok(ColorMapping {
+        pattern: Regex::new(pattern).map_err(|err| format!("invalid regex {pattern}: {err}"))?,
+        color: colors_transform::Rgb::from_hex_str(color).map_err(|_| format!("invalid color {color}, expected a css hex color"))?,
+    });
+}
+
+fn parse_prefix_format(prefix_format: &str) -> Result<Template, String> {
+    return parse_value::<Template>(&prefix_format_to_template(prefix_format).map_err(validation_message)?);
+}
+
+fn parse_timestamp_format(format: &str) -> Result<String, String> {
+    validate_timestamp_format(format).map_err(validation_message)?;
+    return Ok(format.to_string());
 }
 
 impl Settings {
     pub fn to_validated(self) -> Result<SettingsValidated, Errors> {
-        let pod_search = Regex::new(self.pod_search.as_str()).map_err(|err| Errors::Validation(err.to_string()))?;
-        let kubeconfig = if self.kubeconfig == "".to_string() {
-            None
-        } else {
-            Some(PathBuf::from_str(self.kubeconfig.as_str()).map_err(|err| Errors::Other(err.to_string()))?)
-        };
-        let namespaces = if self.namespaces == "" {
-            vec![]
-        } else {
-            self.namespaces.split(",").map(|s| s.to_string()).collect()
-        };
-        let hue_intervals = self.get_hue_intervals()?;
+        return self.to_validated_with_state(Arc::new(RunState::default()), None);
+    }
+
+    /// the settings of the namespaces share the state of the settings they are part of, section is the name of their namespace
+    fn to_validated_with_state(self, state: Arc<RunState>, section: Option<String>) -> Result<SettingsValidated, Errors> {
         let color_saturation = Saturation {
             value: self.color_saturation,
         };
-        color_saturation.validate().map_err(|err| Errors::Validation(err.to_string()))?;
         let color_lightness = Lightness { value: self.color_lightness };
-        color_lightness.validate().map_err(|err| Errors::Validation(err.to_string()))?;
 
-        let filter = if self.filter == "".to_string() {
-            None
-        } else {
-            Some(Regex::new(self.filter.as_str()).map_err(|err| Errors::Validation(err.to_string()))?)
+        // the regexes are compiled again once ignore_case is known, they were already checked by the value parsers
+        let grep = match &self.grep {
+            Some(pattern) => Some(new_regex(pattern.as_str(), self.ignore_case)?),
+            None => None,
         };
-        let inv_filter = if self.inv_filter == "".to_string() {
-            None
-        } else {
-            Some(Regex::new(self.inv_filter.as_str()).map_err(|err| Errors::Validation(err.to_string()))?)
+        let mut filter = Filter {
+            includes: vec![],
+            excludes: vec![],
+            mode: self.filter_mode.clone(),
+            grep: grep.clone(),
+        };
+        for pattern in self.filter.iter().filter(|pattern| pattern.as_str().len() > 0) {
+            filter.includes.push(new_regex(pattern.as_str(), self.ignore_case)?);
+        }
+        for pattern in self.inv_filter.iter().filter(|pattern| pattern.as_str().len() > 0) {
+            filter.excludes.push(new_regex(pattern.as_str(), self.ignore_case)?);
+        }
+
+        let mut replace = vec![];
+        if let (Some(pattern), Some(value)) = (&self.replace_pattern, &self.replace_value) {
+            replace.push(Replace {
+                pattern: pattern.clone(),
+                value: value.clone(),
+                highlight: self.replace_highlight,
+            });
+        }
+        let mut highlight: Vec<Regex> = grep.into_iter().collect();
+        for pattern in self.highlight.iter().filter(|pattern| pattern.as_str().len() > 0) {
+            highlight.push(new_regex(pattern.as_str(), self.ignore_case)?);
+        }
+
+        if let Some(rules_file) = &self.rules_file {
+            for rule in RulesFile::load(rules_file)?.rules {
+                if let Some(pattern) = &rule.filter {
+                    filter.includes.push(new_regex(pattern, self.ignore_case)?);
+                }
+                if let Some(pattern) = &rule.inv_filter {
+                    filter.excludes.push(new_regex(pattern, self.ignore_case)?);
+                }
+                if let (Some(pattern), Some(value)) = (&rule.replace_pattern, &rule.replace_value) {
+                    replace.push(Replace {
+                        pattern: Regex::new(pattern).map_err(|err| Errors::Validation(format!("rule {}: {err}", rule.name)))?,
+                        value: value.clone(),
+                        highlight: self.replace_highlight,
+                    });
+                }
+                if let Some(pattern) = &rule.highlight {
+                    highlight.push(new_regex(pattern, self.ignore_case)?);
+                }
+            }
+        }
+
+        let structured_render = StructuredRender {
+            fields: self.fields.clone(),
+            format: self.normalize.clone(),
+        };
+
+        let on_match = match (&self.on_match, &self.exec) {
+            (Some(pattern), Some(command)) => Some(OnMatch {
+                pattern: new_regex(pattern.as_str(), self.ignore_case)?,
+                command: command.clone(),
+            }),
+            _ => None,
+        };
+
+        let notify = match &self.notify {
+            Some(pattern) => Some(new_regex(pattern.as_str(), self.ignore_case)?),
+            None => None,
+        };
+
+        let fail_on = match &self.fail_on {
+            Some(pattern) => Some(new_regex(pattern.as_str(), self.ignore_case)?),
+            None => None,
+        };
+
+        let sink_filter = |pattern: &Option<Regex>| -> Result<Option<Regex>, Errors> {
+            return match pattern {
+                Some(pattern) => Ok(Some(new_regex(pattern.as_str(), self.ignore_case)?)),
+                None => Ok(None),
+            };
+        };
+        let sink_file_filter = sink_filter(&self.sink_file_filter)?;
+        let sink_http_filter = sink_filter(&self.sink_http_filter)?;
+        let sink_syslog_filter = sink_filter(&self.sink_syslog_filter)?;
+        let sink_sqlite_filter = sink_filter(&self.sink_sqlite_filter)?;
+
+        let output = if self.only_log_lines { OutputMode::Raw } else { self.output.clone() };
+
+        let rotation = Rotation {
+            size: self.rotate_size,
+            age: self.rotate_age.map(std::time::Duration::from_secs),
+            keep: self.rotate_keep,
+        };
+
+        let mode = match &self.command {
+            Some(Command::Export(export)) => {
+                if export.file.is_none() && self.output_dir.is_none() {
+                    return Err(Errors::Validation("export needs --file or --output-dir".to_string()));
+                }
+                Mode::Export(Export {
+                    since_time: export.since_time,
+                    until: export.until,
+                    file: export.file.clone(),
+                })
+            }
+            Some(Command::Ls) => Mode::Ls,
+            _ if self.list => Mode::Ls,
+            _ => Mode::Tail,
+        };
+
+        // the replay command is the replay file at the pace of the recording
+        let (replay_file, replay_speed) = match &self.command {
+            Some(Command::Replay(replay)) => (Some(replay.file.clone()), Some(replay.speed).filter(|speed| *speed > 0.0)),
+            _ => (self.replay_file, None),
+        };
+
+        let since_seconds = match &self.since {
+            Some(since) => Some(since.as_secs() as i64),
+            None => self.since_seconds,
         };
 
-        let replace = if self.replace_pattern.len() > 0 && self.replace_value.len() > 0 {
-            Some(Replace {
-                pattern: Regex::new(&self.replace_pattern).map_err(|err| Errors::Validation(err.to_string()))?,
-                value: self.replace_value,
-            })
+        let config_path = config::resolve_config_path(self.config.as_ref());
+
+        // filters first so that the other processors only run on kept lines, replacements last
+        let mut processors: Vec<Arc<dyn LineProcessor>> = vec![
+            Arc::new(FilterProcessor {
+                filter: filter.clone(),
+                section: section.clone(),
+                state: state.clone(),
+            }),
+            Arc::new(InvFilterProcessor {
+                filter: filter.clone(),
+                section,
+                state: state.clone(),
+            }),
+        ];
+        if let Some(json_query) = &self.json_query {
+            processors.push(Arc::new(json_query.clone()));
+        }
+        if self.json_exclude.len() > 0 {
+            processors.push(Arc::new(JsonExclude {
+                queries: self.json_exclude.clone(),
+            }));
+        }
+        // the sinks with their own filter get the lines transformed like the printed ones, without any of the filtering processors
+        let mut sink_processors: Vec<Arc<dyn LineProcessor>> = vec![
+            Arc::new(TimestampProcessor {
+                timezone: self.timezone.clone(),
+                format: self.timestamp_format.clone(),
+            }),
+            Arc::new(structured_render.clone()),
+        ];
+        for replace in replace.iter() {
+            sink_processors.push(Arc::new(replace.clone()));
+        }
+        // last so that nothing added by the other processors escapes it
+        let redactor = if self.redact.len() > 0 {
+            Some(Redactor::new(&self.redact))
         } else {
             None
         };
+        if let Some(redactor) = &redactor {
+            sink_processors.push(Arc::new(redactor.clone()));
+        }
+        processors.extend(sink_processors.iter().cloned());
+        let template = self.template.or(self.prefix_format);
+        let encoder = encoder::new_encoder(&output, &template)?;
+        let mut namespace_settings = HashMap::new();
+        for (namespace, settings) in self.namespace_settings {
+            let settings = settings.to_validated_with_state(state.clone(), Some(namespace.clone()))?;
+            namespace_settings.insert(namespace, settings);
+        }
 
         return Ok(SettingsValidated {
-            pod_search,
-            kubeconfig,
-            namespaces: namespaces,
+            pod_search: match &self.timeline {
+                Some(pod) => new_regex(&format!("^{}$", regex::escape(pod)), false)?,
+                None => self.pod_search,
+            },
+            kubeconfig: self.kubeconfig,
+            context_regex: self.context_regex,
+            namespaces: self.namespaces,
             previous: self.previous,
-            since_seconds: self.since_seconds,
+            previous_container: self.previous_container,
+            since_seconds,
+            new_pod_tail: self.new_pod_tail,
+            since_time: since_seconds.map(|seconds| (chrono::Utc::now() - chrono::Duration::seconds(seconds)).fixed_offset()),
             tail_lines: self.tail_lines,
-            timestamps: self.timestamps,
+            tail_for: self.tail_for,
+            // json output always carries the timestamp in its own field
+            // the structured outputs have a timestamp field
+            timestamps: self.timestamps || [OutputMode::Json, OutputMode::Logfmt, OutputMode::Csv].contains(&output),
             loop_pause: self.loop_pause,
-            hue_intervals,
+            max_loop_pause: Duration::from_secs(self.max_loop_pause).max(self.loop_pause),
+            list_page_size: self.list_page_size,
+            metadata_listing: self.metadata_listing,
+            api_cache: self.api_cache,
+            retry: RetryPolicy {
+                max: self.retry_max,
+                base_delay: std::time::Duration::from_millis(self.retry_base_delay),
+            },
+            stream_idle_timeout: self.stream_idle_timeout,
+            stream_watchdog: self.stream_watchdog,
+            via_node_proxy: self.via_node_proxy,
+            respect_skip_annotation: self.respect_skip_annotation,
+            with_labels: self.with_label,
+            with_annotations: self.with_annotation,
+            selector: self.selector,
+            job: self.job,
+            explain: self.explain,
+            hue_intervals: self.hue_intervals.into_iter().flat_map(|intervals| intervals.0).collect(),
             color_saturation,
             color_lightness,
+            statefulset_hue: self.statefulset_hue,
+            namespace_hue: self.namespace_hue,
+            color_map: self.color_map,
+            split_by: self.split_by,
+            split_styles: self.split_styles,
             filter,
-            inv_filter,
             replace,
+            replace_highlight: self.replace_highlight,
+            redactor,
+            highlight,
+            json_query: self.json_query,
+            json_exclude: self.json_exclude,
+            structured_render,
+            multiline_start: self.multiline_start,
+            strict_timestamps: self.strict_timestamps,
+            binary: self.binary,
+            strip_ansi: self.strip_ansi,
+            dedup: self.dedup,
+            sample: self.sample,
+            max_lines: self.max_lines,
+            max_matches: if self.exit_on_match {
+                self.max_matches.or(Some(1))
+            } else {
+                self.max_matches
+            },
+            exit_on_match: self.exit_on_match,
+            on_match,
+            notify,
+            notify_mode: self.notify_mode.clone(),
+            no_follow: self.no_follow || self.grep.is_some() || self.offline.is_some(),
+            timeout: self.timeout,
+            max_output_bytes: self.max_output_bytes,
+            max_duration: self.max_duration,
+            fail_on,
+            template,
+            start_banner: self.start_banner,
+            stop_banner: self.stop_banner,
+            output,
+            encoder,
+            line_numbers: self.line_numbers,
+            timezone: self.timezone,
+            timestamp_format: self.timestamp_format,
+            short_names: self.short_names,
+            group_by: self.group_by,
+            condensed: self.condensed,
+            separators: self.separators,
+            time_markers: self.time_markers,
+            max_prefix_width: self.max_prefix_width,
+            wrap_width: if self.wrap { get_terminal_width() } else { None },
+            continuation: self.continuation,
+            ordered: if self.timeline.is_some() {
+                self.ordered.or(Some(1000))
+            } else {
+                self.ordered
+            },
+            aggregate: self.aggregate,
+            aggregate_pattern: self.aggregate_pattern,
+            timeline: self.timeline,
+            replay_file,
+            replay_speed,
+            record: self.record,
+            offline: self.offline,
+            stdin: self.stdin,
+            output_dir: self.output_dir,
+            rotation,
+            compress: self.compress,
+            group_files: self.group_files,
+            sink_http: self.sink_http,
+            sink_http_interval: self.sink_http_interval,
+            sink_http_batch: self.sink_http_batch,
+            sink_syslog: self.sink_syslog,
+            sink_sqlite: self.sink_sqlite.clone(),
+            sink_labels: self.sink_labels,
+            sink_file_filter,
+            sink_http_filter,
+            sink_syslog_filter,
+            sink_sqlite_filter,
+            lifecycle_webhook: self.lifecycle_webhook,
+            state_file: self.state_file,
+            resume: self.resume,
+            color_file: self.color_file,
+            mode,
+            stats: self.stats,
+            summary: self.summary,
+            warn_idle: self.warn_idle,
+            heartbeat: self.heartbeat,
+            spotlight: self.spotlight,
+            show_lag: self.show_lag,
+            warn_lag: self.warn_lag,
+            metrics_addr: self.metrics_addr.map(|metrics_addr| metrics_addr.to_string()),
+            ignore_case: self.ignore_case,
+            control: self.control,
+            focus_others: self.focus_others,
+            pick: self.pick,
+            pager: self.pager,
+            output_buffer: self.output_buffer,
+            replay_buffer: if self.control { self.replay_buffer } else { 0 },
+            status_to_stderr: self.status_to_stderr,
+            status_window: Some(self.status_window).filter(|window| !window.is_zero()),
+            error_style: self.error_style,
+            quiet: self.quiet,
+            config_path,
+            args: self.args,
+            namespace_settings,
+            processors,
+            sink_processors,
+            sources: vec![],
+            state,
+        });
+    }
+
+    /// parse the command line a first time to know which arguments the config file must not override, then parse it with the config arguments
+    pub fn do_parse() -> Result<Settings, Errors> {
+        let args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+        if let Err(err) = Settings::new_command().try_get_matches_from(args.clone()) {
+            // help and version are reported as errors by clap, an invalid value exits like the other configuration errors
+            if err.use_stderr() {
+                let _ = err.print();
+                std::process::exit(error::EXIT_CONFIG);
+            }
+            err.exit();
+        }
+        return Settings::parse_from_args(args);
+    }
+
+    /// same as do_parse with the given arguments, an invalid argument is returned as an error instead of exiting
+    pub fn parse_from_args(args: Vec<std::ffi::OsString>) -> Result<Settings, Errors> {
+        let command = Settings::new_command();
+        let matches = command
+            .clone()
+            .try_get_matches_from(args.clone())
+            .map_err(|err| Errors::Validation(err.to_string()))?;
+        let config_path = config::resolve_config_path(matches.get_one::<PathBuf>("config"));
+        // the doctor reports an invalid config file itself, it runs without it
+        let config_path = match (&config_path, matches.subcommand_name()) {
+            (Some(config_path), Some("doctor")) if Settings::check_config(config_path).is_err() => None,
+            _ => config_path,
+        };
+        let (config_args, namespace_config_args) = match config_path {
+            Some(config_path) => (
+                config::config_args(&config_path, &command, &matches)?,
+                config::namespace_config_args(&config_path, &command, &matches)?,
+            ),
+            None => (vec![], vec![]),
+        };
+        let mut settings = Settings::parse_with_config(&command, &args, config_args)?;
+        for (namespace, config_args) in namespace_config_args {
+            let namespace_settings = Settings::parse_with_config(&command, &args, config_args)
+                .map_err(|err| Errors::Validation(format!("config section namespace.{namespace}: {err}")))?;
+            settings.namespace_settings.push((namespace, namespace_settings));
+        }
+        settings.args = args;
+        return Ok(settings);
+    }
+
+    /// parse the config file and its namespace sections as if no argument was passed on the command line
+    pub fn check_config(config_path: &PathBuf) -> Result<(), Errors> {
+        let command = Settings::new_command();
+        let args = vec![std::ffi::OsString::from(command.get_name())];
+        let matches = command
+            .clone()
+            .try_get_matches_from(args.clone())
+            .map_err(|err| Errors::Validation(err.to_string()))?;
+        Settings::parse_with_config(&command, &args, config::config_args(config_path, &command, &matches)?)?;
+        for (namespace, config_args) in config::namespace_config_args(config_path, &command, &matches)? {
+            Settings::parse_with_config(&command, &args, config_args)
+                .map_err(|err| Errors::Validation(format!("config section namespace.{namespace}: {err}")))?;
+        }
+        return Ok(());
+    }
+
+    /// the config arguments go before the command line ones
+    fn parse_with_config(command: &clap::Command, args: &[std::ffi::OsString], config_args: Vec<std::ffi::OsString>) -> Result<Settings, Errors> {
+        let mut full_args = args[..1].to_vec();
+        full_args.extend(config_args);
+        full_args.extend(args[1..].iter().cloned());
+        // an invalid value in the config file must not exit the program when the file is reloaded
+        let matches = command
+            .clone()
+            .try_get_matches_from(full_args)
+            .map_err(|err| Errors::Validation(format!("invalid config file value: {err}")))?;
+        return Settings::from_arg_matches(&matches).map_err(|err| Errors::Validation(err.to_string()));
+    }
+
+    /// every argument can also be set with a RUSTY_STERN_<ARGUMENT> environment variable (eg: RUSTY_STERN_POD_SEARCH)
+    /// the usage shows kubectl rusty-stern when run as a kubectl plugin
+    fn new_command() -> clap::Command {
+        let command = if is_kubectl_plugin() {
+            Settings::command().bin_name("kubectl rusty-stern")
+        } else {
+            Settings::command()
+        };
+        return command.mut_args(|arg| {
+            let id = arg.get_id().as_str();
+            if arg.is_positional() || id == "help" || id == "version" {
+                return arg;
+            }
+            // the command is built once per run, leaking the names is fine
+            let env: &'static str = Box::leak(format!("RUSTY_STERN_{}", id.to_uppercase()).into_boxed_str());
+            return arg.env(env);
         });
     }
 
-    pub fn do_parse() -> Settings {
-        Settings::parse()
+    pub fn print_man_page() -> Result<(), Errors> {
+        return clap_mangen::Man::new(Settings::new_command())
+            .render(&mut std::io::stdout())
+            .map_err(|err| Errors::StdErr(err.to_string()));
+    }
+
+    pub fn print_completions(shell: clap_complete::Shell) {
+        let mut command = Settings::new_command();
+        let name = command.get_name().to_string();
+        clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+        match shell {
+            clap_complete::Shell::Bash => print!("{BASH_DYNAMIC_COMPLETION}"),
+            clap_complete::Shell::Fish => print!("{FISH_DYNAMIC_COMPLETION}"),
+            _ => {}
+        }
+    }
+}
+
+/// translate a prefix format (%n/%p[%c]) to the equivalent template, followed by the padding and the message
+fn prefix_format_to_template(prefix_format: &str) -> Result<String, Errors> {
+    let mut template = String::new();
+    let mut chars = prefix_format.chars();
+    while let Some(current) = chars.next() {
+        if current != '%' {
+            template.push(current);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => template.push_str("{{namespace}}"),
+            Some('p') => template.push_str("{{pod}}"),
+            Some('c') => template.push_str("{{container}}"),
+            Some('%') => template.push('%'),
+            Some(other) => {
+                return Err(Errors::Validation(format!(
+                    "unknown placeholder %{other} in prefix format {prefix_format}"
+                )))
+            }
+            None => return Err(Errors::Validation(format!("prefix format {prefix_format} ends with a lone %"))),
+        }
+    }
+    template.push_str("{{padding}}{{message}}");
+    return Ok(template);
+}
+
+pub fn new_regex(pattern: &str, ignore_case: bool) -> Result<Regex, Errors> {
+    return RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|err| Errors::Validation(err.to_string()));
+}
+
+/// backfill of the pods found after startup, see new_pod_tail
+#[derive(Debug, Clone, PartialEq)]
+pub enum NewPodTail {
+    Lines(i64),
+    Since,
+}
+
+impl FromStr for NewPodTail {
+    type Err = Errors;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        if string == "since" {
+            return Ok(NewPodTail::Since);
+        }
+        return match string.parse::<u32>() {
+            Ok(tail_lines) => Ok(NewPodTail::Lines(tail_lines as i64)),
+            Err(_) => Err(Errors::Validation(format!(
+                "invalid new pod tail {string}, expected a number of lines or since"
+            ))),
+        };
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum TextStyle {
+    Bold,
+    Underline,
+    Italic,
+    Dimmed,
+    Intense,
+    Plain,
+}
+
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum FilterMode {
+    Any,
+    All,
+}
+
+#[derive(Clone)]
+pub struct Filter {
+    pub includes: Vec<Regex>,
+    pub excludes: Vec<Regex>,
+    pub mode: FilterMode,
+    /// must always match, whatever the filter mode
+    pub grep: Option<Regex>,
+}
+
+impl Filter {
+    /// true if the line passes both the filters and the inverted filters
+    pub fn is_match(&self, line: &str) -> bool {
+        return self.keeps(line) && !self.drops(line);
+    }
+
+    /// true if the line matches the grep pattern and the filters
+    pub fn keeps(&self, line: &str) -> bool {
+        if let Some(grep) = &self.grep {
+            if !grep.is_match(line) {
+                return false;
+            }
+        }
+        if self.includes.len() > 0 {
+            return match self.mode {
+                FilterMode::Any => self.includes.iter().any(|reg| reg.is_match(line)),
+                FilterMode::All => self.includes.iter().all(|reg| reg.is_match(line)),
+            };
+        }
+        return true;
     }
 
-    pub fn get_hue_intervals(&self) -> Result<Vec<HueInterval>, Errors> {
-        let mut intervals: Vec<HueInterval> = Vec::new();
-        for str_intervals in self.hue_intervals.split(",") {
-            let interval = HueInterval::from_str(str_intervals)?;
-            interval.clone().validate().map_err(|err| Errors::Validation(err.to_string()))?;
-            intervals.push(interval);
+    /// true if the line matches the inverted filters
+    pub fn drops(&self, line: &str) -> bool {
+        if self.excludes.len() > 0 {
+            return match self.mode {
+                FilterMode::Any => self.excludes.iter().any(|reg| reg.is_match(line)),
+                FilterMode::All => self.excludes.iter().all(|reg| reg.is_match(line)),
+            };
         }
-        return Ok(intervals);
+        return false;
     }
 }
 
@@ -157,28 +1529,278 @@ impl Settings {
 pub struct Replace {
     pub pattern: Regex,
     pub value: String,
+    /// surround the substituted text with the lineproc::REPLACED_START and REPLACED_END markers
+    pub highlight: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ColorMapping {
+    pub pattern: Regex,
+    pub color: colors_transform::Rgb,
+}
+
+#[derive(Debug, Clone)]
+pub struct TailOverride {
+    pub pattern: Regex,
+    pub tail_lines: i64,
+}
+
+#[derive(Clone)]
+pub struct OnMatch {
+    pub pattern: Regex,
+    pub command: String,
 }
 
 #[derive(Clone)]
 pub struct SettingsValidated {
     pub pod_search: Regex,
     pub kubeconfig: Option<PathBuf>,
+    pub context_regex: Option<Regex>,
     pub namespaces: Vec<String>,
     pub previous: bool,
+    pub previous_container: Option<Regex>,
     pub since_seconds: Option<i64>,
+    /// start of the since window, fixed at startup so that the pods followed later start their stream at the same date
+    pub since_time: Option<DateTime<FixedOffset>>,
+    pub new_pod_tail: Option<NewPodTail>,
     pub tail_lines: Option<i64>,
+    pub tail_for: Vec<TailOverride>,
     pub timestamps: bool,
-    pub loop_pause: u64,
+    pub loop_pause: Duration,
+    pub max_loop_pause: Duration,
+    pub list_page_size: u32,
+    pub metadata_listing: bool,
+    pub api_cache: bool,
+    pub retry: RetryPolicy,
+    pub stream_idle_timeout: Option<Duration>,
+    pub stream_watchdog: Option<Duration>,
+    pub via_node_proxy: bool,
+    pub respect_skip_annotation: bool,
+    pub with_labels: Vec<(String, String)>,
+    pub with_annotations: Vec<(String, String)>,
+    pub selector: Option<LabelSelector>,
+    pub job: Option<Regex>,
+    pub explain: bool,
     pub hue_intervals: Vec<HueInterval>,
     pub color_saturation: Saturation,
     pub color_lightness: Lightness,
-    pub filter: Option<Regex>,
-    pub inv_filter: Option<Regex>,
-    pub replace: Option<Replace>,
+    pub statefulset_hue: bool,
+    pub namespace_hue: bool,
+    pub color_map: Vec<ColorMapping>,
+    pub split_by: Option<String>,
+    pub split_styles: Vec<TextStyle>,
+    pub filter: Filter,
+    pub replace: Vec<Replace>,
+    pub replace_highlight: bool,
+    pub redactor: Option<Redactor>,
+    pub highlight: Vec<Regex>,
+    pub json_query: Option<JsonQuery>,
+    pub json_exclude: Vec<JsonQuery>,
+    pub structured_render: StructuredRender,
+    pub multiline_start: Option<Regex>,
+    pub strict_timestamps: bool,
+    pub binary: BinaryMode,
+    pub strip_ansi: bool,
+    pub dedup: bool,
+    pub sample: Option<u64>,
+    pub max_lines: Option<u64>,
+    pub max_matches: Option<u64>,
+    pub exit_on_match: bool,
+    pub on_match: Option<OnMatch>,
+    pub notify: Option<Regex>,
+    pub notify_mode: NotifyMode,
+    pub no_follow: bool,
+    pub timeout: Option<u64>,
+    pub max_output_bytes: Option<ByteSize>,
+    pub max_duration: Option<Duration>,
+    pub fail_on: Option<Regex>,
+    pub template: Option<Template>,
+    pub start_banner: Option<BannerTemplate>,
+    pub stop_banner: Option<BannerTemplate>,
+    pub output: OutputMode,
+    /// renders the lines for the output, see encoder::Encoder
+    pub encoder: Arc<dyn encoder::Encoder>,
+    pub line_numbers: bool,
+    pub timezone: Option<TimeZone>,
+    pub timestamp_format: Option<String>,
+    pub short_names: bool,
+    pub group_by: GroupBy,
+    pub condensed: bool,
+    pub separators: bool,
+    pub time_markers: Option<Duration>,
+    pub max_prefix_width: Option<usize>,
+    /// terminal width when wrap is requested and stdout is a terminal
+    pub wrap_width: Option<usize>,
+    pub continuation: ContinuationMode,
+    pub ordered: Option<u64>,
+    pub aggregate: Option<u64>,
+    pub aggregate_pattern: Option<Regex>,
+    pub timeline: Option<String>,
+    pub replay_file: Option<PathBuf>,
+    /// the lines are replayed at the pace they were recorded, divided by this speed
+    pub replay_speed: Option<f64>,
+    pub record: Option<PathBuf>,
+    pub offline: Option<PathBuf>,
+    pub stdin: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    pub rotation: Rotation,
+    pub compress: bool,
+    pub group_files: GroupFiles,
+    pub sink_http: Option<reqwest::Url>,
+    pub sink_http_interval: u64,
+    pub sink_http_batch: usize,
+    pub sink_syslog: Option<SyslogTarget>,
+    pub sink_sqlite: Option<PathBuf>,
+    pub sink_labels: Vec<String>,
+    pub sink_file_filter: Option<Regex>,
+    pub sink_http_filter: Option<Regex>,
+    pub sink_syslog_filter: Option<Regex>,
+    pub sink_sqlite_filter: Option<Regex>,
+    pub lifecycle_webhook: Option<reqwest::Url>,
+    pub state_file: Option<PathBuf>,
+    pub resume: bool,
+    pub color_file: Option<PathBuf>,
+    pub mode: Mode,
+    pub stats: Option<u64>,
+    pub summary: bool,
+    pub warn_idle: Option<Duration>,
+    pub heartbeat: Option<Duration>,
+    pub spotlight: Option<Duration>,
+    pub show_lag: bool,
+    pub warn_lag: Option<Duration>,
+    pub metrics_addr: Option<String>,
+    pub ignore_case: bool,
+    pub control: bool,
+    pub focus_others: FocusOthers,
+    pub pick: bool,
+    pub pager: bool,
+    pub output_buffer: usize,
+    pub replay_buffer: usize,
+    pub status_to_stderr: bool,
+    pub status_window: Option<Duration>,
+    pub error_style: Vec<TextStyle>,
+    pub quiet: bool,
+    pub config_path: Option<PathBuf>,
+    /// the arguments the settings were parsed from, see Settings::args
+    pub args: Vec<std::ffi::OsString>,
+    /// the settings replacing these ones for the pods of a namespace, see for_namespace
+    pub namespace_settings: HashMap<String, SettingsValidated>,
+    /// run in order on every line, see lineproc::LineProcessor
+    pub processors: Vec<Arc<dyn LineProcessor>>,
+    /// the processors without the filtering ones (filters, json query and exclusions), for the sinks with their own filter
+    pub sink_processors: Vec<Arc<dyn LineProcessor>>,
+    /// namespaces answered by these sources instead of a cluster, see TailerBuilder::source
+    pub sources: Vec<(String, Arc<dyn source::PodSource>)>,
+    /// what changes while running, see state::RunState
+    pub state: Arc<RunState>,
 }
 
 impl SettingsValidated {
+    /// ordering and checkpointing live lines needs their timestamps
+    pub fn live_timestamps(&self) -> bool {
+        return self.timestamps
+            || self.ordered.is_some()
+            || self.state_file.is_some()
+            || self.is_previous_lines()
+            || self.show_lag
+            || self.warn_lag.is_some()
+            || self.stream_idle_timeout.is_some()
+            || self.stream_watchdog.is_some()
+            || self.sink_sqlite.is_some()
+            || self.record.is_some();
+    }
+
+    /// a sink gets the lines of its own filter instead of the ones kept by the filters
+    pub fn has_sink_filters(&self) -> bool {
+        return self.sink_http_filter.is_some() || self.sink_syslog_filter.is_some() || self.sink_sqlite_filter.is_some();
+    }
+
     pub fn is_previous_lines(&self) -> bool {
-        return self.since_seconds.is_some() || self.tail_lines.is_some();
+        return self.since_seconds.is_some() || self.tail_lines.is_some() || self.tail_for.len() > 0 || self.previous_container.is_some();
+    }
+
+    /// the settings of the [namespace.<name>] section of the config file for this namespace, these ones otherwise
+    pub fn for_namespace(&self, namespace: &str) -> &SettingsValidated {
+        return self.namespace_settings.get(namespace).unwrap_or(self);
+    }
+
+    /// the tail_lines of the first tail_for regex matching the pod name, tail_lines otherwise
+    pub fn tail_lines_for(&self, pod_name: &str) -> Option<i64> {
+        return match self.tail_for.iter().find(|tail| tail.pattern.is_match(pod_name)) {
+            Some(tail) => Some(tail.tail_lines),
+            None => self.tail_lines,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_are_in_seconds() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert!(parse_duration("500ms").is_err());
+        assert!(parse_duration(&format!("{}d", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn loop_pause_accepts_milliseconds() {
+        assert_eq!(parse_loop_pause("3"), Ok(Duration::from_secs(3)));
+        assert_eq!(parse_loop_pause("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_loop_pause("1m"), Ok(Duration::from_secs(60)));
+        assert!(parse_loop_pause("50ms").is_err());
+    }
+
+    fn run_processors(processors: &[Arc<dyn LineProcessor>], line: &str) -> Option<String> {
+        return processors.iter().try_fold(line.to_string(), |line, processor| processor.process(line));
+    }
+
+    #[test]
+    fn sink_processors_keep_the_lines_dropped_by_the_json_queries() {
+        let settings = Settings::try_parse_from([
+            "rusty-stern",
+            "--json-query",
+            ".level == \"error\"",
+            "--json-exclude",
+            ".msg == \"db down\"",
+        ])
+        .unwrap()
+        .to_validated()
+        .unwrap();
+        let line = r#"{"level":"info","msg":"db down"}"#;
+        assert_eq!(run_processors(&settings.processors, line), None);
+        assert_eq!(run_processors(&settings.sink_processors, line), Some(line.to_string()));
+    }
+
+    #[test]
+    fn the_command_line_and_the_environment_win_over_the_config_sections() {
+        let path = std::env::temp_dir().join(format!("rusty_stern_settings_{}_layering.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "tail-lines = 10\nmax-prefix-width = 20\n\n[namespace.shop]\ntail-lines = 50\nmax-prefix-width = 30\n",
+        )
+        .unwrap();
+        let parse = |args: &[&str]| {
+            let args = ["rusty_stern", "--config", path.to_str().unwrap()]
+                .iter()
+                .chain(args)
+                .map(std::ffi::OsString::from)
+                .collect();
+            return Settings::parse_from_args(args).unwrap();
+        };
+        let settings = parse(&[]);
+        assert_eq!((settings.tail_lines, settings.max_prefix_width), (Some(10), Some(20)));
+        let (namespace, shop) = &settings.namespace_settings[0];
+        assert_eq!((namespace.as_str(), shop.tail_lines, shop.max_prefix_width), ("shop", Some(50), Some(30)));
+
+        // no other test reads this variable
+        std::env::set_var("RUSTY_STERN_MAX_PREFIX_WIDTH", "40");
+        let settings = parse(&["--tail-lines", "3"]);
+        std::env::remove_var("RUSTY_STERN_MAX_PREFIX_WIDTH");
+        assert_eq!((settings.tail_lines, settings.max_prefix_width), (Some(3), Some(40)));
+        let (_, shop) = &settings.namespace_settings[0];
+        assert_eq!((shop.tail_lines, shop.max_prefix_width), (Some(3), Some(40)));
     }
 }
@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::display;
+use crate::error::Errors;
+use crate::kubernetes;
+use crate::metrics;
+use crate::types;
+
+/// a size in bytes, parsed from a number optionally followed by K, M or G
+#[derive(Debug, Clone, Copy)]
+pub struct ByteSize {
+    pub value: u64,
+}
+
+impl FromStr for ByteSize {
+    type Err = Errors;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let upper = string.trim().to_uppercase();
+        let (number, multiplier) = match upper.chars().last() {
+            Some('K') => (&upper[..upper.len() - 1], 1024),
+            Some('M') => (&upper[..upper.len() - 1], 1024 * 1024),
+            Some('G') => (&upper[..upper.len() - 1], 1024 * 1024 * 1024),
+            _ => (upper.as_str(), 1),
+        };
+        let value = number
+            .parse::<u64>()
+            .map_err(|err| Errors::Validation(format!("failled to parse size {string}: {err}")))?;
+        return Ok(ByteSize { value: value * multiplier });
+    }
+}
+
+/// when files are rotated and how many rotated files are kept
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    pub size: Option<ByteSize>,
+    pub age: Option<Duration>,
+    pub keep: usize,
+}
+
+/// gzip streams are appended as new members when the file already exists, which gzip readers handle transparently
+enum FileWriter {
+    Plain(std::io::LineWriter<std::fs::File>),
+    Gzip(GzEncoder<std::fs::File>),
+}
+
+impl FileWriter {
+    fn new(file: std::fs::File, compress: bool) -> FileWriter {
+        if compress {
+            return FileWriter::Gzip(GzEncoder::new(file, Compression::default()));
+        }
+        return FileWriter::Plain(std::io::LineWriter::new(file));
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        return match self {
+            FileWriter::Plain(writer) => writer.write_all(buf),
+            FileWriter::Gzip(writer) => writer.write_all(buf),
+        };
+    }
+
+    /// flush the buffered lines and, for gzip, write the stream trailer
+    fn finish(&mut self) -> std::io::Result<()> {
+        return match self {
+            FileWriter::Plain(writer) => writer.flush(),
+            FileWriter::Gzip(writer) => writer.try_finish(),
+        };
+    }
+}
+
+/// how the lines written to the output dir are split between files
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+pub enum GroupFiles {
+    /// a file per pod: <namespace>_<pod>_<container>.log
+    ByPod,
+    /// a file per workload owning the pods (deployment, statefulset, job...): <namespace>_<workload>_<container>.log,
+    /// the pods without owner keep their own file
+    ByOwner,
+}
+
+/// path of the file receiving the lines of the pod (.log.gz when compressed)
+pub fn pod_file_path(dir: &PathBuf, pod: &kubernetes::Pod, group_files: &GroupFiles, compress: bool) -> PathBuf {
+    let extension = if compress { "log.gz" } else { "log" };
+    let namespace = pod.namespace.qualified_name.replace('/', "_");
+    let name = match (group_files, pod.workload()) {
+        (GroupFiles::ByOwner, Some(workload)) => workload,
+        _ => pod.name.clone(),
+    };
+    return dir.join(format!("{}_{}_{}.{extension}", namespace, name, pod.default_container()));
+}
+
+/// the files of the output dir opened by the live streams, the pods of a workload share the same one when grouped by owner
+/// so that their lines are merged in the order they are received, a file is closed once no stream writes to it
+#[derive(Clone)]
+pub struct OutputFiles {
+    dir: PathBuf,
+    rotation: Rotation,
+    compress: bool,
+    group_files: GroupFiles,
+    files: Arc<Mutex<HashMap<PathBuf, Weak<Mutex<PodFile>>>>>,
+}
+
+impl OutputFiles {
+    pub fn new(dir: &PathBuf, rotation: &Rotation, compress: bool, group_files: &GroupFiles) -> OutputFiles {
+        return OutputFiles {
+            dir: dir.clone(),
+            rotation: rotation.clone(),
+            compress,
+            group_files: group_files.clone(),
+            files: Arc::new(Mutex::new(HashMap::new())),
+        };
+    }
+
+    pub fn open(&self, pod: &kubernetes::Pod) -> Result<Arc<Mutex<PodFile>>, Errors> {
+        let path = pod_file_path(&self.dir, pod, &self.group_files, self.compress);
+        let mut files = self
+            .files
+            .lock()
+            .map_err(|err| Errors::Other(format!("opening {}: {err}", path.display())))?;
+        if let Some(file) = files.get(&path).and_then(|file| file.upgrade()) {
+            return Ok(file);
+        }
+        let file = Arc::new(Mutex::new(PodFile::open(&self.dir, path.clone(), &self.rotation, self.compress)?));
+        files.retain(|_, file| file.strong_count() > 0);
+        files.insert(path, Arc::downgrade(&file));
+        return Ok(file);
+    }
+}
+
+/// raw log lines of a single pod, or of the pods of a workload, written to the file given by pod_file_path
+/// rotated files are suffixed by .1 (most recent) to .n
+pub struct PodFile {
+    path: PathBuf,
+    writer: FileWriter,
+    compress: bool,
+    rotation: Rotation,
+    written: u64,
+    opened: Instant,
+}
+
+fn open_file(path: &PathBuf) -> Result<std::fs::File, Errors> {
+    return std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| Errors::Other(format!("opening {}: {err}", path.display())));
+}
+
+impl PodFile {
+    pub fn open(dir: &PathBuf, path: PathBuf, rotation: &Rotation, compress: bool) -> Result<PodFile, Errors> {
+        std::fs::create_dir_all(dir).map_err(|err| Errors::Other(format!("creating directory {}: {err}", dir.display())))?;
+        let file = open_file(&path)?;
+        let written = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        return Ok(PodFile {
+            path,
+            writer: FileWriter::new(file, compress),
+            compress,
+            rotation: rotation.clone(),
+            written,
+            opened: Instant::now(),
+        });
+    }
+
+    fn rotated_path(&self, idx: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{idx}"));
+        return PathBuf::from(path);
+    }
+
+    fn needs_rotation(&self) -> bool {
+        let size_exceeded = self.rotation.size.map_or(false, |size| self.written >= size.value);
+        let age_exceeded = self.rotation.age.map_or(false, |age| self.opened.elapsed() >= age);
+        return size_exceeded || age_exceeded;
+    }
+
+    fn rotate(&mut self) -> Result<(), Errors> {
+        self.writer
+            .finish()
+            .map_err(|err| Errors::Other(format!("writing to {}: {err}", self.path.display())))?;
+        for idx in (1..self.rotation.keep).rev() {
+            let from = self.rotated_path(idx);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(idx + 1)).map_err(|err| Errors::Other(format!("rotating {}: {err}", from.display())))?;
+            }
+        }
+        if self.rotation.keep > 0 {
+            std::fs::rename(&self.path, self.rotated_path(1)).map_err(|err| Errors::Other(format!("rotating {}: {err}", self.path.display())))?;
+        } else {
+            std::fs::remove_file(&self.path).map_err(|err| Errors::Other(format!("rotating {}: {err}", self.path.display())))?;
+        }
+        self.writer = FileWriter::new(open_file(&self.path)?, self.compress);
+        self.written = 0;
+        self.opened = Instant::now();
+        return Ok(());
+    }
+
+    pub fn write_line(&mut self, line: &str) -> Result<(), Errors> {
+        if self.needs_rotation() {
+            self.rotate()?;
+        }
+        let line = format!("{}\n", line.trim_end_matches("\n"));
+        self.writer
+            .write_all(line.as_bytes())
+            .map_err(|err| Errors::Other(format!("writing to {}: {err}", self.path.display())))?;
+        self.written += line.len() as u64;
+        metrics::add(&metrics::OUTPUT_BYTES, line.len());
+        return Ok(());
+    }
+}
+
+/// a log line with its pod metadata, as printed by the json output and sent to the remote sinks
+pub fn json_record(pod: &kubernetes::Pod, line: &str) -> serde_json::Value {
+    let (timestamp, message) = crate::lineproc::split_timestamp(line);
+    let mut record = serde_json::json!({
+        "ns": pod.namespace.name,
+        "pod": pod.name,
+        "container": pod.default_container(),
+        "ts": timestamp,
+        "msg": message,
+    });
+    if let Some(context) = &pod.namespace.context {
+        record["context"] = serde_json::Value::String(context.clone());
+    }
+    return record;
+}
+
+/// json_record with what the systems receiving the sinks filter on without looking the pod up: its node, image and sink_labels
+pub fn sink_record(pod: &kubernetes::Pod, line: &str, sink_labels: &[String]) -> serde_json::Value {
+    let mut record = json_record(pod, line);
+    record["node"] = serde_json::Value::String(pod.node_name());
+    record["image"] = serde_json::Value::String(pod.image());
+    record["labels"] = serde_json::json!(pod.labels(sink_labels));
+    return record;
+}
+
+#[derive(Clone)]
+pub struct HttpTarget {
+    client: reqwest::Client,
+    url: reqwest::Url,
+}
+
+impl HttpTarget {
+    pub fn new(url: reqwest::Url) -> HttpTarget {
+        return HttpTarget {
+            client: reqwest::Client::new(),
+            url,
+        };
+    }
+
+    /// post the body as json, a batch of lines is sent as an array
+    async fn post<T: serde::Serialize>(&self, body: &T) -> Result<(), Errors> {
+        self.client
+            .post(self.url.clone())
+            .json(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| Errors::Other(format!("posting to {}: {err}", self.url)))?;
+        return Ok(());
+    }
+}
+
+/// what happened to a followed pod, posted to the lifecycle webhook
+pub enum LifecycleEvent {
+    Appeared,
+    Disappeared,
+    Restarted,
+    Crashed,
+}
+
+impl LifecycleEvent {
+    fn name(&self) -> &'static str {
+        return match self {
+            LifecycleEvent::Appeared => "appeared",
+            LifecycleEvent::Disappeared => "disappeared",
+            LifecycleEvent::Restarted => "restarted",
+            LifecycleEvent::Crashed => "crashed",
+        };
+    }
+}
+
+/// post the event to the lifecycle webhook if any, in the background so that a slow webhook does not delay the logs
+/// a failled post is reported on stderr, in the color of the pod if it's still known
+pub async fn send_lifecycle_event(
+    streams_lock: &types::ArcMutex<display::Streams>,
+    event: LifecycleEvent,
+    pod_id: &str,
+    color: Option<colors_transform::Rgb>,
+    detail: String,
+) {
+    let Some(target) = streams_lock.lock().await.lifecycle_webhook.clone() else {
+        return;
+    };
+    let body = serde_json::json!({
+        "event": event.name(),
+        "pod": pod_id,
+        "detail": detail,
+        "ts": chrono::Utc::now().to_rfc3339(),
+    });
+    let (name, pod_id) = (event.name(), pod_id.to_string());
+    let streams_lock = streams_lock.clone();
+    tokio::spawn(async move {
+        if let Err(err) = target.post(&body).await {
+            let mut streams = streams_lock.lock().await;
+            let message = format!("lifecycle event {name} of {pod_id} not sent: {err}");
+            display::print_error(&mut streams, color, message).await?;
+        }
+        return Ok::<(), Errors>(());
+    });
+}
+
+/// lines waiting to be posted to the http sink
+pub struct HttpSink {
+    pub target: HttpTarget,
+    batch_size: usize,
+    batch: Vec<serde_json::Value>,
+}
+
+impl HttpSink {
+    pub fn new(url: reqwest::Url, batch_size: usize) -> HttpSink {
+        return HttpSink {
+            target: HttpTarget::new(url),
+            batch_size,
+            batch: vec![],
+        };
+    }
+
+    /// returns the batch when it's full and must be sent
+    pub fn push(&mut self, record: serde_json::Value) -> Option<Vec<serde_json::Value>> {
+        self.batch.push(record);
+        if self.batch.len() >= self.batch_size {
+            return Some(self.take());
+        }
+        return None;
+    }
+
+    pub fn take(&mut self) -> Vec<serde_json::Value> {
+        return std::mem::take(&mut self.batch);
+    }
+}
+
+/// a failled post is reported but does not stop the streams, the batch is dropped
+pub async fn send_http_batch(target: HttpTarget, batch: Vec<serde_json::Value>, streams: types::ArcMutex<display::Streams>) -> Result<(), Errors> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    if let Err(err) = target.post(&batch).await {
+        let mut streams = streams.lock().await;
+        display::print_error(&mut streams, None, format!("dropped {} lines: {}", batch.len(), err)).await?;
+    }
+    return Ok(());
+}
+
+/// send the pending lines of the http sink at a regular interval
+pub async fn flush_http_sink(interval: tokio::time::Duration, streams_lock: types::ArcMutex<display::Streams>) -> Result<(), Errors> {
+    loop {
+        tokio::time::sleep(interval).await;
+        send_http_pending(&streams_lock).await?;
+    }
+}
+
+/// send the lines of the http sink which do not fill a batch yet
+pub async fn send_http_pending(streams_lock: &types::ArcMutex<display::Streams>) -> Result<(), Errors> {
+    let pending = {
+        let mut streams = streams_lock.lock().await;
+        streams.http_sink.as_mut().map(|http_sink| (http_sink.target.clone(), http_sink.take()))
+    };
+    if let Some((target, batch)) = pending {
+        send_http_batch(target, batch, streams_lock.clone()).await?;
+    }
+    return Ok(());
+}
+
+/// syslog collector address, parsed from tcp://host:port or udp://host:port
+#[derive(Debug, Clone)]
+pub enum SyslogTarget {
+    Tcp(String),
+    Udp(String),
+}
+
+impl FromStr for SyslogTarget {
+    type Err = Errors;
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        if let Some(address) = string.strip_prefix("tcp://") {
+            return Ok(SyslogTarget::Tcp(address.to_string()));
+        }
+        if let Some(address) = string.strip_prefix("udp://") {
+            return Ok(SyslogTarget::Udp(address.to_string()));
+        }
+        return Err(Errors::Validation(format!(
+            "failled to parse syslog target {string}: expected tcp://host:port or udp://host:port"
+        )));
+    }
+}
+
+enum SyslogConnection {
+    Tcp(tokio::net::TcpStream),
+    Udp(tokio::net::UdpSocket),
+}
+
+impl SyslogTarget {
+    async fn connect(&self) -> std::io::Result<SyslogConnection> {
+        return match self {
+            SyslogTarget::Tcp(address) => Ok(SyslogConnection::Tcp(tokio::net::TcpStream::connect(address).await?)),
+            SyslogTarget::Udp(address) => {
+                let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+                socket.connect(address).await?;
+                Ok(SyslogConnection::Udp(socket))
+            }
+        };
+    }
+}
+
+impl SyslogConnection {
+    /// tcp messages are framed with octet counting (rfc6587)
+    async fn send(&mut self, message: &str) -> std::io::Result<()> {
+        return match self {
+            SyslogConnection::Tcp(stream) => {
+                let frame = format!("{} {}", message.len(), message);
+                tokio::io::AsyncWriteExt::write_all(stream, frame.as_bytes()).await
+            }
+            SyslogConnection::Udp(socket) => socket.send(message.as_bytes()).await.map(|_| ()),
+        };
+    }
+}
+
+fn escape_sd_value(value: &str) -> String {
+    return value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]");
+}
+
+/// format the line as a rfc5424 message, facility user and severity info, the labels of sink_labels go in their own element
+pub fn syslog_message(pod: &kubernetes::Pod, line: &str, sink_labels: &[String]) -> String {
+    let (timestamp, message) = crate::lineproc::split_timestamp(line);
+    let container = pod.default_container();
+    let labels = pod.labels(sink_labels);
+    let labels = if labels.is_empty() {
+        String::new()
+    } else {
+        let params: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key.replace(['=', ' ', ']', '"'], "_"), escape_sd_value(value)))
+            .collect();
+        format!("[labels@32473 {}]", params.join(" "))
+    };
+    return format!(
+        "<14>1 {} {} {} - - [k8s@32473 namespace=\"{}\" pod=\"{}\" container=\"{}\" node=\"{}\" image=\"{}\"]{} {}",
+        timestamp.unwrap_or("-"),
+        pod.name,
+        container,
+        escape_sd_value(&pod.namespace.name),
+        escape_sd_value(&pod.name),
+        escape_sd_value(&container),
+        escape_sd_value(&pod.node_name()),
+        escape_sd_value(&pod.image()),
+        labels,
+        message.trim_end_matches("\n"),
+    );
+}
+
+/// send the messages to the syslog collector, reconnecting after a failure
+/// the message which failled to be sent is dropped
+pub async fn forward_syslog(
+    target: SyslogTarget,
+    mut receiver: tokio::sync::mpsc::UnboundedReceiver<String>,
+    streams: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let mut connection: Option<SyslogConnection> = None;
+    while let Some(message) = receiver.recv().await {
+        let res = match &mut connection {
+            Some(connection) => connection.send(&message).await,
+            None => match target.connect().await {
+                Ok(mut new_connection) => {
+                    let res = new_connection.send(&message).await;
+                    connection = Some(new_connection);
+                    res
+                }
+                Err(err) => Err(err),
+            },
+        };
+        if let Err(err) = res {
+            connection = None;
+            let mut streams = streams.lock().await;
+            display::print_error(&mut streams, None, format!("failled to send line to syslog {:?}: {}", target, err)).await?;
+        }
+    }
+    return Ok(());
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use futures::future::BoxFuture;
+use k8s_openapi::api::core::v1::{Container, Pod as ApiPod, PodSpec, PodStatus};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{ListParams, LogParams, ObjectList};
+use kube::Api;
+
+use crate::lineproc;
+
+/// a pod log stream, read line by line by LogLines
+pub type LogReader = Pin<Box<dyn futures::AsyncBufRead + Send>>;
+
+/// the pod operations of a namespace used to find the pods to follow and stream their logs,
+/// answered by the kubernetes api or by the fixtures of the offline mode
+pub trait PodSource: Send + Sync {
+    fn list<'a>(&'a self, params: &'a ListParams) -> BoxFuture<'a, Result<ObjectList<ApiPod>, kube::Error>>;
+
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<ApiPod, kube::Error>>;
+
+    fn log_stream<'a>(&'a self, name: &'a str, params: &'a LogParams) -> BoxFuture<'a, Result<LogReader, kube::Error>>;
+}
+
+pub struct KubeSource {
+    api: Api<ApiPod>,
+}
+
+impl KubeSource {
+    pub fn new(api: Api<ApiPod>) -> KubeSource {
+        return KubeSource { api };
+    }
+}
+
+impl PodSource for KubeSource {
+    fn list<'a>(&'a self, params: &'a ListParams) -> BoxFuture<'a, Result<ObjectList<ApiPod>, kube::Error>> {
+        return Box::pin(self.api.list(params));
+    }
+
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<ApiPod, kube::Error>> {
+        return Box::pin(self.api.get(name));
+    }
+
+    fn log_stream<'a>(&'a self, name: &'a str, params: &'a LogParams) -> BoxFuture<'a, Result<LogReader, kube::Error>> {
+        return Box::pin(async move {
+            let reader = self.api.log_stream(name, params).await?;
+            return Ok(Box::pin(reader) as LogReader);
+        });
+    }
+}
+
+/// a running pod with a single container, for the pods known only by their logs
+pub fn running_pod(namespace: &str, name: &str, container: &str) -> ApiPod {
+    return ApiPod {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some(namespace.to_string()),
+            uid: Some(format!("{namespace}/{name}")),
+            ..Default::default()
+        },
+        spec: Some(PodSpec {
+            containers: vec![Container {
+                name: container.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }),
+        status: Some(PodStatus {
+            phase: Some("Running".to_string()),
+            ..Default::default()
+        }),
+    };
+}
+
+/// the log stream the api would answer with the params: the lines without timestamp get the one of the line before them
+/// (the pod creation for the first ones), so that the output is the same at each run
+fn render_logs(pod: &ApiPod, content: &str, params: &LogParams) -> LogReader {
+    let mut last_date: DateTime<FixedOffset> = pod
+        .metadata
+        .creation_timestamp
+        .as_ref()
+        .map(|created| created.0)
+        .unwrap_or_default()
+        .fixed_offset();
+    let mut lines = vec![];
+    for line in content.lines() {
+        let (timestamp, message) = lineproc::split_timestamp(line);
+        if let Some(date) = timestamp.and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok()) {
+            last_date = date;
+        }
+        lines.push((last_date, message));
+    }
+    if let Some(since_seconds) = params.since_seconds {
+        let start = Utc::now() - chrono::Duration::seconds(since_seconds);
+        lines.retain(|(date, _)| *date >= start);
+    }
+    if let Some(tail_lines) = params.tail_lines {
+        let skipped = lines.len().saturating_sub(tail_lines.max(0) as usize);
+        lines.drain(..skipped);
+    }
+    let mut body = String::new();
+    for (date, message) in lines {
+        if params.timestamps {
+            body.push_str(&date.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Nanos, true));
+            body.push(' ');
+        }
+        body.push_str(message);
+        body.push('\n');
+    }
+    return Box::pin(futures::io::Cursor::new(body.into_bytes()));
+}
+
+fn pod_list(items: Vec<ApiPod>) -> Result<ObjectList<ApiPod>, kube::Error> {
+    let list = serde_json::json!({"apiVersion": "v1", "kind": "PodList", "metadata": {}, "items": items});
+    return serde_json::from_value(list).map_err(kube::Error::SerdeError);
+}
+
+/// the fixtures are local files, an error is a bad fixture which retrying won't fix
+fn fixture_error(code: u16, message: String) -> kube::Error {
+    return kube::Error::Api(kube::core::ErrorResponse {
+        status: "Failure".to_string(),
+        message,
+        reason: if code == 404 { "NotFound".to_string() } else { "BadRequest".to_string() },
+        code,
+    });
+}
+
+/// the pods of a namespace directory of the offline fixtures: <pod>.log holds the logs of the pod (<pod>_<container>.log the ones of
+/// its other containers) and the optional <pod>.yaml its object, a running pod with a single container named main is assumed without it
+pub struct FixtureSource {
+    dir: PathBuf,
+    namespace: String,
+}
+
+impl FixtureSource {
+    pub fn new(dir: PathBuf, namespace: String) -> FixtureSource {
+        return FixtureSource { dir, namespace };
+    }
+
+    /// pod names can't contain an underscore, so the container logs are told apart from the pod ones
+    fn pod_names(&self) -> Result<Vec<String>, kube::Error> {
+        let entries = std::fs::read_dir(&self.dir).map_err(|err| fixture_error(400, format!("failled to read {}: {err}", self.dir.display())))?;
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let name = file_name.strip_suffix(".yaml").or(file_name.strip_suffix(".log"))?;
+                if name.contains('_') {
+                    return None;
+                }
+                return Some(name.to_string());
+            })
+            .collect();
+        names.sort();
+        names.dedup();
+        return Ok(names);
+    }
+
+    fn load_pod(&self, name: &str) -> Result<ApiPod, kube::Error> {
+        let path = self.dir.join(format!("{name}.yaml"));
+        if !path.exists() {
+            if !self.dir.join(format!("{name}.log")).exists() {
+                return Err(fixture_error(404, format!("pod {name} not found in {}", self.dir.display())));
+            }
+            return Ok(running_pod(&self.namespace, name, "main"));
+        }
+        let content = std::fs::read_to_string(&path).map_err(|err| fixture_error(400, format!("failled to read {}: {err}", path.display())))?;
+        let mut pod: ApiPod =
+            serde_yaml::from_str(&content).map_err(|err| fixture_error(400, format!("failled to parse {}: {err}", path.display())))?;
+        pod.metadata.name.get_or_insert(name.to_string());
+        pod.metadata.namespace.get_or_insert(self.namespace.clone());
+        pod.metadata.uid.get_or_insert(format!("{}/{name}", self.namespace));
+        // the status is usually left out of the hand written fixtures
+        pod.status.get_or_insert(PodStatus {
+            phase: Some("Running".to_string()),
+            ..Default::default()
+        });
+        return Ok(pod);
+    }
+}
+
+impl PodSource for FixtureSource {
+    /// the selectors are not applied, the filters of the settings are
+    fn list<'a>(&'a self, _params: &'a ListParams) -> BoxFuture<'a, Result<ObjectList<ApiPod>, kube::Error>> {
+        return Box::pin(async move {
+            let mut items = vec![];
+            for name in self.pod_names()? {
+                items.push(self.load_pod(&name)?);
+            }
+            return pod_list(items);
+        });
+    }
+
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<ApiPod, kube::Error>> {
+        return Box::pin(async move {
+            return self.load_pod(name);
+        });
+    }
+
+    fn log_stream<'a>(&'a self, name: &'a str, params: &'a LogParams) -> BoxFuture<'a, Result<LogReader, kube::Error>> {
+        return Box::pin(async move {
+            let pod = self.load_pod(name)?;
+            let path = match &params.container {
+                Some(container) if self.dir.join(format!("{name}_{container}.log")).exists() => self.dir.join(format!("{name}_{container}.log")),
+                _ => self.dir.join(format!("{name}.log")),
+            };
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(err) => return Err(fixture_error(400, format!("failled to read {}: {err}", path.display()))),
+            };
+            return Ok(render_logs(&pod, &content, params));
+        });
+    }
+}
+
+/// the pods of a namespace and their logs held in memory, to drive the tailer without a cluster (eg: in tests)
+#[derive(Default)]
+pub struct MemorySource {
+    pods: Vec<ApiPod>,
+    /// by pod then container, the logs are rendered like the ones of the fixtures
+    logs: HashMap<String, HashMap<String, String>>,
+}
+
+impl MemorySource {
+    pub fn new() -> MemorySource {
+        return MemorySource::default();
+    }
+
+    /// the logs of the first container of the pod
+    pub fn pod(mut self, pod: ApiPod, logs: &str) -> MemorySource {
+        let container = pod
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.containers.first())
+            .map_or(String::new(), |container| container.name.clone());
+        let name = pod.metadata.name.clone().unwrap_or_default();
+        self.pods.push(pod);
+        return self.container_logs(&name, &container, logs);
+    }
+
+    pub fn container_logs(mut self, pod: &str, container: &str, logs: &str) -> MemorySource {
+        self.logs
+            .entry(pod.to_string())
+            .or_default()
+            .insert(container.to_string(), logs.to_string());
+        return self;
+    }
+
+    fn find_pod(&self, name: &str) -> Result<&ApiPod, kube::Error> {
+        return self
+            .pods
+            .iter()
+            .find(|pod| pod.metadata.name.as_deref() == Some(name))
+            .ok_or_else(|| fixture_error(404, format!("pod {name} not found")));
+    }
+}
+
+impl PodSource for MemorySource {
+    /// the selectors are not applied, the filters of the settings are
+    fn list<'a>(&'a self, _params: &'a ListParams) -> BoxFuture<'a, Result<ObjectList<ApiPod>, kube::Error>> {
+        return Box::pin(async move {
+            return pod_list(self.pods.clone());
+        });
+    }
+
+    fn get<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<ApiPod, kube::Error>> {
+        return Box::pin(async move {
+            return self.find_pod(name).cloned();
+        });
+    }
+
+    /// without container in the params, the logs of the first container of the pod
+    fn log_stream<'a>(&'a self, name: &'a str, params: &'a LogParams) -> BoxFuture<'a, Result<LogReader, kube::Error>> {
+        return Box::pin(async move {
+            let pod = self.find_pod(name)?;
+            let containers = self.logs.get(name);
+            let content = match &params.container {
+                Some(container) => containers.and_then(|containers| containers.get(container)),
+                None => pod
+                    .spec
+                    .as_ref()
+                    .and_then(|spec| spec.containers.first())
+                    .and_then(|container| containers?.get(&container.name)),
+            };
+            return Ok(render_logs(pod, content.map_or("", |content| content.as_str()), params));
+        });
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use regex::Regex;
+
+use crate::settings;
+
+/// what changes while a tailer runs (live and reloaded filters, fail_on, split values, terminal width),
+/// shared by the clones of its settings and the ones of its namespaces so that two tailers don't see each other's
+#[derive(Default)]
+pub struct RunState {
+    /// filter typed while streaming, applied on top of the command line filters
+    live_filter: RwLock<Option<Regex>>,
+    /// filters read again on SIGHUP by [namespace.<name>] section (None for the top level ones), they replace the ones read at startup
+    reloaded_filters: RwLock<Option<HashMap<Option<String>, settings::Filter>>>,
+    /// set once a line matched the fail_on pattern, the run then exits with code 2
+    fail_on_matched: AtomicBool,
+    /// values of the split_by label in order of appearance, the pods of each value get the split style at its index
+    split_values: Mutex<Vec<String>>,
+    /// width of the terminal since it was last resized, 0 until then
+    resized_width: AtomicUsize,
+}
+
+impl RunState {
+    pub fn live_filter_match(&self, line: &str) -> bool {
+        return match self.live_filter.read() {
+            Ok(live_filter) => live_filter.as_ref().map_or(true, |live_filter| live_filter.is_match(line)),
+            Err(_) => true,
+        };
+    }
+
+    pub fn set_live_filter(&self, live_filter: Option<Regex>) {
+        if let Ok(mut current) = self.live_filter.write() {
+            *current = live_filter;
+        }
+    }
+
+    /// run the check against the reloaded filters of the section if any, the given ones otherwise.
+    /// a section removed from the config file since startup gets the reloaded top level filters
+    pub fn current_filter_match(
+        &self,
+        section: &Option<String>,
+        filter: &settings::Filter,
+        check: fn(&settings::Filter, &str) -> bool,
+        line: &str,
+    ) -> bool {
+        return match self.reloaded_filters.read() {
+            Ok(reloaded_filters) => match reloaded_filters.as_ref() {
+                Some(reloaded_filters) => check(
+                    reloaded_filters.get(section).or_else(|| reloaded_filters.get(&None)).unwrap_or(filter),
+                    line,
+                ),
+                None => check(filter, line),
+            },
+            Err(_) => check(filter, line),
+        };
+    }
+
+    pub fn set_reloaded_filters(&self, filters: HashMap<Option<String>, settings::Filter>) {
+        if let Ok(mut reloaded_filters) = self.reloaded_filters.write() {
+            *reloaded_filters = Some(filters);
+        }
+    }
+
+    pub fn set_fail_on_matched(&self) {
+        self.fail_on_matched.store(true, Ordering::Relaxed);
+    }
+
+    pub fn fail_on_matched(&self) -> bool {
+        return self.fail_on_matched.load(Ordering::Relaxed);
+    }
+
+    /// index of the value in order of appearance, the value is added when it wasn't seen yet
+    pub fn split_index(&self, value: String) -> usize {
+        let mut values = self.split_values.lock().unwrap();
+        return match values.iter().position(|known| *known == value) {
+            Some(idx) => idx,
+            None => {
+                values.push(value);
+                values.len() - 1
+            }
+        };
+    }
+
+    pub fn set_resized_width(&self, width: usize) {
+        self.resized_width.store(width, Ordering::Relaxed);
+    }
+
+    pub fn resized_width(&self) -> Option<usize> {
+        return match self.resized_width.load(Ordering::Relaxed) {
+            0 => None,
+            width => Some(width),
+        };
+    }
+}
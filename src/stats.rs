@@ -0,0 +1,303 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::error::Errors;
+use crate::{display, kubernetes, metrics, types};
+
+/// counters of a followed pod, shared between clones
+#[derive(Debug, Default)]
+pub struct PodStats {
+    pub received_lines: AtomicU64,
+    pub received_bytes: AtomicU64,
+    /// lines passing the filters
+    pub matched_lines: AtomicU64,
+    pub printed_lines: AtomicU64,
+    /// log streams which ended with an error
+    pub errors: AtomicU64,
+    /// lines removed by the filters and the json query
+    pub filtered_lines: AtomicU64,
+    /// lines printed but dropped by the output because the terminal could not keep up or it was paused for too long
+    pub dropped_lines: AtomicU64,
+    /// incomplete lines at the end of a stream which was closed or failled
+    pub truncated_lines: AtomicU64,
+    /// lines which were not valid utf8, skipped by the binary setting
+    pub undecodable_lines: AtomicU64,
+}
+
+impl PodStats {
+    pub fn record_received(&self, line: &str) {
+        self.received_lines.fetch_add(1, Ordering::Relaxed);
+        self.received_bytes.fetch_add(line.len() as u64, Ordering::Relaxed);
+    }
+}
+
+/// counters of every pod followed since the start, a pod followed again after its stream stopped counts as a reconnect
+pub struct Summary {
+    started: std::time::Instant,
+    pods: Vec<(String, Vec<Arc<PodStats>>)>,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        return Summary {
+            started: std::time::Instant::now(),
+            pods: vec![],
+        };
+    }
+
+    pub fn register(&mut self, pod: &kubernetes::Pod) {
+        let pod_id = pod.id();
+        match self.pods.iter_mut().find(|(id, _)| *id == pod_id) {
+            Some((_, streams)) => {
+                if !streams.iter().any(|stats| Arc::ptr_eq(stats, &pod.stats)) {
+                    streams.push(pod.stats.clone());
+                }
+            }
+            None => self.pods.push((pod_id, vec![pod.stats.clone()])),
+        }
+    }
+
+    pub fn report(&self) -> Vec<String> {
+        let sum = |streams: &Vec<Arc<PodStats>>, counter: fn(&PodStats) -> &AtomicU64| -> u64 {
+            return streams.iter().map(|stats| counter(stats).load(Ordering::Relaxed)).sum();
+        };
+        let mut report = vec![format!(
+            "--- summary: {} pods followed during {:.1}s",
+            self.pods.len(),
+            self.started.elapsed().as_secs_f64()
+        )];
+        for (pod_id, streams) in self.pods.iter() {
+            report.push(format!(
+                "{}: {} lines printed, {} matched, {} reconnects, {} errors, {} filtered, {} dropped, {} truncated, {} undecodable",
+                pod_id,
+                sum(streams, |stats| &stats.printed_lines),
+                sum(streams, |stats| &stats.matched_lines),
+                streams.len() - 1,
+                sum(streams, |stats| &stats.errors),
+                sum(streams, |stats| &stats.filtered_lines),
+                sum(streams, |stats| &stats.dropped_lines),
+                sum(streams, |stats| &stats.truncated_lines),
+                sum(streams, |stats| &stats.undecodable_lines),
+            ));
+        }
+        return report;
+    }
+}
+
+/// 1536 => 1.5K
+fn human_bytes(bytes: f64) -> String {
+    let units = ["", "K", "M", "G"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    return format!("{:.1}{}", value, units[unit]);
+}
+
+fn human_count(count: u64) -> String {
+    return match count {
+        0..=999 => count.to_string(),
+        1_000..=999_999 => format!("{:.1}k", count as f64 / 1_000.0),
+        _ => format!("{:.1}M", count as f64 / 1_000_000.0),
+    };
+}
+
+/// print the number of followed pods and the lines received over the last interval to stderr
+pub async fn print_heartbeat_loop(
+    interval: tokio::time::Duration,
+    pods: types::ArcMutex<kubernetes::Pods>,
+    streams: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let mut previous = metrics::LINES_RECEIVED.load(Ordering::Relaxed);
+    loop {
+        tokio::time::sleep(interval).await;
+        let received = metrics::LINES_RECEIVED.load(Ordering::Relaxed);
+        let (pod_cnt, namespace_cnt) = {
+            let pods = pods.lock().await;
+            let namespaces: HashSet<&str> = pods.items.iter().map(|pod| pod.namespace.qualified_name.as_str()).collect();
+            (pods.items.len(), namespaces.len())
+        };
+        let message = format!(
+            "♥ following {} pods across {} namespaces, {} lines in last {}s",
+            pod_cnt,
+            namespace_cnt,
+            human_count(received - previous),
+            interval.as_secs()
+        );
+        previous = received;
+        let mut streams = streams.lock().await;
+        display::print_color(&mut streams.err, None, message).await?;
+    }
+}
+
+/// intervals of the sliding window the baseline of a pod is the average of
+const SPOTLIGHT_WINDOW: usize = 12;
+/// intervals received before a pod is flagged, so that the baseline means something
+const SPOTLIGHT_WARMUP: usize = 4;
+/// a pod receiving this many times its baseline in an interval is spiking
+const SPOTLIGHT_SPIKE_FACTOR: f64 = 4.0;
+/// below this many lines in an interval a pod is not spiking, whatever its baseline
+const SPOTLIGHT_SPIKE_MIN: u64 = 20;
+
+/// lines received by a pod in the last intervals, the anomalous ones are left out so that they don't move the baseline
+struct Baseline {
+    counts: VecDeque<u64>,
+    previous: u64,
+    anomaly: Option<&'static str>,
+}
+
+impl Baseline {
+    /// the anomaly of the interval, if the pod was not already flagged for it
+    fn record(&mut self, received: u64) -> Option<Option<&'static str>> {
+        let count = received - self.previous;
+        self.previous = received;
+        let average = self.average();
+        let anomaly = if self.counts.len() < SPOTLIGHT_WARMUP {
+            None
+        } else if count >= SPOTLIGHT_SPIKE_MIN && count as f64 > average * SPOTLIGHT_SPIKE_FACTOR {
+            Some("spiking")
+        } else if count == 0 && average >= 1.0 {
+            Some("silent")
+        } else {
+            None
+        };
+        if anomaly.is_none() {
+            self.counts.push_back(count);
+            if self.counts.len() > SPOTLIGHT_WINDOW {
+                self.counts.pop_front();
+            }
+        }
+        if anomaly == self.anomaly {
+            return None;
+        }
+        self.anomaly = anomaly;
+        return Some(anomaly);
+    }
+
+    fn average(&self) -> f64 {
+        return self.counts.iter().sum::<u64>() as f64 / self.counts.len().max(1) as f64;
+    }
+}
+
+/// learn the lines received by each pod every interval and print an alert in the pod color when its volume spikes
+/// or drops to zero compared to the average of the last intervals, and another when it's back to normal
+pub async fn spotlight_loop(
+    interval: tokio::time::Duration,
+    pods: types::ArcMutex<kubernetes::Pods>,
+    streams: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let mut baselines: HashMap<String, Baseline> = HashMap::new();
+    loop {
+        tokio::time::sleep(interval).await;
+        let mut alerts = vec![];
+        {
+            let pods = pods.lock().await;
+            let followed: HashSet<String> = pods.items.iter().map(|pod| pod.id()).collect();
+            baselines.retain(|pod_id, _| followed.contains(pod_id));
+            for pod in pods.items.iter() {
+                let received = pod.stats.received_lines.load(Ordering::Relaxed);
+                // the lines received before the pod was first seen (eg: its previous lines) are not part of an interval
+                let baseline = baselines.entry(pod.id()).or_insert_with(|| Baseline {
+                    counts: VecDeque::new(),
+                    previous: received,
+                    anomaly: None,
+                });
+                let lines = received - baseline.previous;
+                let message = match baseline.record(received) {
+                    Some(Some(anomaly)) => format!(
+                        "⚠ {} {anomaly}: {} lines in last {}s, {:.1} on average",
+                        pod.id(),
+                        human_count(lines),
+                        interval.as_secs(),
+                        baseline.average()
+                    ),
+                    Some(None) => format!("✓ {} back to its usual volume", pod.id()),
+                    None => continue,
+                };
+                alerts.push((pod.color, message));
+            }
+        }
+        let mut streams = streams.lock().await;
+        for (color, message) in alerts {
+            display::print_status(&mut streams, Some(color), message).await?;
+        }
+    }
+}
+
+/// print the lines/sec and bytes/sec of each pod over the last interval to stderr, followed by the totals of the lines
+/// which were not printed: filtered, dropped by the output, truncated and undecodable, then the hits of the api cache if used
+pub async fn print_stats_loop(
+    interval: tokio::time::Duration,
+    pods: types::ArcMutex<kubernetes::Pods>,
+    streams: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let mut previous: HashMap<String, (u64, u64)> = HashMap::new();
+    loop {
+        tokio::time::sleep(interval).await;
+        let seconds = interval.as_secs_f64();
+        let mut rows = vec![];
+        {
+            let pods = pods.lock().await;
+            for pod in pods.items.iter() {
+                let pod_id = pod.id();
+                let lines = pod.stats.received_lines.load(Ordering::Relaxed);
+                let bytes = pod.stats.received_bytes.load(Ordering::Relaxed);
+                let (previous_lines, previous_bytes) = previous.insert(pod_id.clone(), (lines, bytes)).unwrap_or((0, 0));
+                let lost = [
+                    &pod.stats.filtered_lines,
+                    &pod.stats.dropped_lines,
+                    &pod.stats.truncated_lines,
+                    &pod.stats.undecodable_lines,
+                ]
+                .map(|counter| counter.load(Ordering::Relaxed));
+                rows.push((
+                    pod.color,
+                    pod_id,
+                    (lines - previous_lines) as f64 / seconds,
+                    (bytes - previous_bytes) as f64 / seconds,
+                    lost,
+                ));
+            }
+        }
+        let width = rows.iter().map(|row| row.1.chars().count()).max().unwrap_or(0).max(3);
+        let mut streams = streams.lock().await;
+        let header = format!(
+            "{:width$}  {:>10}  {:>10}  {:>8}  {:>8}  {:>9}  {:>11}",
+            "pod", "lines/s", "bytes/s", "filtered", "dropped", "truncated", "undecodable"
+        );
+        display::print_color(&mut streams.err, None, header).await?;
+        for (color, pod_id, lines_per_sec, bytes_per_sec, [filtered, dropped, truncated, undecodable]) in rows {
+            display::print_color(
+                &mut streams.err,
+                Some(color),
+                format!(
+                    "{:width$}  {:>10.1}  {:>10}  {:>8}  {:>8}  {:>9}  {:>11}",
+                    pod_id,
+                    lines_per_sec,
+                    human_bytes(bytes_per_sec),
+                    human_count(filtered),
+                    human_count(dropped),
+                    human_count(truncated),
+                    human_count(undecodable)
+                ),
+            )
+            .await?;
+        }
+        let (hits, misses) = (
+            metrics::POD_CACHE_HITS.load(Ordering::Relaxed),
+            metrics::POD_CACHE_MISSES.load(Ordering::Relaxed),
+        );
+        if hits + misses > 0 {
+            let message = format!(
+                "api cache: {} hits, {} misses ({:.0}% hit rate)",
+                human_count(hits),
+                human_count(misses),
+                hits as f64 * 100.0 / (hits + misses) as f64
+            );
+            display::print_color(&mut streams.err, None, message).await?;
+        }
+    }
+}
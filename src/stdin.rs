@@ -0,0 +1,57 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use tokio::io::AsyncBufReadExt;
+
+use crate::display::OutputMode;
+use crate::error::Errors;
+use crate::{actions, display, kubernetes, lineproc, settings, stats, types};
+
+/// print the lines read from stdin with the pod logs, prefixed by the stdin label and padded like the pods,
+/// they go through the same filters and the same output, the pod logs are still followed once stdin is closed
+pub async fn follow_stdin(
+    settings: settings::SettingsValidated,
+    pods_lock: types::ArcMutex<kubernetes::Pods>,
+    streams_lock: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let Some(label) = settings.stdin.clone() else {
+        return Ok(());
+    };
+    let (color, layout) = {
+        let mut pods = pods_lock.lock().await;
+        (pods.colors.get_new_color(), pods.layout.clone())
+    };
+    let out = streams_lock.lock().await.out.clone();
+    let stats = Arc::new(stats::PodStats::default());
+    let pod_id: Arc<str> = Arc::from(label.as_str());
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await.map_err(|err| Errors::Other(format!("reading stdin: {err}")))? {
+        stats.record_received(&line);
+        // stdin lines have no timestamp, they get the reception date in the format of the kubernetes ones
+        let line = if settings.timestamps {
+            format!("{} {line}", chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Nanos, true))
+        } else {
+            line
+        };
+        let Some(line) = display::process_log_line(line, &settings) else {
+            stats.filtered_lines.fetch_add(1, Ordering::Relaxed);
+            continue;
+        };
+        actions::check_fail_on(&settings, &line);
+        match settings.output {
+            OutputMode::Json => {
+                let (timestamp, message) = lineproc::split_timestamp(&line);
+                let record = serde_json::json!({ "pod": label, "ts": timestamp, "msg": message });
+                out.record(stats.clone(), record.to_string())?;
+            }
+            OutputMode::Raw => out.record(stats.clone(), line)?,
+            _ => {
+                // the padding follows the pod set, which may change while stdin is read
+                let padding = " ".repeat(layout.padding.load(Ordering::Relaxed).saturating_sub(label.chars().count()));
+                out.line(stats.clone(), pod_id.clone(), color, None, Arc::from(format!("{label}:{padding} ")), line)?;
+            }
+        }
+    }
+    let mut streams = streams_lock.lock().await;
+    return display::print_status(&mut streams, Some(color), format!("--- {label} closed")).await;
+}
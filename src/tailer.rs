@@ -0,0 +1,1174 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, FixedOffset};
+use clap::Parser;
+use futures::Stream;
+use tokio::task::JoinHandle;
+
+use crate::error::Errors;
+use crate::{
+    capture, control, display, error, kubernetes, lineproc, metrics, picker, poll, replay, settings, sinks, source, stats, stdin, timeline, types,
+};
+
+/// once the followed jobs have ended, how long their pods streams are given to print their last lines
+const JOB_DRAIN_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+/// failled streams in a row after which a pod is no longer followed
+const STREAM_FAILURES_BEFORE_PARKING: u32 = 5;
+/// wait before the second attempt to follow a pod whose stream failled, doubled on each next failure
+const STREAM_RETRY_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+/// a stream which failled after running for longer than this is not counted as failling in a row
+const STREAM_FAILURE_RESET: tokio::time::Duration = tokio::time::Duration::from_secs(60);
+/// previous lines read ahead for each log stream while they are merged
+const PREVIOUS_LINES_READ_AHEAD: usize = 1000;
+/// lines read ahead by Tailer::stream, across every pod, before the pod streams wait for the consumer
+const STREAM_READ_AHEAD: usize = 1000;
+
+/// build a tailer from code instead of the command line, the defaults are the command line ones
+pub struct TailerBuilder {
+    args: Vec<String>,
+    processors: Vec<Arc<dyn lineproc::LineProcessor>>,
+    sources: Vec<(String, Arc<dyn source::PodSource>)>,
+}
+
+impl TailerBuilder {
+    pub fn new() -> TailerBuilder {
+        return TailerBuilder {
+            args: vec!["rusty_stern".to_string()],
+            processors: vec![],
+            sources: vec![],
+        };
+    }
+
+    fn arg(mut self, name: &str, value: &str) -> TailerBuilder {
+        self.args.push(format!("--{name}={value}"));
+        return self;
+    }
+
+    pub fn kubeconfig(self, kubeconfig: PathBuf) -> TailerBuilder {
+        return self.arg("kubeconfig", &kubeconfig.to_string_lossy());
+    }
+
+    pub fn namespaces(self, namespaces: &[&str]) -> TailerBuilder {
+        return self.arg("namespaces", &namespaces.join(","));
+    }
+
+    /// regex matching the names of the pods to follow
+    pub fn pod_search(self, pod_search: &str) -> TailerBuilder {
+        return self.arg("pod-search", pod_search);
+    }
+
+    pub fn filter(self, filter: &str) -> TailerBuilder {
+        return self.arg("filter", filter);
+    }
+
+    pub fn inv_filter(self, inv_filter: &str) -> TailerBuilder {
+        return self.arg("inv-filter", inv_filter);
+    }
+
+    pub fn replace(self, pattern: &str, value: &str) -> TailerBuilder {
+        return self.arg("replace-pattern", pattern).arg("replace-value", value);
+    }
+
+    /// run returns EXIT_FAIL_ON once a line matched this pattern
+    pub fn fail_on(self, fail_on: &str) -> TailerBuilder {
+        return self.arg("fail-on", fail_on);
+    }
+
+    /// print the existing lines and stop instead of following the pods
+    pub fn no_follow(mut self, no_follow: bool) -> TailerBuilder {
+        self.args.retain(|arg| arg != "--no-follow");
+        if no_follow {
+            self.args.push("--no-follow".to_string());
+        }
+        return self;
+    }
+
+    /// relative duration like 15m or 2h
+    pub fn since(self, since: &str) -> TailerBuilder {
+        return self.arg("since", since);
+    }
+
+    pub fn output_dir(self, output_dir: PathBuf) -> TailerBuilder {
+        return self.arg("output-dir", &output_dir.to_string_lossy());
+    }
+
+    pub fn sink_http(self, url: &str) -> TailerBuilder {
+        return self.arg("sink-http", url);
+    }
+
+    /// tcp://host:port or udp://host:port
+    pub fn sink_syslog(self, target: &str) -> TailerBuilder {
+        return self.arg("sink-syslog", target);
+    }
+
+    /// run after the built-in processors (filters, json query, timestamps, structured rendering and replacements),
+    /// also for the sinks with their own filter
+    pub fn processor(mut self, processor: impl lineproc::LineProcessor + 'static) -> TailerBuilder {
+        self.processors.push(Arc::new(processor));
+        return self;
+    }
+
+    /// answer the namespace from the source instead of a cluster (eg: source::MemorySource in tests), like the offline fixtures
+    /// no cluster is reached once a source is given and the existing lines are printed without following the pods
+    pub fn source(mut self, namespace: &str, source: impl source::PodSource + 'static) -> TailerBuilder {
+        self.sources.push((namespace.to_string(), Arc::new(source)));
+        return self;
+    }
+
+    /// the settings are validated as if they were passed on the command line
+    pub fn build(self) -> Result<Tailer, Errors> {
+        let mut settings = settings::Settings::try_parse_from(&self.args).map_err(|err| Errors::Validation(err.to_string()))?;
+        settings.args = self.args.iter().map(std::ffi::OsString::from).collect();
+        let mut settings = settings.to_validated()?;
+        settings.sink_processors.extend(self.processors.iter().cloned());
+        settings.processors.extend(self.processors);
+        if self.sources.len() > 0 {
+            settings.no_follow = true;
+            settings.sources = self.sources;
+        }
+        return Ok(Tailer::new(settings));
+    }
+}
+
+/// follows the pods matching the settings and prints their logs
+pub struct Tailer {
+    settings: settings::SettingsValidated,
+    signals: bool,
+}
+
+impl Tailer {
+    pub fn new(settings: settings::SettingsValidated) -> Tailer {
+        return Tailer { settings, signals: false };
+    }
+
+    /// reload the settings on SIGHUP or when the config file changes and stop on ctrl + c or SIGTERM, like the binary does.
+    /// without it the process signals are left to the caller, which stops the run by dropping its future
+    pub fn with_signals(mut self) -> Tailer {
+        self.signals = true;
+        return self;
+    }
+
+    /// stream the lines of the matching pods instead of printing them, the filters and replacements are applied
+    /// but none of the output options. the stream ends when every pod stream is closed (always the case with no_follow).
+    /// the errors are yielded too: the one stopping the stream (eg: the pods can't be listed) is its last item,
+    /// a pod whose stream failled is streamed again on the next refresh when following.
+    /// the lines are read ahead up to a bound, the pod streams then wait for the stream to be consumed
+    pub fn stream(&self) -> impl Stream<Item = Result<kubernetes::LogLine, Errors>> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_READ_AHEAD);
+        let settings = self.settings.clone();
+        tokio::spawn(async move {
+            if let Err(err) = stream_pods(settings, sender.clone()).await {
+                let _ = sender.send(Err(err)).await;
+            }
+        });
+        return futures::stream::unfold(receiver, |mut receiver| async move {
+            return receiver.recv().await.map(|line| (line, receiver));
+        });
+    }
+
+    /// returns the exit code of the run: 0 when the streams ended, EXIT_INTERRUPTED or EXIT_FAIL_ON otherwise
+    pub async fn run(self) -> Result<i32, Errors> {
+        let mut settings = self.settings;
+        metrics::reset();
+
+        let streams: display::Streams = display::new_streams(&settings)?;
+        if let Some(header) = settings.encoder.header() {
+            streams.out.text(None, header)?;
+        }
+        let streams_lock = display::new_streams_mutex(streams);
+
+        if let Some(replay_file) = &settings.replay_file {
+            replay::replay_file(&settings, replay_file, &streams_lock).await?;
+            display::flush_pending_lines(&settings, &streams_lock).await?;
+            let mut streams = streams_lock.lock().await;
+            if settings.state.fail_on_matched() {
+                display::print_error(&mut streams, None, "a line matched the fail_on pattern".to_string()).await?;
+                display::finish(&mut streams);
+                return Ok(error::EXIT_FAIL_ON);
+            }
+            display::finish(&mut streams);
+            return Ok(0);
+        }
+        let log_params = kubernetes::new_log_param(&settings, false);
+        let clients = new_clients(&settings).await?;
+        for warning in kubernetes::version_skew_warnings(&clients).await {
+            let mut streams = streams_lock.lock().await;
+            display::print_color(&mut streams.err, None, warning).await?;
+        }
+        kubernetes::pick_namespaces(&clients, &mut settings).await?;
+
+        let namespaces = new_namespaces(&clients, &settings).await?;
+        let mut listed = namespaces.list_pods(&settings.pod_search).await?;
+        if settings.pick {
+            // the selection replaces the pod search so that the picked pods are also the ones followed after a refresh
+            settings.pod_search = picker::pick_pods(listed.iter().map(|listed_pod| listed_pod.name.clone()).collect())?;
+            listed.retain(|listed_pod| settings.pod_search.is_match(&listed_pod.name));
+        }
+        for explanation in namespaces.take_explanations() {
+            let mut streams = streams_lock.lock().await;
+            display::print_status(&mut streams, None, explanation).await?;
+        }
+        let pod_cnt = listed.len();
+        let mut colors_params = display::ColorParams::new(&settings, pod_cnt);
+        let colors = display::Colors::new(&mut colors_params);
+        let pods = kubernetes::Pods::new(namespaces.clone(), &settings, colors, listed)?;
+        let pods_lock = pods.to_mutex();
+
+        let running_pods = kubernetes::new_running_pods();
+
+        if let settings::Mode::Ls = settings.mode {
+            let pods = pods_lock.lock().await;
+            let mut streams = streams_lock.lock().await;
+            match settings.output {
+                display::OutputMode::Json => display::print_pod_inventory(&pods, &mut streams).await?,
+                _ => display::print_pod_list(&pods, &mut streams).await?,
+            }
+            display::finish(&mut streams);
+            return Ok(0);
+        }
+        if pod_cnt == 0 && (settings.no_follow || matches!(settings.mode, settings::Mode::Export(_))) {
+            // nothing would ever be printed
+            let mut streams = streams_lock.lock().await;
+            display::finish(&mut streams);
+            return Err(Errors::NoPodMatched(settings.pod_search.as_str().to_string()));
+        }
+
+        {
+            let mut streams = streams_lock.lock().await;
+            display::print_status(
+                &mut streams,
+                None,
+                format!("initial search found {} pods across {} namespaces", pod_cnt, namespaces.items.len()),
+            )
+            .await?;
+        }
+        if let Some(metrics_addr) = &settings.metrics_addr {
+            let listener = metrics::bind(metrics_addr).await?;
+            tokio::spawn(metrics::serve(listener));
+        }
+        if let settings::Mode::Export(export) = &settings.mode {
+            return export_lines(&settings, export, &pods_lock).await.map(|_| 0);
+        }
+        let signals = self.signals;
+        let exit_on_match = Arc::new(tokio::sync::Notify::new());
+        let streaming = async {
+            if settings.is_previous_lines() || settings.no_follow {
+                print_previous_lines(&settings, &pods_lock, &streams_lock).await?;
+            }
+
+            if !settings.no_follow {
+                #[cfg(unix)]
+                if signals {
+                    tokio::spawn(control::reload_on_sighup(
+                        settings.clone(),
+                        clients.clone(),
+                        pods_lock.clone(),
+                        streams_lock.clone(),
+                    ));
+                }
+                if settings.wrap_width.is_some() {
+                    tokio::spawn(control::follow_terminal_resize(settings.state.clone()));
+                }
+                if let Some(config_path) = settings.config_path.as_ref().filter(|_| signals) {
+                    tokio::spawn(control::reload_on_config_change(
+                        config_path.clone(),
+                        settings.clone(),
+                        clients.clone(),
+                        pods_lock.clone(),
+                        streams_lock.clone(),
+                    ));
+                }
+                let follow = follow_pods(
+                    &settings,
+                    log_params,
+                    pods_lock.clone(),
+                    streams_lock.clone(),
+                    running_pods.clone(),
+                    pod_cnt,
+                    exit_on_match.clone(),
+                );
+                let follow = async {
+                    return match settings.job {
+                        Some(_) => follow_jobs(&settings, &namespaces, follow, running_pods).await,
+                        None => follow.await,
+                    };
+                };
+                match settings.timeout {
+                    Some(timeout) => {
+                        // the timeout elapsing is the expected way to stop following
+                        if let Ok(res) = tokio::time::timeout(tokio::time::Duration::from_secs(timeout), follow).await {
+                            res?;
+                        }
+                    }
+                    None => follow.await?,
+                }
+            }
+            return Ok::<(), Errors>(());
+        };
+        // on a signal the streaming future is dropped, which aborts the pod streams
+        let mut job_failed = None;
+        let interrupted = tokio::select! {
+            res = streaming => {
+                match res {
+                    // reported once the output is finished
+                    Err(err @ Errors::JobFailed(_)) => job_failed = Some(err),
+                    res => res?,
+                }
+                false
+            }
+            res = control::shutdown_signal(), if signals => {
+                res?;
+                true
+            }
+            _ = exit_on_match.notified() => false,
+            message = output_limit_reached(&settings) => {
+                let mut streams = streams_lock.lock().await;
+                display::print_status(&mut streams, None, message).await?;
+                false
+            }
+        };
+        display::flush_pending_lines(&settings, &streams_lock).await?;
+        kubernetes::save_checkpoint(&pods_lock).await?;
+
+        let mut streams = streams_lock.lock().await;
+        if interrupted {
+            display::finish(&mut streams);
+            return Ok(error::EXIT_INTERRUPTED);
+        }
+        if settings.state.fail_on_matched() {
+            display::print_error(&mut streams, None, "a line matched the fail_on pattern".to_string()).await?;
+            display::finish(&mut streams);
+            return Ok(error::EXIT_FAIL_ON);
+        }
+        display::finish(&mut streams);
+        return match job_failed {
+            Some(err) => Err(err),
+            None => Ok(0),
+        };
+    }
+}
+
+/// the offline mode and the sources given to the builder reach no cluster, without clients the namespace pick is skipped too
+async fn new_clients(settings: &settings::SettingsValidated) -> Result<Vec<kubernetes::ContextClient>, Errors> {
+    if settings.offline.is_some() || settings.sources.len() > 0 {
+        return Ok(vec![]);
+    }
+    return kubernetes::new_clients(settings).await;
+}
+
+/// the namespaces of the offline fixtures, of the sources given to the builder or of the clusters
+async fn new_namespaces(clients: &[kubernetes::ContextClient], settings: &settings::SettingsValidated) -> Result<kubernetes::Namespaces, Errors> {
+    if let Some(fixture_dir) = &settings.offline {
+        return kubernetes::Namespaces::fixtures(fixture_dir, settings);
+    }
+    if settings.sources.len() > 0 {
+        return kubernetes::Namespaces::from_sources(&settings.sources, settings);
+    }
+    let namespaces = kubernetes::Namespaces::new(clients, settings);
+    namespaces.check_permissions().await?;
+    return Ok(namespaces);
+}
+
+/// resolves with a summary once the output reached max_output_bytes or max_duration elapsed, never without them
+async fn output_limit_reached(settings: &settings::SettingsValidated) -> String {
+    if settings.max_output_bytes.is_none() && settings.max_duration.is_none() {
+        return std::future::pending().await;
+    }
+    let start = tokio::time::Instant::now();
+    loop {
+        let output_bytes = metrics::get(&metrics::OUTPUT_BYTES);
+        let reason = match (settings.max_output_bytes, settings.max_duration) {
+            (Some(max_output_bytes), _) if output_bytes >= max_output_bytes.value => Some("max output size reached"),
+            (_, Some(max_duration)) if start.elapsed() >= max_duration => Some("max duration reached"),
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            return format!(
+                "--- stopping, {reason} after {}s: {} lines received, {output_bytes} bytes written",
+                start.elapsed().as_secs(),
+                metrics::get(&metrics::LINES_RECEIVED)
+            );
+        }
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    }
+}
+
+/// follow the pods until the jobs matching the job setting have ended, then let the streams of their pods end
+async fn follow_jobs(
+    settings: &settings::SettingsValidated,
+    namespaces: &kubernetes::Namespaces,
+    follow: impl std::future::Future<Output = Result<(), Errors>>,
+    running_pods: types::ArcMutex<HashSet<String>>,
+) -> Result<(), Errors> {
+    let jobs_ended = async {
+        loop {
+            match namespaces.jobs_state().await {
+                Ok(kubernetes::JobsState::Running) => {}
+                Ok(state) => return Ok(state),
+                Err(err) if err.is_retryable() => tracing::warn!(error = %err, "getting the jobs state failled, retrying on the next loop"),
+                Err(err) => return Err(err),
+            }
+            tokio::time::sleep(settings.loop_pause).await;
+        }
+    };
+    tokio::pin!(follow);
+    let state = tokio::select! {
+        res = &mut follow => return res,
+        state = jobs_ended => state?,
+    };
+    let streams_ended = async {
+        while !running_pods.lock().await.is_empty() {
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        }
+    };
+    tokio::select! {
+        res = &mut follow => res?,
+        _ = tokio::time::timeout(JOB_DRAIN_DELAY, streams_ended) => {}
+    }
+    return match state {
+        kubernetes::JobsState::Failed(jobs) => Err(Errors::JobFailed(jobs.join(", "))),
+        _ => Ok(()),
+    };
+}
+
+/// the previous lines of the pods merged by timestamp while they are read: each log stream is read ahead by a bounded channel,
+/// so that the memory does not grow with the number of lines (eg: --since 24h on many pods)
+/// the logs of a pod are in their timestamp order, the merge is stable: the lines of the pods sharing the same date, like the ones
+/// with an unparseable timestamp, keep the order of the pods and their receive order
+struct MergedLines {
+    receivers: Vec<tokio::sync::mpsc::Receiver<kubernetes::PreviousLine>>,
+    tasks: Vec<Option<JoinHandle<Result<(), Errors>>>>,
+    /// the next line of each stream, by date then stream index
+    heads: BinaryHeap<Reverse<(DateTime<FixedOffset>, usize)>>,
+    pending: Vec<Option<kubernetes::PreviousLine>>,
+}
+
+impl MergedLines {
+    async fn new(
+        streams: Vec<(tokio::sync::mpsc::Receiver<kubernetes::PreviousLine>, JoinHandle<Result<(), Errors>>)>,
+    ) -> Result<MergedLines, Errors> {
+        let mut merged = MergedLines {
+            receivers: vec![],
+            tasks: vec![],
+            heads: BinaryHeap::new(),
+            pending: vec![],
+        };
+        for (idx, (receiver, task)) in streams.into_iter().enumerate() {
+            merged.receivers.push(receiver);
+            merged.tasks.push(Some(task));
+            merged.pending.push(None);
+            merged.read_ahead(idx).await?;
+        }
+        return Ok(merged);
+    }
+
+    /// the next line of the stream becomes its head, the error of the stream is returned once it's read
+    async fn read_ahead(&mut self, idx: usize) -> Result<(), Errors> {
+        match self.receivers[idx].recv().await {
+            Some(line) => {
+                self.heads.push(Reverse((line.0, idx)));
+                self.pending[idx] = Some(line);
+            }
+            None => {
+                if let Some(task) = self.tasks[idx].take() {
+                    task.await.map_err(|err| Errors::Other(err.to_string()))??;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    async fn next(&mut self) -> Result<Option<kubernetes::PreviousLine>, Errors> {
+        let Some(Reverse((_, idx))) = self.heads.pop() else {
+            return Ok(None);
+        };
+        let line = self.pending[idx].take();
+        self.read_ahead(idx).await?;
+        return Ok(line);
+    }
+}
+
+/// fetch the logs of all the pods, or of their restarted containers selected by previous_container, merged by timestamp
+async fn fetch_previous_lines(
+    previous_lines_settings: kube::api::LogParams,
+    tail_overrides: bool,
+    settings: &settings::SettingsValidated,
+    pods_lock: &types::ArcMutex<kubernetes::Pods>,
+) -> Result<MergedLines, Errors> {
+    let mut streams = vec![];
+    {
+        let pods = pods_lock.lock().await;
+        for pod in pods.items.iter() {
+            let containers = match &settings.previous_container {
+                Some(search) => pod.restarted_containers(search).into_iter().map(Some).collect(),
+                None => vec![None],
+            };
+            for container in containers {
+                let pod = pod.clone();
+                let settings = settings.for_namespace(&pod.namespace.name).clone();
+                let mut previous_lines_settings = previous_lines_settings.clone();
+                previous_lines_settings.container = container;
+                if tail_overrides {
+                    previous_lines_settings.tail_lines = settings.tail_lines_for(&pod.name);
+                    previous_lines_settings.since_seconds = settings.since_seconds;
+                }
+                let (sender, receiver) = tokio::sync::mpsc::channel(PREVIOUS_LINES_READ_AHEAD);
+                let task: JoinHandle<Result<(), Errors>> = tokio::spawn(async move {
+                    return pod.send_previous_log_lines(&previous_lines_settings, &settings, sender).await;
+                });
+                streams.push((receiver, task));
+            }
+        }
+    }
+    return MergedLines::new(streams).await;
+}
+
+async fn print_previous_lines(
+    settings: &settings::SettingsValidated,
+    pods_lock: &types::ArcMutex<kubernetes::Pods>,
+    streams_lock: &types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let mut log_lines = fetch_previous_lines(kubernetes::new_log_param(settings, true), true, settings, pods_lock).await?;
+    let out = streams_lock.lock().await.out.clone();
+    let layout = pods_lock.lock().await.layout.clone();
+    let mut pod_files: HashMap<PathBuf, sinks::PodFile> = HashMap::new();
+    let mut history_ends: HashMap<String, DateTime<FixedOffset>> = HashMap::new();
+    while let Some((date, line, pod)) = log_lines.next().await? {
+        if let Some(output_dir) = &settings.output_dir {
+            let raw_line = lineproc::redact_raw(&settings.redactor, &line);
+            if settings.sink_file_filter.as_ref().map_or(true, |filter| filter.is_match(&raw_line)) {
+                write_pod_file(&mut pod_files, output_dir, &pod, &raw_line, settings)?;
+            }
+        }
+        let prefix = display::Prefix::new(&pod, &layout, settings);
+        display::print_log_line(line, settings.for_namespace(&pod.namespace.name), &prefix, &out, &pod).await?;
+        // the lines are sorted, the last one of each pod is kept
+        history_ends.insert(pod.id(), date);
+    }
+    let mut pods = pods_lock.lock().await;
+    for (pod_id, history_end) in history_ends {
+        pods.set_history_end(&pod_id, history_end);
+    }
+    return Ok(());
+}
+
+/// write the lines of the export time window matching the filters to a single file and/or a file per pod
+async fn export_lines(
+    settings: &settings::SettingsValidated,
+    export: &settings::Export,
+    pods_lock: &types::ArcMutex<kubernetes::Pods>,
+) -> Result<(), Errors> {
+    let log_params = kubernetes::new_export_log_param(settings, export);
+    let mut log_lines = fetch_previous_lines(log_params, false, settings, pods_lock).await?;
+    let mut file = match &export.file {
+        Some(path) => Some(std::io::LineWriter::new(
+            std::fs::File::create(path).map_err(|err| Errors::Other(format!("creating {}: {err}", path.display())))?,
+        )),
+        None => None,
+    };
+    let mut pod_files: HashMap<PathBuf, sinks::PodFile> = HashMap::new();
+    while let Some((date, line, pod)) = log_lines.next().await? {
+        if date < export.since_time || export.until.map_or(false, |until| date > until) {
+            continue;
+        }
+        let line = match display::process_log_line(line, settings) {
+            Some(line) => line,
+            None => continue,
+        };
+        if let Some(file) = &mut file {
+            writeln!(file, "{} {}", pod.id(), line.trim_end_matches("\n")).map_err(|err| Errors::Other(format!("writing export file: {err}")))?;
+        }
+        if let Some(output_dir) = &settings.output_dir {
+            write_pod_file(&mut pod_files, output_dir, &pod, &line, settings)?;
+        }
+    }
+    return Ok(());
+}
+
+/// the lines are sorted by timestamp across pods, so the file of a workload grouping several pods is sorted too
+fn write_pod_file(
+    pod_files: &mut HashMap<PathBuf, sinks::PodFile>,
+    output_dir: &PathBuf,
+    pod: &kubernetes::Pod,
+    line: &str,
+    settings: &settings::SettingsValidated,
+) -> Result<(), Errors> {
+    let path = sinks::pod_file_path(output_dir, pod, &settings.group_files, settings.compress);
+    if !pod_files.contains_key(&path) {
+        let pod_file = sinks::PodFile::open(output_dir, path.clone(), &settings.rotation, settings.compress)?;
+        pod_files.insert(path.clone(), pod_file);
+    }
+    if let Some(pod_file) = pod_files.get_mut(&path) {
+        pod_file.write_line(line)?;
+    }
+    return Ok(());
+}
+
+async fn stream_pods(
+    settings: settings::SettingsValidated,
+    sender: tokio::sync::mpsc::Sender<Result<kubernetes::LogLine, Errors>>,
+) -> Result<(), Errors> {
+    let clients = new_clients(&settings).await?;
+    let namespaces = new_namespaces(&clients, &settings).await?;
+    let listed = namespaces.list_pods(&settings.pod_search).await?;
+    let mut colors_params = display::ColorParams::new(&settings, listed.len());
+    let colors = display::Colors::new(&mut colors_params);
+    let mut pods = kubernetes::Pods::new(namespaces, &settings, colors, listed)?;
+    // a single request per pod: the existing lines then, unless no_follow is set, the new ones
+    let mut log_params = kubernetes::new_log_param(&settings, true);
+    log_params.follow = !settings.no_follow;
+    let mut stream_tasks: HashMap<String, JoinHandle<()>> = HashMap::new();
+    loop {
+        stream_tasks.retain(|_, stream_task| !stream_task.is_finished());
+        for pod in pods.items.iter() {
+            if !pod.is_running() || stream_tasks.contains_key(&pod.id()) {
+                continue;
+            }
+            let pod = pod.clone();
+            let settings = settings.for_namespace(&pod.namespace.name).clone();
+            let mut log_params = log_params.clone();
+            log_params.tail_lines = settings.tail_lines_for(&pod.name);
+            let sender = sender.clone();
+            let stream_task = tokio::spawn(async move {
+                if let Err(err) = pod.stream_logs(log_params, settings, sender.clone()).await {
+                    let _ = sender.send(Err(err)).await;
+                }
+            });
+            stream_tasks.insert(pod.id(), stream_task);
+        }
+        if settings.no_follow {
+            return Ok(());
+        }
+        tokio::time::sleep(settings.loop_pause).await;
+        if sender.is_closed() {
+            return Ok(());
+        }
+        refresh_pods(&mut pods).await?;
+    }
+}
+
+/// handles of the pod stream tasks, aborted when following stops (eg: on ctrl + c)
+struct StreamTasks(HashMap<String, tokio::task::AbortHandle>);
+
+impl Drop for StreamTasks {
+    fn drop(&mut self) {
+        for (_, stream_task) in self.0.drain() {
+            stream_task.abort();
+        }
+    }
+}
+
+/// failled streams in a row of a pod, it's followed again once retry_at is reached, unless it's parked
+struct StreamFailures {
+    count: u32,
+    retry_at: tokio::time::Instant,
+}
+
+impl StreamFailures {
+    fn is_parked(&self) -> bool {
+        return self.count >= STREAM_FAILURES_BEFORE_PARKING;
+    }
+
+    fn is_waiting(&self) -> bool {
+        return self.is_parked() || self.retry_at > tokio::time::Instant::now();
+    }
+
+    /// count a failure of a stream started at stream_start, returns the wait before the next attempt
+    fn record(&mut self, stream_start: tokio::time::Instant) -> tokio::time::Duration {
+        if stream_start.elapsed() > STREAM_FAILURE_RESET {
+            self.count = 0;
+        }
+        self.count += 1;
+        // a stream interrupted once is reconnected right away, the wait starts with the second failure
+        let delay = match self.count {
+            1 => tokio::time::Duration::ZERO,
+            count => STREAM_RETRY_DELAY * 2u32.pow(count - 2),
+        };
+        self.retry_at = tokio::time::Instant::now() + delay;
+        return delay;
+    }
+}
+
+/// the namespaces which don't exist yet (eg: the one of a pull request environment) are checked until they are created,
+/// their pods are then found by the pod refresh like the other ones
+async fn wait_for_namespaces(
+    loop_pause: std::time::Duration,
+    pods_lock: types::ArcMutex<kubernetes::Pods>,
+    streams_lock: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let namespaces = pods_lock.lock().await.namespaces.items.clone();
+    let mut missing = vec![];
+    for namespace in namespaces {
+        if !namespace.exists().await {
+            let mut streams = streams_lock.lock().await;
+            let message = format!("namespace {} does not exist yet, waiting for it", namespace.qualified_name);
+            display::print_status(&mut streams, None, message).await?;
+            missing.push(namespace);
+        }
+    }
+    while missing.len() > 0 {
+        tokio::time::sleep(loop_pause).await;
+        let mut still_missing = vec![];
+        for namespace in missing {
+            if !namespace.exists().await {
+                still_missing.push(namespace);
+                continue;
+            }
+            let mut streams = streams_lock.lock().await;
+            display::print_status(&mut streams, None, format!("namespace {} created", namespace.qualified_name)).await?;
+        }
+        missing = still_missing;
+    }
+    return Ok(());
+}
+
+async fn follow_pods(
+    settings: &settings::SettingsValidated,
+    log_params: kube::api::LogParams,
+    pods_lock: types::ArcMutex<kubernetes::Pods>,
+    streams_lock: types::ArcMutex<display::Streams>,
+    running_pods: types::ArcMutex<HashSet<String>>,
+    pod_cnt: usize,
+    exit_on_match: Arc<tokio::sync::Notify>,
+) -> Result<(), Errors> {
+    if let Some(window) = settings.ordered {
+        let window = tokio::time::Duration::from_millis(window);
+        tokio::spawn(display::flush_reorder_buffer(window, settings.clone(), streams_lock.clone()));
+    }
+    if let Some(window) = settings.aggregate {
+        let window = tokio::time::Duration::from_millis(window);
+        tokio::spawn(display::flush_aggregate_buffer(window, settings.clone(), streams_lock.clone()));
+    }
+    if settings.timeline.is_some() {
+        tokio::spawn(timeline::follow_timeline(settings.clone(), pods_lock.clone(), streams_lock.clone()));
+    }
+    if settings.stdin.is_some() {
+        tokio::spawn(stdin::follow_stdin(settings.clone(), pods_lock.clone(), streams_lock.clone()));
+    }
+    tokio::spawn(wait_for_namespaces(settings.loop_pause, pods_lock.clone(), streams_lock.clone()));
+    if settings.sink_http.is_some() {
+        let interval = tokio::time::Duration::from_millis(settings.sink_http_interval);
+        tokio::spawn(sinks::flush_http_sink(interval, streams_lock.clone()));
+    }
+    if settings.state_file.is_some() {
+        tokio::spawn(kubernetes::save_checkpoint_loop(pods_lock.clone()));
+    }
+    if settings.control {
+        tokio::spawn(control::read_commands(settings.clone(), pods_lock.clone(), streams_lock.clone()));
+    }
+    if let Some(interval) = settings.stats {
+        let interval = tokio::time::Duration::from_secs(interval);
+        tokio::spawn(stats::print_stats_loop(interval, pods_lock.clone(), streams_lock.clone()));
+    }
+    if let Some(interval) = settings.heartbeat {
+        tokio::spawn(stats::print_heartbeat_loop(interval, pods_lock.clone(), streams_lock.clone()));
+    }
+    if let Some(interval) = settings.spotlight {
+        tokio::spawn(stats::spotlight_loop(interval, pods_lock.clone(), streams_lock.clone()));
+    }
+    if let Some(target) = &settings.sink_syslog {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        {
+            let mut streams = streams_lock.lock().await;
+            streams.syslog_sink = Some(sender);
+        }
+        tokio::spawn(sinks::forward_syslog(target.clone(), receiver, streams_lock.clone()));
+    }
+    if let Some(path) = &settings.sink_sqlite {
+        let connection = capture::open(path)?;
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        {
+            let mut streams = streams_lock.lock().await;
+            streams.sqlite_sink = Some(sender);
+        }
+        let path = path.clone();
+        let streams_lock = streams_lock.clone();
+        tokio::spawn(async move {
+            let res = tokio::task::spawn_blocking(move || capture::write_lines(path, connection, receiver)).await;
+            if let Ok(Err(err)) = res {
+                let mut streams = streams_lock.lock().await;
+                streams.sqlite_sink = None;
+                display::print_error(&mut streams, None, format!("failled to write the sqlite capture: {err}")).await?;
+            }
+            return Ok::<(), Errors>(());
+        });
+    }
+    let mut pacer = poll::PollPacer::new(settings.loop_pause, settings.max_loop_pause);
+    let mut no_pod_found = pod_cnt == 0;
+    // the pods listed after startup are backfilled with new_pod_tail
+    let startup_pods: HashSet<String> = pods_lock.lock().await.items.iter().map(|pod| pod.id()).collect();
+    let mut followed_pods: HashSet<String> = HashSet::new();
+    let mut stream_tasks = StreamTasks(HashMap::new());
+    let stream_failures: types::ArcMutex<HashMap<String, StreamFailures>> = Arc::new(tokio::sync::Mutex::new(HashMap::new()));
+    loop {
+        if no_pod_found {
+            // printed once, the pods are then waited for (eg: the pods of a job not created yet)
+            let mut streams = streams_lock.lock().await;
+            display::print_color(&mut streams.err, None, "no pod found :(".to_string()).await?;
+        }
+        let (pod_list, explanations, dropped_ids, readiness_changes, waiting_changes, restart_changes) = {
+            let mut pods = pods_lock.lock().await;
+            (
+                pods.items.clone(),
+                pods.namespaces.take_explanations(),
+                pods.dropped_ids.drain(..).collect::<Vec<String>>(),
+                pods.readiness_changes.drain(..).collect::<Vec<kubernetes::ReadinessChange>>(),
+                pods.waiting_changes.drain(..).collect::<Vec<kubernetes::WaitingChange>>(),
+                pods.restart_changes.drain(..).collect::<Vec<kubernetes::RestartChange>>(),
+            )
+        };
+        // a followed pod which is not listed anymore was deleted or stopped matching, it appears again if it comes back
+        let listed_ids: HashSet<String> = pod_list.iter().map(|pod| pod.id()).collect();
+        let gone_ids: Vec<String> = followed_pods.iter().filter(|pod_id| !listed_ids.contains(*pod_id)).cloned().collect();
+        for pod_id in gone_ids {
+            followed_pods.remove(&pod_id);
+            sinks::send_lifecycle_event(&streams_lock, sinks::LifecycleEvent::Disappeared, &pod_id, None, String::new()).await;
+        }
+        for explanation in explanations {
+            let mut streams = streams_lock.lock().await;
+            display::print_status(&mut streams, None, explanation).await?;
+        }
+        for change in restart_changes {
+            let (event, message) = match &change.crash {
+                Some(crash) => (
+                    sinks::LifecycleEvent::Crashed,
+                    format!(">>> {} crashed, {} (restarts={})", change.pod_id, crash, change.restarts),
+                ),
+                None => (
+                    sinks::LifecycleEvent::Restarted,
+                    format!(">>> {} restarted (restarts={})", change.pod_id, change.restarts),
+                ),
+            };
+            {
+                let mut streams = streams_lock.lock().await;
+                display::print_status(&mut streams, Some(change.color), message).await?;
+            }
+            let detail = change.crash.unwrap_or_default();
+            sinks::send_lifecycle_event(&streams_lock, event, &change.pod_id, Some(change.color), detail).await;
+        }
+        for change in waiting_changes {
+            // the pod gets its color once it runs, amber until then
+            let mut streams = streams_lock.lock().await;
+            let message = format!("... {} waiting: {}", change.pod_id, change.reason);
+            display::print_status(&mut streams, Some(colors_transform::Rgb::from(255.0, 176.0, 0.0)), message).await?;
+        }
+        for change in readiness_changes {
+            let message = match (change.ready, change.reason.as_str()) {
+                (true, _) => format!(">>> {} became Ready", change.pod_id),
+                (false, "") => format!(">>> {} NotReady", change.pod_id),
+                (false, reason) => format!(">>> {} NotReady ({})", change.pod_id, reason),
+            };
+            let mut streams = streams_lock.lock().await;
+            display::print_status(&mut streams, Some(change.color), message).await?;
+        }
+        for pod_id in dropped_ids {
+            if let Some(stream_task) = stream_tasks.0.remove(&pod_id) {
+                tracing::info!(pod = %pod_id, "stream aborted: pod dropped after reload");
+                stream_task.abort();
+            }
+            {
+                let mut running_pods = running_pods.lock().await;
+                running_pods.remove(&pod_id);
+            }
+            let mut streams = streams_lock.lock().await;
+            display::print_status(&mut streams, None, format!("--- {} dropped after reload", pod_id)).await?;
+        }
+        let running_pods = running_pods.clone();
+        let listed_cnt = pod_list.len();
+        for pod in pod_list {
+            let pod_id = pod.id();
+            if !pod.is_running() {
+                tracing::debug!(pod = %pod_id, phase = %pod.phase(), "pod not running anymore");
+                {
+                    let mut pods = pods_lock.lock().await;
+                    if pods.remove_pod(&pod).await {
+                        pods.colors.set_color_to_unused(pod.color);
+                    }
+                }
+                {
+                    let mut running_pods = running_pods.lock().await;
+                    running_pods.remove(&pod_id);
+                }
+                continue;
+            }
+
+            let already_running = {
+                let running_pods = running_pods.lock().await;
+                running_pods.get(&pod_id).is_some()
+            };
+            if already_running {
+                continue;
+            }
+            let failling = {
+                let stream_failures = stream_failures.lock().await;
+                stream_failures.get(&pod_id).is_some_and(|failures| failures.is_waiting())
+            };
+            if failling {
+                continue;
+            }
+            {
+                let mut running_pods = running_pods.lock().await;
+                running_pods.insert(pod_id.clone());
+            }
+            let discovered = !startup_pods.contains(&pod_id) && !followed_pods.contains(&pod_id);
+            if !followed_pods.insert(pod_id.clone()) {
+                tracing::info!(pod = %pod_id, "reconnecting to a pod followed before");
+                metrics::inc(&metrics::RECONNECTS);
+            } else {
+                sinks::send_lifecycle_event(
+                    &streams_lock,
+                    sinks::LifecycleEvent::Appeared,
+                    &pod_id,
+                    Some(pod.color),
+                    pod.start_details(),
+                )
+                .await;
+            }
+            let log_params = log_params.clone();
+            let streams_lock = streams_lock.clone();
+            let pods_lock = pods_lock.clone();
+            let settings = settings.for_namespace(&pod.namespace.name).clone();
+            let running_pods = running_pods.clone();
+            let stream_failures = stream_failures.clone();
+            let exit_on_match = exit_on_match.clone();
+
+            let stream_task_id = pod_id.clone();
+            let stream_task = tokio::spawn(async move {
+                {
+                    let mut streams = streams_lock.lock().await;
+                    if let Some(summary) = &mut streams.summary {
+                        summary.register(&pod);
+                    }
+                    let message = format!("+++ {} just started ({})", pod_id, pod.start_details());
+                    if let Some(message) = display::banner_message(&settings.start_banner, &pod, message) {
+                        display::print_banner(&mut streams, &pod_id, true, Some(pod.color), message).await?;
+                    }
+                }
+
+                // the stream starts after the previous lines printed for the pod, or the last line seen by a previous invocation,
+                // and not before the since window so that the pods without previous lines (eg: created later) show it too
+                let log_params = {
+                    let mut pods = pods_lock.lock().await;
+                    let history_end = pods.take_history_end(&pod_id);
+                    pods.merge_checkpoint();
+                    let last_seen = pods.checkpoint.as_ref().and_then(|checkpoint| checkpoint.last_seen(&pod_id));
+                    match (last_seen.max(history_end).max(settings.since_time), &settings.new_pod_tail) {
+                        (None, Some(new_pod_tail)) if discovered => kubernetes::backfill_log_param(log_params, new_pod_tail),
+                        (start, _) => kubernetes::resume_log_param(log_params, start),
+                    }
+                };
+                let stop_on_match = settings.exit_on_match;
+                let stop_banner = settings.stop_banner.clone();
+                metrics::inc(&metrics::ACTIVE_STREAMS);
+                let stream_start = tokio::time::Instant::now();
+                let print_res = pod.print_logs(log_params, settings, pods_lock.clone(), streams_lock.clone()).await;
+                metrics::dec(&metrics::ACTIVE_STREAMS);
+                let stop_message = match &print_res {
+                    Ok(kubernetes::StreamEnd::MaxLines(max_lines)) => Some(format!("--- {} stopped after {} lines", pod_id, max_lines)),
+                    Ok(kubernetes::StreamEnd::MaxMatches(max_matches)) => Some(format!("--- {} stopped after {} matches", pod_id, max_matches)),
+                    _ => None,
+                };
+                if let Some(stop_message) = stop_message {
+                    // the pod is kept as running so that it's not followed again
+                    let mut streams = streams_lock.lock().await;
+                    display::print_status(&mut streams, Some(pod.color), stop_message).await?;
+                    if stop_on_match && matches!(print_res, Ok(kubernetes::StreamEnd::MaxMatches(_))) {
+                        exit_on_match.notify_one();
+                    }
+                    return Ok(());
+                }
+                let failled = {
+                    let mut stream_failures = stream_failures.lock().await;
+                    match &print_res {
+                        Ok(_) => {
+                            stream_failures.remove(&pod_id);
+                            None
+                        }
+                        Err(_) => {
+                            let failures = stream_failures.entry(pod_id.clone()).or_insert(StreamFailures {
+                                count: 0,
+                                retry_at: tokio::time::Instant::now(),
+                            });
+                            Some((failures.record(stream_start), failures.count))
+                        }
+                    }
+                };
+                let parked = failled.is_some_and(|(_, failures)| failures >= STREAM_FAILURES_BEFORE_PARKING);
+                {
+                    // a parked pod is kept so that the refresh doesn't find it again, the loop skips it instead
+                    let mut pods = pods_lock.lock().await;
+                    if parked {
+                        pods.explain_pod(&pod, "skipped: parked after repeated stream failures");
+                    } else if pods.remove_pod(&pod).await {
+                        // the refresh loop already released the color of a pod which stopped running
+                        pods.colors.set_color_to_unused(pod.color);
+                    }
+                }
+                {
+                    let mut running_pods = running_pods.lock().await;
+                    running_pods.remove(&pod_id);
+                }
+                match (print_res, failled) {
+                    (Err(err), Some((delay, failures))) => {
+                        tracing::warn!(pod = %pod_id, error = %err, retryable = err.is_retryable(), "log stream failled");
+                        pod.stats.errors.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        metrics::inc(&metrics::API_ERRORS);
+                        // either way the pod is no longer running, so it's followed again by a next refresh if it still exists,
+                        // once the wait for its stream to be retried is over
+                        let message = if parked {
+                            format!(
+                                "--- {} parked after {} failled streams, not followed anymore ({})",
+                                pod_id,
+                                failures,
+                                err.to_string()
+                            )
+                        } else if delay.is_zero() {
+                            format!("--- {} stream interrupted, reconnecting ({})", pod_id, err.to_string())
+                        } else if err.is_retryable() {
+                            format!(
+                                "--- {} stream interrupted, reconnecting in {}s ({})",
+                                pod_id,
+                                delay.as_secs(),
+                                err.to_string()
+                            )
+                        } else {
+                            format!("--- {} failled miserably, retrying in {}s ({})", pod_id, delay.as_secs(), err.to_string())
+                        };
+                        {
+                            let mut streams = streams_lock.lock().await;
+                            display::print_error(&mut streams, Some(pod.color), message).await?;
+                        }
+                        return Err(err);
+                    }
+                    _ => Ok({
+                        let mut streams = streams_lock.lock().await;
+                        let message = format!("--- {} gracefully stopped (maybe)", pod_id);
+                        if let Some(message) = display::banner_message(&stop_banner, &pod, message) {
+                            display::print_banner(&mut streams, &pod_id, false, Some(pod.color), message).await?;
+                        }
+                    }),
+                }
+            });
+            stream_tasks.0.insert(stream_task_id, stream_task.abort_handle());
+        }
+        no_pod_found = false;
+        display::flush_banners(&mut *streams_lock.lock().await).await?;
+        tokio::time::sleep(pacer.next_pause()).await;
+        {
+            let mut pods = pods_lock.lock().await;
+            refresh_pods(&mut pods).await?;
+            // pods stopped or found since the last loop, or changes to report
+            pacer.record(pods.items.len() != listed_cnt || pods.has_pending_changes());
+        }
+    }
+}
+
+/// a refresh failing on a transient error is given up until the next loop instead of stopping the program
+async fn refresh_pods(pods: &mut kubernetes::Pods) -> Result<(), Errors> {
+    return match pods.refresh().await {
+        Err(err) if err.is_retryable() => {
+            tracing::warn!(error = %err, "refreshing pods failled, retrying on the next loop");
+            metrics::inc(&metrics::API_ERRORS);
+            Ok(())
+        }
+        res => res,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_pod() -> Arc<kubernetes::Pod> {
+        let settings = settings::Settings::try_parse_from(["rusty_stern"]).unwrap().to_validated().unwrap();
+        let namespaces = kubernetes::Namespaces::offline(&[(None, "shop".to_string())], &settings).unwrap();
+        let pod_api = source::running_pod("shop", "api-1", "main");
+        let color = colors_transform::Rgb::from(0.0, 0.0, 0.0);
+        return Arc::new(kubernetes::Pod::new(
+            "api-1".to_string(),
+            &pod_api,
+            namespaces.items[0].clone(),
+            color,
+            false,
+            None,
+        ));
+    }
+
+    fn previous_lines(
+        pod: &Arc<kubernetes::Pod>,
+        lines: &[(&str, &str)],
+        end: Result<(), Errors>,
+    ) -> (tokio::sync::mpsc::Receiver<kubernetes::PreviousLine>, JoinHandle<Result<(), Errors>>) {
+        let (sender, receiver) = tokio::sync::mpsc::channel(PREVIOUS_LINES_READ_AHEAD);
+        let lines: Vec<kubernetes::PreviousLine> = lines
+            .iter()
+            .map(|(date, message)| (DateTime::parse_from_rfc3339(date).unwrap(), message.to_string(), pod.clone()))
+            .collect();
+        let task = tokio::spawn(async move {
+            for line in lines {
+                let _ = sender.send(line).await;
+            }
+            return end;
+        });
+        return (receiver, task);
+    }
+
+    async fn merge(merged: &mut MergedLines) -> Result<Vec<String>, Errors> {
+        let mut messages = vec![];
+        while let Some((_, message, _)) = merged.next().await? {
+            messages.push(message);
+        }
+        return Ok(messages);
+    }
+
+    #[tokio::test]
+    async fn merges_the_sorted_streams_by_date_then_stream_order() {
+        let pod = test_pod().await;
+        let streams = vec![
+            previous_lines(
+                &pod,
+                &[
+                    ("2024-01-01T10:00:00Z", "a0"),
+                    ("2024-01-01T10:00:02Z", "a2"),
+                    ("2024-01-01T10:00:04Z", "a4"),
+                ],
+                Ok(()),
+            ),
+            // ends before the others
+            previous_lines(&pod, &[("2024-01-01T10:00:01Z", "b1")], Ok(())),
+            previous_lines(
+                &pod,
+                &[
+                    ("2024-01-01T10:00:02Z", "c2"),
+                    ("2024-01-01T10:00:03Z", "c3"),
+                    ("2024-01-01T10:00:05Z", "c5"),
+                ],
+                Ok(()),
+            ),
+            previous_lines(&pod, &[], Ok(())),
+        ];
+        let mut merged = MergedLines::new(streams).await.unwrap();
+        assert_eq!(merge(&mut merged).await.unwrap(), vec!["a0", "b1", "a2", "c2", "c3", "a4", "c5"]);
+        assert!(merged.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_error_of_a_stream_once_it_is_read() {
+        let pod = test_pod().await;
+        let streams = vec![
+            previous_lines(&pod, &[("2024-01-01T10:00:00Z", "a0"), ("2024-01-01T10:00:02Z", "a2")], Ok(())),
+            previous_lines(
+                &pod,
+                &[("2024-01-01T10:00:01Z", "b1")],
+                Err(Errors::LogError("stream closed".to_string())),
+            ),
+        ];
+        let mut merged = MergedLines::new(streams).await.unwrap();
+        assert_eq!(merged.next().await.unwrap().map(|line| line.1), Some("a0".to_string()));
+        assert!(matches!(merged.next().await, Err(Errors::LogError(_))));
+    }
+}
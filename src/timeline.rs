@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, FixedOffset};
+use k8s_openapi::api::core::v1::Event;
+use kube::api::ListParams;
+use kube::Api;
+
+use crate::error::Errors;
+use crate::{display, kubernetes, settings, types};
+
+/// date of the last occurrence of the event, the older fields are used for the events which don't set it
+fn event_date(event: &Event) -> Option<DateTime<FixedOffset>> {
+    return event
+        .last_timestamp
+        .as_ref()
+        .map(|time| time.0)
+        .or(event.event_time.as_ref().map(|time| time.0))
+        .or(event.first_timestamp.as_ref().map(|time| time.0))
+        .or(event.metadata.creation_timestamp.as_ref().map(|time| time.0))
+        .map(|date| date.fixed_offset());
+}
+
+/// eg: [event] Warning BackOff: Back-off restarting failed container (x4)
+fn event_line(event: &Event) -> String {
+    let mut line = format!(
+        "[event] {} {}: {}",
+        event.type_.as_deref().unwrap_or("Normal"),
+        event.reason.as_deref().unwrap_or_default(),
+        event.message.as_deref().unwrap_or_default().trim_end()
+    );
+    if let Some(count) = event.count.filter(|count| *count > 1) {
+        line.push_str(&format!(" (x{count})"));
+    }
+    return line;
+}
+
+/// the timestamp is added like the kubernetes one of the log lines when they are shown
+fn timeline_line(date: DateTime<FixedOffset>, line: String, settings: &settings::SettingsValidated) -> String {
+    if settings.timestamps {
+        return format!("{} {line}", date.to_rfc3339());
+    }
+    return line;
+}
+
+/// print the events and the condition changes of the pod followed with the timeline setting, they go through the reorder buffer
+/// so that they are printed in order with its log lines, the ones which happened before the start are printed first
+pub async fn follow_timeline(
+    settings: settings::SettingsValidated,
+    pods_lock: types::ArcMutex<kubernetes::Pods>,
+    streams_lock: types::ArcMutex<display::Streams>,
+) -> Result<(), Errors> {
+    let Some(pod_name) = settings.timeline.clone() else {
+        return Ok(());
+    };
+    // an event is printed again when it occurs again, a condition when it transitions again
+    let mut seen_events: HashSet<(String, i32)> = HashSet::new();
+    let mut seen_conditions: HashSet<(String, String, String)> = HashSet::new();
+    loop {
+        let found = {
+            let pods = pods_lock.lock().await;
+            pods.items
+                .iter()
+                .find(|pod| pod.name == pod_name)
+                .map(|pod| (pod.clone(), display::Prefix::new(pod, &pods.layout, &settings)))
+        };
+        if let Some((pod, prefix)) = found {
+            let client = pod.namespace.api.clone().into_client();
+            let events: Api<Event> = Api::namespaced(client, &pod.namespace.name);
+            let params = ListParams::default().fields(&format!("involvedObject.name={pod_name}"));
+            let mut entries = vec![];
+            match events.list(&params).await {
+                Ok(events) => {
+                    for event in events.items.iter() {
+                        let key = (event.metadata.uid.clone().unwrap_or_default(), event.count.unwrap_or(1));
+                        if let Some(date) = event_date(event).filter(|_| seen_events.insert(key)) {
+                            entries.push((date, event_line(event)));
+                        }
+                    }
+                }
+                Err(err) => tracing::warn!(pod = %pod.id(), error = %err, "listing the pod events failled"),
+            }
+            match pod.namespace.get_pod(&pod_name).await {
+                Ok(pod_api) => {
+                    let conditions = pod_api.status.and_then(|status| status.conditions).unwrap_or_default();
+                    for condition in conditions {
+                        let Some(date) = condition.last_transition_time.map(|time| time.0.fixed_offset()) else {
+                            continue;
+                        };
+                        if !seen_conditions.insert((condition.type_.clone(), condition.status.clone(), date.to_rfc3339())) {
+                            continue;
+                        }
+                        let reason = condition.reason.map(|reason| format!(" ({reason})")).unwrap_or_default();
+                        entries.push((date, format!("[condition] {}={}{}", condition.type_, condition.status, reason)));
+                    }
+                }
+                Err(err) => tracing::warn!(pod = %pod.id(), error = %err, "getting the pod conditions failled"),
+            }
+            for (date, line) in entries {
+                display::push_reordered(&streams_lock, &pod, &prefix, date, timeline_line(date, line, &settings)).await;
+            }
+        }
+        tokio::time::sleep(settings.loop_pause).await;
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+
+use colors_transform::Rgb;
+use regex::Regex;
+use termcolor::WriteColor;
+
+use crate::display;
+use crate::error::Errors;
+use crate::settings;
+use crate::stats::PodStats;
+
+/// width of the rule printed between pods when separators are enabled
+const SEPARATOR_WIDTH: usize = 80;
+
+/// number of lines kept while the output is paused, the next ones are dropped
+const PAUSE_BUFFER_SIZE: usize = 10000;
+
+/// lines received within this interval are rendered in memory and written at once
+const BATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(5);
+
+/// the rendered lines are written before the end of the interval once they reach this size
+const BATCH_SIZE: usize = 64 * 1024;
+
+enum Message {
+    /// status messages and listings, printed even while the output is paused
+    Text(Option<Rgb>, String),
+    /// written as is, without newline (eg: the terminal bell)
+    Raw(String),
+    /// a log line, without pod (nor prefix) for raw and json output
+    Line {
+        pod_id: Option<Arc<str>>,
+        /// counts the line as dropped when the output can't keep up
+        stats: Arc<PodStats>,
+        color: Option<Rgb>,
+        /// split style of the pod, on top of its color
+        style: Option<settings::TextStyle>,
+        prefix: Arc<str>,
+        line: String,
+    },
+    TogglePause,
+    /// print only the lines of this pod, or all of them again with None
+    Focus(Option<Arc<str>>),
+    /// back to the default colors
+    Reset,
+    /// answered once everything sent before was written
+    Flush(mpsc::Sender<()>),
+    /// same as flush but the colors are reset and the pager is waited for, nothing is written after it
+    Finish(mpsc::Sender<()>),
+}
+
+/// lines received while the output is paused
+struct Paused {
+    lines: Vec<Message>,
+    dropped: u64,
+}
+
+/// pod the output is focused on, and the lines of the other pods received meanwhile
+struct Focused {
+    pod_id: Arc<str>,
+    lines: Vec<Message>,
+    dropped: u64,
+}
+
+struct QueueState {
+    messages: VecDeque<Message>,
+    /// number of log lines in messages
+    lines: usize,
+    /// lines dropped since the last report and the color of their pod, by pod (None for raw and json output)
+    dropped: HashMap<Option<Arc<str>>, (Option<Rgb>, u64)>,
+    /// number of writers alive, the writer thread stops once they are all dropped
+    senders: usize,
+    /// set when the writer thread stopped
+    stopped: bool,
+}
+
+/// messages waiting for the writer thread: sending never blocks the kube streams and the log lines are bounded,
+/// the oldest ones are dropped when the terminal can't keep up
+struct Queue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+impl Queue {
+    fn push(&self, message: Message) -> Result<(), Errors> {
+        let mut state = self.state.lock().unwrap();
+        // the writer thread only stops after a write error, which it already reported
+        if state.stopped {
+            return Err(Errors::StdErr("the output writer stopped".to_string()));
+        }
+        if let Message::Line { .. } = message {
+            if state.lines >= self.capacity {
+                let oldest = state.messages.iter().position(|message| matches!(message, Message::Line { .. }));
+                if let Some(Message::Line { pod_id, stats, color, .. }) = oldest.and_then(|idx| state.messages.remove(idx)) {
+                    stats.dropped_lines.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    state.dropped.entry(pod_id).or_insert((color, 0)).1 += 1;
+                    state.lines -= 1;
+                }
+            }
+            state.lines += 1;
+        }
+        state.messages.push_back(message);
+        self.ready.notify_one();
+        return Ok(());
+    }
+
+    /// the pending messages are dropped, which releases the flush waiters
+    fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stopped = true;
+        state.messages.clear();
+    }
+
+    fn pop(state: &mut QueueState) -> Option<Message> {
+        let message = state.messages.pop_front()?;
+        if let Message::Line { .. } = message {
+            state.lines -= 1;
+        }
+        return Some(message);
+    }
+
+    /// None once every writer is dropped
+    fn recv(&self) -> Option<Message> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(message) = Queue::pop(&mut state) {
+                return Some(message);
+            }
+            if state.senders == 0 {
+                return None;
+            }
+            state = self.ready.wait(state).unwrap();
+        }
+    }
+
+    fn recv_timeout(&self, timeout: std::time::Duration) -> Option<Message> {
+        let mut state = self.state.lock().unwrap();
+        if state.messages.is_empty() && state.senders > 0 {
+            state = self.ready.wait_timeout(state, timeout).unwrap().0;
+        }
+        return Queue::pop(&mut state);
+    }
+
+    fn take_dropped(&self) -> HashMap<Option<Arc<str>>, (Option<Rgb>, u64)> {
+        return std::mem::take(&mut self.state.lock().unwrap().dropped);
+    }
+}
+
+/// where the writer thread prints
+pub enum Target {
+    /// printed through a buffer writer, which falls back to the console api on windows terminals without ansi support
+    Stdout(termcolor::BufferWriter),
+    /// the stdin of the pager process, which is waited for before exiting
+    Pager(display::Output, std::process::Child),
+}
+
+/// state owned by the writer thread
+struct WriterState {
+    /// None once the output is finished
+    target: Option<Target>,
+    /// lines rendered with their colors, waiting to be written
+    buffer: termcolor::Buffer,
+    err: termcolor::StandardStream,
+    error_style: Vec<settings::TextStyle>,
+    highlight: Vec<Regex>,
+    separators: bool,
+    condensed: bool,
+    /// interval of the time markers, in seconds
+    time_markers: Option<u64>,
+    /// interval of the last time marker, counted from the epoch
+    last_marker: Option<i64>,
+    /// namespace/name of the pod which printed the last log line
+    last_pod: Option<Arc<str>>,
+    /// set while the output is paused
+    paused: Option<Paused>,
+    /// set while the output is focused on a pod
+    focused: Option<Focused>,
+    focus_others: display::FocusOthers,
+}
+
+impl WriterState {
+    fn run(mut self, queue: Arc<Queue>) {
+        if let Err(err) = self.run_batches(&queue) {
+            queue.stop();
+            let _ = display::write_error(
+                &mut self.err,
+                None,
+                &self.error_style,
+                format!("writing output failled: {}", err.to_string()),
+            );
+        }
+    }
+
+    /// wait for a message then handle the ones following it during the batch interval before writing them all
+    fn run_batches(&mut self, queue: &Queue) -> Result<(), Errors> {
+        while let Some(message) = queue.recv() {
+            let deadline = std::time::Instant::now() + BATCH_INTERVAL;
+            let mut next = Some(message);
+            while let Some(message) = next {
+                self.handle(message)?;
+                next = if self.buffer.len() >= BATCH_SIZE {
+                    None
+                } else {
+                    queue.recv_timeout(deadline.saturating_duration_since(std::time::Instant::now()))
+                };
+            }
+            self.write_buffer()?;
+            self.report_dropped(queue)?;
+        }
+        return self.write_buffer();
+    }
+
+    fn report_dropped(&mut self, queue: &Queue) -> Result<(), Errors> {
+        for (pod_id, (color, dropped)) in queue.take_dropped() {
+            let message = match pod_id {
+                Some(pod_id) => format!("{dropped} lines dropped for pod {pod_id}, the output can't keep up"),
+                None => format!("{dropped} lines dropped, the output can't keep up"),
+            };
+            display::write_error(&mut self.err, color, &self.error_style, message)?;
+        }
+        return Ok(());
+    }
+
+    fn write_buffer(&mut self) -> Result<(), Errors> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        match &mut self.target {
+            Some(Target::Stdout(stdout)) => stdout.print(&self.buffer).map_err(|err| Errors::StdErr(err.to_string()))?,
+            Some(Target::Pager(out, _)) => {
+                out.write_all(self.buffer.as_slice()).map_err(|err| Errors::StdErr(err.to_string()))?;
+                out.flush().map_err(|err| Errors::StdErr(err.to_string()))?;
+            }
+            None => {}
+        }
+        self.buffer.clear();
+        return Ok(());
+    }
+
+    fn handle(&mut self, message: Message) -> Result<(), Errors> {
+        return match message {
+            Message::Text(color, text) => display::write_color(&mut self.buffer, color, text),
+            Message::Raw(text) => {
+                self.buffer
+                    .set_color(&termcolor::ColorSpec::default())
+                    .map_err(|err| Errors::StdErr(err.to_string()))?;
+                self.buffer
+                    .write_fmt(format_args!("{text}"))
+                    .map_err(|err| Errors::StdErr(err.to_string()))
+            }
+            Message::Line { .. } => match self.focus_line(message) {
+                Some(message) => self.pause_line(message),
+                None => Ok(()),
+            },
+            Message::TogglePause => match self.paused.take() {
+                Some(paused) => {
+                    for line in paused.lines {
+                        self.write_line(line)?;
+                    }
+                    self.write_buffer()?;
+                    display::write_color(&mut self.err, None, format!("output resumed, {} lines dropped", paused.dropped))
+                }
+                None => {
+                    self.paused = Some(Paused { lines: vec![], dropped: 0 });
+                    self.write_buffer()?;
+                    display::write_color(&mut self.err, None, "output paused".to_string())
+                }
+            },
+            Message::Focus(pod_id) => self.set_focus(pod_id),
+            Message::Reset => self.buffer.reset().map_err(|err| Errors::StdErr(err.to_string())),
+            Message::Flush(done) => {
+                let written = self.write_buffer();
+                let _ = done.send(());
+                written
+            }
+            Message::Finish(done) => {
+                let _ = self.buffer.reset();
+                let _ = self.write_buffer();
+                if let Some(Target::Pager(out, mut pager)) = self.target.take() {
+                    // closing its stdin lets the pager know the output is complete
+                    drop(out);
+                    let _ = pager.wait();
+                }
+                let _ = done.send(());
+                Ok(())
+            }
+        };
+    }
+
+    /// the line if it must be printed now, the lines of the other pods are buffered or dropped while the output is focused,
+    /// the raw and json lines have no pod and are always printed
+    fn focus_line(&mut self, message: Message) -> Option<Message> {
+        let Some(focused) = &mut self.focused else {
+            return Some(message);
+        };
+        let Message::Line {
+            pod_id: Some(pod_id), stats, ..
+        } = &message
+        else {
+            return Some(message);
+        };
+        if *pod_id == focused.pod_id {
+            return Some(message);
+        }
+        if self.focus_others == display::FocusOthers::Buffer && focused.lines.len() < PAUSE_BUFFER_SIZE {
+            focused.lines.push(message);
+        } else {
+            stats.dropped_lines.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            focused.dropped += 1;
+        }
+        return None;
+    }
+
+    /// focus on the pod, or print the lines buffered while focused and remove the focus
+    fn set_focus(&mut self, pod_id: Option<Arc<str>>) -> Result<(), Errors> {
+        let previous = self.focused.take();
+        if let Some(pod_id) = pod_id {
+            let message = format!("output focused on {pod_id}");
+            self.focused = Some(match previous {
+                // switching to another pod keeps the lines buffered meanwhile
+                Some(previous) => Focused { pod_id, ..previous },
+                None => Focused {
+                    pod_id,
+                    lines: vec![],
+                    dropped: 0,
+                },
+            });
+            self.write_buffer()?;
+            return display::write_color(&mut self.err, None, message);
+        }
+        let Some(previous) = previous else {
+            return display::write_color(&mut self.err, None, "output not focused".to_string());
+        };
+        let buffered = previous.lines.len();
+        for line in previous.lines {
+            self.pause_line(line)?;
+        }
+        self.write_buffer()?;
+        let message = format!("focus removed, {buffered} lines of the other pods printed, {} dropped", previous.dropped);
+        return display::write_color(&mut self.err, None, message);
+    }
+
+    /// the line is kept while the output is paused
+    fn pause_line(&mut self, message: Message) -> Result<(), Errors> {
+        let Some(paused) = &mut self.paused else {
+            return self.write_line(message);
+        };
+        if paused.lines.len() < PAUSE_BUFFER_SIZE {
+            paused.lines.push(message);
+        } else if let Message::Line { stats, .. } = message {
+            stats.dropped_lines.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            paused.dropped += 1;
+        }
+        return Ok(());
+    }
+
+    fn write_line(&mut self, message: Message) -> Result<(), Errors> {
+        let Message::Line {
+            pod_id,
+            color,
+            style,
+            prefix,
+            line,
+            ..
+        } = message
+        else {
+            return Ok(());
+        };
+        let Some(pod_id) = pod_id else {
+            return display::write_color(&mut self.buffer, color, line);
+        };
+        self.write_time_marker()?;
+        let same_pod = self.last_pod.as_deref() == Some(&*pod_id);
+        if self.separators && !same_pod {
+            let rule = "─".repeat(SEPARATOR_WIDTH.saturating_sub(pod_id.chars().count() + 4));
+            display::write_color(&mut self.buffer, color, format!("── {pod_id} {rule}"))?;
+        }
+        self.last_pod = Some(pod_id);
+        if self.condensed && same_pod {
+            let blank = " ".repeat(prefix.chars().count());
+            return display::write_color_highlighted(&mut self.buffer, color, &blank, &line, &self.highlight, style.as_ref());
+        }
+        return display::write_color_highlighted(&mut self.buffer, color, &prefix, &line, &self.highlight, style.as_ref());
+    }
+
+    /// a dimmed rule with the start time of the interval before its first line, the intervals without lines get none
+    fn write_time_marker(&mut self) -> Result<(), Errors> {
+        let Some(interval) = self.time_markers else {
+            return Ok(());
+        };
+        let now = chrono::Local::now();
+        let current = now.timestamp().div_euclid(interval as i64);
+        if self.last_marker == Some(current) {
+            return Ok(());
+        }
+        self.last_marker = Some(current);
+        let start = now - chrono::Duration::seconds(now.timestamp().rem_euclid(interval as i64));
+        let label = start.format("%H:%M:%S").to_string();
+        let rule = "─".repeat(SEPARATOR_WIDTH.saturating_sub(label.len() + 4));
+        self.buffer
+            .set_color(termcolor::ColorSpec::new().set_dimmed(true))
+            .map_err(|err| Errors::StdErr(err.to_string()))?;
+        writeln!(self.buffer, "── {label} {rule}").map_err(|err| Errors::StdErr(err.to_string()))?;
+        return self.buffer.reset().map_err(|err| Errors::StdErr(err.to_string()));
+    }
+}
+
+/// sends the output to the writer thread which owns stdout (or the pager), so that printing a line never waits for a lock
+/// nor blocks the runtime on a slow terminal
+pub struct Writer {
+    queue: Arc<Queue>,
+}
+
+impl Clone for Writer {
+    fn clone(&self) -> Writer {
+        self.queue.state.lock().unwrap().senders += 1;
+        return Writer { queue: self.queue.clone() };
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().senders -= 1;
+        self.queue.ready.notify_one();
+    }
+}
+
+impl Writer {
+    pub fn new(target: Target, settings: &settings::SettingsValidated) -> Writer {
+        let queue = Arc::new(Queue {
+            state: Mutex::new(QueueState {
+                messages: VecDeque::new(),
+                lines: 0,
+                dropped: HashMap::new(),
+                senders: 1,
+                stopped: false,
+            }),
+            ready: Condvar::new(),
+            capacity: settings.output_buffer,
+        });
+        // rendering the colors in memory lets a whole batch be written with a single call
+        let buffer = match &target {
+            Target::Stdout(stdout) => stdout.buffer(),
+            Target::Pager(out, _) if out.supports_color() => termcolor::Buffer::ansi(),
+            Target::Pager(_, _) => termcolor::Buffer::no_color(),
+        };
+        let state = WriterState {
+            target: Some(target),
+            buffer,
+            err: termcolor::StandardStream::stderr(termcolor::ColorChoice::Always),
+            error_style: settings.error_style.clone(),
+            highlight: settings.highlight.clone(),
+            separators: settings.separators,
+            condensed: settings.condensed,
+            time_markers: settings.time_markers.map(|interval| interval.as_secs().max(1)),
+            last_marker: None,
+            last_pod: None,
+            paused: None,
+            focused: None,
+            focus_others: settings.focus_others.clone(),
+        };
+        let receiver = queue.clone();
+        std::thread::spawn(move || state.run(receiver));
+        return Writer { queue };
+    }
+
+    fn send(&self, message: Message) -> Result<(), Errors> {
+        return self.queue.push(message);
+    }
+
+    pub fn text(&self, color: Option<Rgb>, text: String) -> Result<(), Errors> {
+        return self.send(Message::Text(color, text));
+    }
+
+    pub fn raw(&self, text: String) -> Result<(), Errors> {
+        return self.send(Message::Raw(text));
+    }
+
+    /// prefixed log line, a separator is printed before it if the previous line came from another pod and separators are enabled
+    pub fn line(
+        &self,
+        stats: Arc<PodStats>,
+        pod_id: Arc<str>,
+        color: Rgb,
+        style: Option<settings::TextStyle>,
+        prefix: Arc<str>,
+        line: String,
+    ) -> Result<(), Errors> {
+        return self.send(Message::Line {
+            pod_id: Some(pod_id),
+            stats,
+            color: Some(color),
+            style,
+            prefix,
+            line,
+        });
+    }
+
+    /// log line printed as is (raw and json output), buffered like the others while the output is paused
+    pub fn record(&self, stats: Arc<PodStats>, line: String) -> Result<(), Errors> {
+        return self.send(Message::Line {
+            pod_id: None,
+            stats,
+            color: None,
+            style: None,
+            prefix: Arc::from(""),
+            line,
+        });
+    }
+
+    /// pause the output, or print the lines buffered while it was paused and resume it
+    pub fn toggle_pause(&self) -> Result<(), Errors> {
+        return self.send(Message::TogglePause);
+    }
+
+    /// print only the lines of the pod, or all of them again with None
+    pub fn set_focus(&self, pod_id: Option<Arc<str>>) -> Result<(), Errors> {
+        return self.send(Message::Focus(pod_id));
+    }
+
+    pub fn reset(&self) -> Result<(), Errors> {
+        return self.send(Message::Reset);
+    }
+
+    /// wait until everything sent before is written
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.send(Message::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// write everything sent before, reset the colors and wait for the user to quit the pager
+    pub fn finish(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.send(Message::Finish(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
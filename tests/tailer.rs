@@ -0,0 +1,112 @@
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::{ListParams, LogParams, ObjectList};
+use rusty_stern::error::Errors;
+use rusty_stern::source::{self, LogReader, MemorySource, PodSource};
+use rusty_stern::{LogLine, TailerBuilder};
+
+fn shop_namespace() -> MemorySource {
+    return MemorySource::new()
+        .pod(
+            source::running_pod("shop", "api-1", "main"),
+            "2024-01-01T10:00:00Z starting\n2024-01-01T10:00:01Z error: connection to db refused\n2024-01-01T10:00:02Z ready\n",
+        )
+        .pod(source::running_pod("shop", "api-2", "main"), "2024-01-01T10:00:00Z error: db timeout\n")
+        .pod(
+            source::running_pod("shop", "worker-1", "main"),
+            "2024-01-01T10:00:00Z error: queue full\n",
+        );
+}
+
+async fn collect(builder: TailerBuilder) -> Vec<LogLine> {
+    let tailer = builder.source("shop", shop_namespace()).build().unwrap();
+    let lines: Vec<Result<LogLine, Errors>> = tailer.stream().collect().await;
+    let mut lines: Vec<LogLine> = lines.into_iter().collect::<Result<_, _>>().unwrap();
+    // each pod is streamed by its own task
+    lines.sort_by(|first, second| (&first.pod, first.timestamp).cmp(&(&second.pod, second.timestamp)));
+    return lines;
+}
+
+#[tokio::test]
+async fn streams_every_line_of_the_matching_pods() {
+    let lines = collect(TailerBuilder::new().pod_search("^api-")).await;
+    let messages: Vec<(&str, &str)> = lines.iter().map(|line| (line.pod.as_str(), line.message.as_str())).collect();
+    assert_eq!(
+        messages,
+        vec![
+            ("api-1", "starting"),
+            ("api-1", "error: connection to db refused"),
+            ("api-1", "ready"),
+            ("api-2", "error: db timeout"),
+        ]
+    );
+    assert!(lines.iter().all(|line| line.namespace == "shop" && line.container == "main"));
+}
+
+#[tokio::test]
+async fn filters_and_replaces_the_lines() {
+    let lines = collect(TailerBuilder::new().pod_search("^api-").filter("error").replace("db", "database")).await;
+    let messages: Vec<(&str, &str)> = lines.iter().map(|line| (line.pod.as_str(), line.message.as_str())).collect();
+    assert_eq!(
+        messages,
+        vec![("api-1", "error: connection to database refused"), ("api-2", "error: database timeout")]
+    );
+}
+
+#[tokio::test]
+async fn prints_the_lines_of_the_sources() {
+    let tailer = TailerBuilder::new().filter("queue").source("shop", shop_namespace()).build().unwrap();
+    assert_eq!(tailer.run().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn a_fail_on_match_only_fails_its_own_run() {
+    let tailer = TailerBuilder::new()
+        .fail_on("queue full")
+        .source("shop", shop_namespace())
+        .build()
+        .unwrap();
+    assert_eq!(tailer.run().await.unwrap(), rusty_stern::error::EXIT_FAIL_ON);
+    let tailer = TailerBuilder::new()
+        .fail_on("queue full")
+        .filter("db")
+        .source("shop", shop_namespace())
+        .build()
+        .unwrap();
+    assert_eq!(tailer.run().await.unwrap(), 0);
+}
+
+/// a namespace whose pods can't be listed
+struct Forbidden;
+
+fn forbidden() -> kube::Error {
+    return kube::Error::Api(kube::core::ErrorResponse {
+        status: "Failure".to_string(),
+        message: "pods is forbidden".to_string(),
+        reason: "Forbidden".to_string(),
+        code: 403,
+    });
+}
+
+impl PodSource for Forbidden {
+    fn list<'a>(&'a self, _params: &'a ListParams) -> BoxFuture<'a, Result<ObjectList<Pod>, kube::Error>> {
+        return Box::pin(async { Err(forbidden()) });
+    }
+
+    fn get<'a>(&'a self, _name: &'a str) -> BoxFuture<'a, Result<Pod, kube::Error>> {
+        return Box::pin(async { Err(forbidden()) });
+    }
+
+    fn log_stream<'a>(&'a self, _name: &'a str, _params: &'a LogParams) -> BoxFuture<'a, Result<LogReader, kube::Error>> {
+        return Box::pin(async { Err(forbidden()) });
+    }
+}
+
+#[tokio::test]
+async fn ends_the_stream_with_its_error() {
+    let tailer = TailerBuilder::new().source("shop", Forbidden).build().unwrap();
+    let lines: Vec<Result<LogLine, Errors>> = tailer.stream().collect().await;
+    assert_eq!(lines.len(), 1);
+    assert!(matches!(&lines[0], Err(err) if err.exit_code() == rusty_stern::error::EXIT_AUTH));
+}